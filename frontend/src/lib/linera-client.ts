@@ -120,7 +120,7 @@ export class ConwayBetsClient {
   async getMarket(marketId: string): Promise<Market> {
     const query: LineraQuery = {
       query: `
-        query GetMarket($marketId: ID!) {
+        query GetMarket($marketId: String!) {
           market(id: $marketId) {
             id
             title
@@ -154,39 +154,20 @@ export class ConwayBetsClient {
     description: string,
     endTime: number,
     outcomes: string[]
-  ): Promise<string> {
+  ): Promise<boolean> {
+    // The mutation only schedules the operation; the market's id is known
+    // once it runs, when the market shows up in getAllMarkets.
     const mutation: LineraMutation = {
       mutation: `
-        mutation CreateMarket(
-          $creator: String!,
-          $title: String!,
-          $description: String!,
-          $endTime: Int!,
-          $outcomes: [String!]!
-        ) {
-          createMarket(
-            creator: $creator,
-            title: $title,
-            description: $description,
-            endTime: $endTime,
-            outcomes: $outcomes
-          ) {
-            marketId
-            receipt {
-              id
-              status
-              finalizedAt
-            }
-          }
+        mutation CreateMarket($creator: AccountOwner!, $market: MarketCreationInput!) {
+          createMarket(creator: $creator, market: $market)
         }
       `,
-      variables: { creator, title, description, endTime, outcomes },
+      variables: { creator, market: { title, description, endTime, outcomes } },
     };
 
-    // Note: If the backend expects actual operations instead of GraphQL mutations, 
-    // this might need adjustment, but we pass the mutation structure to the adapter.
-    const result = await lineraAdapter.queryApplication<{ data: { createMarket: { marketId: string } } }>(mutation);
-    return result.data.createMarket.marketId;
+    const result = await lineraAdapter.queryApplication<{ data: { createMarket: boolean } }>(mutation);
+    return result.data.createMarket;
   }
 
   async placeBet(
@@ -194,38 +175,30 @@ export class ConwayBetsClient {
     user: string,
     outcomeIndex: number,
     amount: string
-  ): Promise<{ receiptId: string; status: string }> {
+  ): Promise<boolean> {
+    // Scheduled like createMarket: the position appears in getUserBets once
+    // the operation runs.
     const mutation: LineraMutation = {
       mutation: `
         mutation PlaceBet(
-          $marketId: ID!,
-          $user: String!,
+          $marketId: String!,
+          $user: AccountOwner!,
           $outcomeIndex: Int!,
           $amount: String!
         ) {
           placeBet(
-            marketId: $marketId,
+            id: $marketId,
             user: $user,
             outcomeIndex: $outcomeIndex,
             amount: $amount
-          ) {
-            receipt {
-              id
-              status
-              finalizedAt
-            }
-            betId
-          }
+          )
         }
       `,
       variables: { marketId, user, outcomeIndex, amount },
     };
 
-    const result = await lineraAdapter.queryApplication<{ data: { placeBet: { receipt: { id: string, status: string } } } }>(mutation);
-    return {
-      receiptId: result.data.placeBet.receipt.id,
-      status: result.data.placeBet.receipt.status,
-    };
+    const result = await lineraAdapter.queryApplication<{ data: { placeBet: boolean } }>(mutation);
+    return result.data.placeBet;
   }
 
   async getUserPortfolio(userAddress: string): Promise<UserPortfolio> {
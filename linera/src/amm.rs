@@ -0,0 +1,63 @@
+//! Logarithmic Market Scoring Rule pricing.
+//!
+//! Pools hold the shares outstanding per outcome and `b` is the liquidity
+//! parameter, both in attos. Cost is `C(q) = b * ln(sum(exp(q_i / b)))`.
+
+use linera_sdk::linera_base_types::Amount;
+
+fn scaled(pools: &[Amount], b: f64) -> Vec<f64> {
+    pools.iter().map(|q| u128::from(*q) as f64 / b).collect()
+}
+
+/// `ln(sum(exp(x_i)))`, shifted by the maximum so large pools don't overflow.
+fn log_sum_exp(xs: &[f64]) -> f64 {
+    let max = xs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return max;
+    }
+    max + xs.iter().map(|x| (x - max).exp()).sum::<f64>().ln()
+}
+
+fn to_amount(attos: f64) -> Amount {
+    Amount::from_attos(attos.max(0.0) as u128)
+}
+
+/// Implied probability of each outcome; these sum to 1.
+pub fn price(outcome_pools: &[Amount], b: f64) -> Vec<f64> {
+    let xs = scaled(outcome_pools, b);
+    let total = log_sum_exp(&xs);
+    xs.iter().map(|x| (x - total).exp()).collect()
+}
+
+/// What it costs to buy `shares` of `outcome`, rounded up.
+pub fn cost(pools: &[Amount], outcome: usize, shares: Amount, b: f64) -> Amount {
+    let before = scaled(pools, b);
+    let mut after = before.clone();
+    after[outcome] += u128::from(shares) as f64 / b;
+    let cost = b * (log_sum_exp(&after) - log_sum_exp(&before));
+    Amount::from_attos(cost.max(0.0).ceil() as u128)
+}
+
+/// Shares of `outcome` that `amount` buys, rounded down.
+pub fn shares_for_cost(pools: &[Amount], outcome: usize, amount: Amount, b: f64) -> Amount {
+    let xs = scaled(pools, b);
+    let others: Vec<f64> = xs.iter().enumerate().filter(|(i, _)| *i != outcome).map(|(_, x)| *x).collect();
+    // Solve exp(x_k') = exp(C + m) - sum_{j != k} exp(x_j) in log space.
+    let target_total = log_sum_exp(&xs) + u128::from(amount) as f64 / b;
+    let others_total = log_sum_exp(&others);
+    let new_x = target_total + (-(others_total - target_total).exp()).ln_1p();
+    to_amount((new_x - xs[outcome]) * b)
+}
+
+/// Shares of `outcome` needed to move its price up to `target`, or `None`
+/// if it's already there or `target` isn't a probability.
+pub fn shares_for_price(pools: &[Amount], outcome: usize, target: f64, b: f64) -> Option<Amount> {
+    if !(0.0..1.0).contains(&target) || target <= price(pools, b)[outcome] {
+        return None;
+    }
+    let xs = scaled(pools, b);
+    let others: Vec<f64> = xs.iter().enumerate().filter(|(i, _)| *i != outcome).map(|(_, x)| *x).collect();
+    // p = e^x / (e^x + S)  =>  x = ln(p / (1 - p)) + ln(S)
+    let new_x = (target / (1.0 - target)).ln() + log_sum_exp(&others);
+    Some(to_amount((new_x - xs[outcome]) * b))
+}
@@ -1,6 +1,9 @@
 #![cfg_attr(target_arch = "wasm32", no_main)]
 
-use linera::{ConwayBets, ConwayBetsAbi, ConwayBetsMessage, Operation};
+use linera::{
+    event_key, BlockContext, Config, ConwayBets, ConwayBetsAbi, ConwayBetsMessage, ConwayBetsParameters, Operation,
+    STATE_KEY,
+};
 use linera_sdk::{
     abi::WithContractAbi,
     Contract, ContractRuntime,
@@ -14,6 +17,9 @@ use linera_sdk::views::linera_views::store::ReadableKeyValueStore;
 pub struct ConwayBetsContract {
     state: ConwayBets,
     runtime: ContractRuntime<Self>,
+    // Oldest event still stored when loaded; anything older than the new
+    // oldest is deleted on store.
+    oldest_stored_event: u64,
 }
 
 impl WithContractAbi for ConwayBetsContract {
@@ -25,14 +31,14 @@ linera_sdk::contract!(ConwayBetsContract);
 #[derive(Deserialize, Serialize, Debug)]
 pub struct InstantiationArgument {
     pub initial_markets: Vec<String>,
+    #[serde(default)]
+    pub config: Config,
 }
 
-const STATE_KEY: &[u8] = b"conway_bets_state";
-
 impl Contract for ConwayBetsContract {
     type Message = ConwayBetsMessage;
     type InstantiationArgument = InstantiationArgument;
-    type Parameters = ();
+    type Parameters = ConwayBetsParameters;
     type EventValue = ();
 
     async fn load(runtime: ContractRuntime<Self>) -> Self {
@@ -42,47 +48,71 @@ impl Contract for ConwayBetsContract {
             .await
             .expect("Failed to read state");
             
-        let state: ConwayBets = match state_bytes {
+        let mut state: ConwayBets = match state_bytes {
             Some(bytes) => bcs::from_bytes(&bytes).expect("Failed to deserialize state"),
             None => ConwayBets::default(),
         };
+        state.rebuild_creator_index();
+        #[cfg(feature = "debug-log")]
+        if let Some(bytes) = runtime
+            .key_value_store()
+            .read_value_bytes(linera::OPERATION_LOG_KEY)
+            .await
+            .expect("Failed to read operation log")
+        {
+            state.operation_log = bcs::from_bytes(&bytes).expect("Failed to deserialize operation log");
+        }
+        let oldest_stored_event = *state.retained_event_seqs().start();
             
-        ConwayBetsContract { state, runtime }
+        ConwayBetsContract { state, runtime, oldest_stored_event }
     }
 
     async fn instantiate(
         &mut self,
-        _argument: Self::InstantiationArgument,
+        argument: Self::InstantiationArgument,
     ) {
         // Initialization logic
+        let fee_bps = self.runtime.application_parameters().fee_bps;
+        if let Err(error) = self.state.configure(argument.config, fee_bps) {
+            panic!("invalid instantiation argument: {error}");
+        }
     }
 
     async fn execute_operation(
         &mut self,
         operation: Operation,
     ) -> Self::Response {
-        match operation {
-            Operation::CreateMarket { creator, title, description, end_time, outcomes } => {
-                self.state.create_market(creator, title, description, end_time, outcomes).await;
-            }
-            Operation::PlaceBet { market_id, user, outcome_index, amount } => {
-                let _ = self.state.place_bet(market_id, user, outcome_index, amount).await;
-            }
+        self.refresh_block_context();
+        // Every account an operation names is checked against the signer.
+        let signer = self.runtime.authenticated_signer();
+        // Panicking rejects the operation, so a failure leaves no partial state behind.
+        if let Err(error) = self.state.execute(signer, operation).await {
+            panic!("operation signed by {signer:?} failed: {error}");
         }
     }
 
     async fn execute_message(
         &mut self,
-        _message: Self::Message,
+        message: Self::Message,
     ) {
         // Handle cross-chain messages
+        self.refresh_block_context();
+        let Some(origin) = self.runtime.message_origin_chain_id() else {
+            log::warn!("dropping message {:02x?} with no origin chain", message.correlation_id());
+            return;
+        };
+        self.state.execute_message(origin, message);
     }
 
-    async fn store(self) {
+    async fn store(mut self) {
+        self.state.flush_bet_batches();
+        for (destination, message) in std::mem::take(&mut self.state.outbox) {
+            self.runtime.prepare_message(message).send_to(destination);
+        }
         let bytes = bcs::to_bytes(&self.state).expect("Failed to serialize state");
         
         // FIX: Use the correct Batch structure from linera_views
-        let batch = Batch {
+        let mut batch = Batch {
             operations: vec![
                 WriteOperation::Put {
                     key: STATE_KEY.to_vec(),
@@ -90,7 +120,31 @@ impl Contract for ConwayBetsContract {
                 }
             ],
         };
+        for logged in &self.state.events {
+            batch.operations.push(WriteOperation::Put {
+                key: event_key(logged.seq),
+                value: bcs::to_bytes(logged).expect("Failed to serialize event"),
+            });
+        }
+        for seq in self.oldest_stored_event..*self.state.retained_event_seqs().start() {
+            batch.operations.push(WriteOperation::Delete { key: event_key(seq) });
+        }
+        #[cfg(feature = "debug-log")]
+        batch.operations.push(WriteOperation::Put {
+            key: linera::OPERATION_LOG_KEY.to_vec(),
+            value: bcs::to_bytes(&self.state.operation_log).expect("Failed to serialize operation log"),
+        });
 
         self.runtime.key_value_store().write_batch(batch).await.expect("Failed to store state");
     }
-}
\ No newline at end of file
+}
+
+impl ConwayBetsContract {
+    fn refresh_block_context(&mut self) {
+        self.state.block = BlockContext {
+            timestamp: self.runtime.system_time().micros() / 1_000_000,
+            block_height: self.runtime.block_height().0,
+            chain_id: self.runtime.chain_id(),
+        };
+    }
+}
@@ -0,0 +1,80 @@
+//! Conversions between human-readable token strings and raw `Amount`s.
+
+use crate::ConwayBetsError;
+use linera_sdk::linera_base_types::Amount;
+
+fn is_digits(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Parses a decimal string such as `"10.5"` into raw units with `decimals`
+/// fractional digits.
+pub fn parse_amount(s: &str, decimals: u8) -> Result<Amount, ConwayBetsError> {
+    let invalid = || ConwayBetsError::InvalidAmount(s.to_string());
+    let (whole, fraction) = match s.split_once('.') {
+        Some((whole, fraction)) if is_digits(fraction) => (whole, fraction),
+        Some(_) => return Err(invalid()),
+        None => (s, ""),
+    };
+    if !is_digits(whole) || fraction.len() > decimals as usize {
+        return Err(invalid());
+    }
+    // Right-padding the fraction to `decimals` digits leaves just raw units.
+    let raw = format!("{whole}{fraction:0<width$}", width = decimals as usize);
+    let raw = raw.parse::<u128>().map_err(|_| ConwayBetsError::AmountOverflow)?;
+    Ok(Amount::from_attos(raw))
+}
+
+/// Renders raw units as a decimal string with `decimals` fractional digits,
+/// dropping trailing zeros.
+pub fn format_amount(a: Amount, decimals: u8) -> String {
+    let digits = u128::from(a).to_string();
+    let decimals = decimals as usize;
+    let padded = format!("{digits:0>width$}", width = decimals + 1);
+    let (whole, fraction) = padded.split_at(padded.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_string()
+    } else {
+        format!("{whole}.{fraction}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trips_decimal_strings() {
+        for (input, decimals, raw) in [
+            ("10.5", 18, 10_500_000_000_000_000_000),
+            ("0.000000000000000001", 18, 1),
+            ("42", 0, 42),
+            ("7.25", 2, 725),
+            ("0", 6, 0),
+        ] {
+            let amount = parse_amount(input, decimals).unwrap();
+            assert_eq!(amount, Amount::from_attos(raw));
+            assert_eq!(format_amount(amount, decimals), input);
+        }
+        assert_eq!(parse_amount("10.5", 18).unwrap(), Amount::from_millis(10_500));
+        assert_eq!(format_amount(Amount::from_attos(1_500), 2), "15");
+        assert_eq!(format_amount(parse_amount("3.10", 2).unwrap(), 2), "3.1");
+    }
+
+    #[test]
+    fn test_rejects_malformed_and_overflowing_input() {
+        for input in ["", ".", "1.", ".5", "1.2.3", "-1", "+1", "1e3", " 1", "abc", "1.234"] {
+            assert_eq!(
+                parse_amount(input, 2),
+                Err(ConwayBetsError::InvalidAmount(input.to_string())),
+                "{input:?}"
+            );
+        }
+        assert_eq!(parse_amount("1", 39), Err(ConwayBetsError::AmountOverflow));
+        assert_eq!(
+            parse_amount("340282366920938463463374607431768211456", 0),
+            Err(ConwayBetsError::AmountOverflow)
+        );
+    }
+}
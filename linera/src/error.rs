@@ -0,0 +1,114 @@
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ConwayBetsError {
+    #[error("market not found")]
+    MarketNotFound,
+    #[error("caller is not authorized for this action")]
+    NotAuthorized,
+    #[error("resolver can no longer change once the market has bets")]
+    ResolverLocked,
+    #[error("outcome index is out of range")]
+    OutcomeOutOfRange,
+    #[error("outcome labels are frozen once the market has liquidity")]
+    OutcomesFrozen,
+    #[error("operation would touch more than {limit} markets/positions; paginate or run it in batches")]
+    WorkLimitExceeded { limit: u64 },
+    #[error("market is closed for betting")]
+    MarketClosed,
+    #[error("not enough funds to cover this stake")]
+    InsufficientFunds,
+    #[error("stake is below the market's minimum bet")]
+    BetTooSmall,
+    #[error("market is already resolved")]
+    AlreadyResolved,
+    #[error("position not found")]
+    PositionNotFound,
+    #[error("position is locked")]
+    PositionLocked,
+    #[error("position has already been claimed")]
+    AlreadyClaimed,
+    #[error("withdrawal would leave the pool short of the other positions' claims")]
+    WouldUnderflowPool,
+    #[error("resolution must name at least one winning outcome")]
+    EmptyResolution,
+    #[error("a winning outcome cannot also be voided")]
+    VoidedWinner,
+    #[error("too many markets created in this block; retry in the next one")]
+    BlockQuotaExceeded,
+    #[error("market was cancelled")]
+    MarketCancelled,
+    #[error("market needs {required} distinct bettors to resolve but has {actual}")]
+    NotEnoughBettors { required: u32, actual: u32 },
+    #[error("current odds do not satisfy the bet's condition")]
+    ConditionNotMet,
+    #[error("suspending this outcome would leave fewer than {min_live} live outcomes")]
+    TooManySuspensions { min_live: usize },
+    #[error("outcome is suspended")]
+    OutcomeSuspended,
+    #[error("market doesn't take bets until its opening delay has passed")]
+    BettingNotOpen,
+    #[error("market is still being funded")]
+    MarketFunding,
+    #[error("winnings can't be claimed yet")]
+    ClaimsNotOpen,
+    #[error("invalid amount: {0:?}")]
+    InvalidAmount(String),
+    #[error("invalid market id {0:?}, expected \"<chain_id>:<id>\"")]
+    InvalidMarketId(String),
+    #[error("amount does not fit in 128 bits")]
+    AmountOverflow,
+    #[error("a bet with this commitment already exists")]
+    DuplicateCommitment,
+    #[error("no bet matches this secret")]
+    UnknownCommitment,
+    #[error("nullifier has already been spent")]
+    NullifierSpent,
+    #[error("resolution can't be challenged now")]
+    NotDisputable,
+    #[error("market has no open dispute")]
+    NotDisputed,
+    #[error("market is frozen")]
+    MarketFrozen,
+    #[error("bet is no longer pending")]
+    BetNotPending,
+    #[error("bet isn't waiting to be retried")]
+    BetNotRetriable,
+    #[error("an earlier bet in the same batch failed")]
+    BatchAborted,
+    #[error("market is awaiting moderator approval")]
+    AwaitingApproval,
+    #[error("market isn't awaiting approval")]
+    NotPendingApproval,
+    #[error("only markets with more than two outcomes can be split")]
+    NotSplittable,
+    #[error("markets don't offer the same outcomes")]
+    OutcomesMismatch,
+    #[error("market has no numeric range to resolve against")]
+    NotScalarMarket,
+    #[error("market is not priced by an automated market maker")]
+    NotAmmMarket,
+    #[error("target probability must be above the current price and below 1")]
+    InvalidTargetProbability,
+    #[error("fee must stay below 100%")]
+    FeeTooHigh,
+    #[error("configured fee disagrees with the application parameters")]
+    FeeMismatch,
+    #[error(transparent)]
+    Market(#[from] MarketError),
+}
+
+/// Why `create_market` refused a market definition.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum MarketError {
+    #[error("a market needs at least two outcomes")]
+    TooFewOutcomes,
+    #[error("outcome {0:?} is listed more than once")]
+    DuplicateOutcome(String),
+    #[error("market title is empty")]
+    EmptyTitle,
+    #[error("market must end after the current block")]
+    EndTimeInPast,
+    #[error("creator fee can't exceed 100%")]
+    CreatorFeeTooHigh,
+}
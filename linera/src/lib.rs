@@ -1,8 +1,45 @@
+pub mod amm;
+pub mod denom;
+pub mod error;
+pub mod merkle;
+pub mod privacy;
 pub mod state;
+pub use denom::{format_amount, parse_amount};
+pub use error::*;
 pub use state::*;
 
 use linera_sdk::abi::{ContractAbi, ServiceAbi};
 use async_graphql::{Request, Response};
+use serde::{Deserialize, Serialize};
+
+/// Key the contract stores the BCS-encoded `ConwayBets` under; the service
+/// reads it back from the same place.
+pub const STATE_KEY: &[u8] = b"conway_bets_state";
+
+/// Key the contract keeps `ConwayBets::operation_log` under, apart from the
+/// state so the state's encoding doesn't depend on the `debug-log` feature.
+#[cfg(feature = "debug-log")]
+pub const OPERATION_LOG_KEY: &[u8] = b"conway_bets_operation_log";
+
+/// How many of the latest operations `ConwayBets::operation_log` keeps.
+#[cfg(feature = "debug-log")]
+pub const OPERATION_LOG_LIMIT: usize = 1_000;
+
+/// Prefix of the keys the contract stores each `LoggedEvent` under, apart
+/// from the state so the event log doesn't grow what's rewritten every block.
+pub const EVENT_KEY_PREFIX: &[u8] = b"conway_bets_event_";
+
+/// Key of the event numbered `seq`. Big-endian, so keys sort in log order.
+pub fn event_key(seq: u64) -> Vec<u8> {
+    [EVENT_KEY_PREFIX, &seq.to_be_bytes()[..]].concat()
+}
+
+/// Settings fixed when the application is created.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct ConwayBetsParameters {
+    /// Protocol fee taken from every bet into the treasury, in basis points.
+    pub fee_bps: u16,
+}
 
 // --- ABI Definition ---
 
@@ -0,0 +1,75 @@
+//! Binary SHA-256 Merkle trees over a market's positions.
+//!
+//! Leaves and inner nodes are domain-separated, and an odd node at the end
+//! of a level is paired with itself. The root of an empty tree is all zeros.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub fn hash_leaf(data: &[u8]) -> [u8; 32] {
+    Sha256::new().chain_update([0u8]).chain_update(data).finalize().into()
+}
+
+fn hash_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update([1u8])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| hash_node(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+        .collect()
+}
+
+pub fn root(leaves: &[[u8; 32]]) -> [u8; 32] {
+    if leaves.is_empty() {
+        return [0; 32];
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level[0]
+}
+
+/// Inclusion path from one leaf up to the root.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub leaf: [u8; 32],
+    pub index: u64,
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl MerkleProof {
+    pub fn new(leaves: &[[u8; 32]], index: usize) -> Option<Self> {
+        let leaf = *leaves.get(index)?;
+        let mut siblings = Vec::new();
+        let mut level = leaves.to_vec();
+        let mut position = index;
+        while level.len() > 1 {
+            siblings.push(*level.get(position ^ 1).unwrap_or(&level[position]));
+            level = next_level(&level);
+            position /= 2;
+        }
+        Some(MerkleProof { leaf, index: index as u64, siblings })
+    }
+
+    pub fn verify(&self, root: &[u8; 32]) -> bool {
+        let mut hash = self.leaf;
+        let mut position = self.index;
+        for sibling in &self.siblings {
+            hash = if position % 2 == 0 {
+                hash_node(&hash, sibling)
+            } else {
+                hash_node(sibling, &hash)
+            };
+            position /= 2;
+        }
+        hash == *root
+    }
+}
@@ -0,0 +1,20 @@
+//! Commitments and nullifiers for bets whose owner isn't recorded.
+//!
+//! A bettor picks a random 32-byte secret and stakes against
+//! `commitment(secret)`. Claiming reveals the secret; its `nullifier` is
+//! stored so the same stake can't be claimed twice, and winnings can go to
+//! any account.
+
+use sha2::{Digest, Sha256};
+
+fn tagged_hash(tag: &[u8], secret: &[u8; 32]) -> [u8; 32] {
+    Sha256::new().chain_update(tag).chain_update(secret).finalize().into()
+}
+
+pub fn commitment(secret: &[u8; 32]) -> [u8; 32] {
+    tagged_hash(b"conwaybets/commitment", secret)
+}
+
+pub fn nullifier(secret: &[u8; 32]) -> [u8; 32] {
+    tagged_hash(b"conwaybets/nullifier", secret)
+}
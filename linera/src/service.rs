@@ -1,9 +1,17 @@
 #![cfg_attr(target_arch = "wasm32", no_main)]
 
-use async_graphql::{EmptyMutation, EmptySubscription, Object, Request, Response, Schema, SimpleObject};
-use linera::{ConwayBets, Market};
+use async_graphql::{EmptySubscription, InputObject, Object, Request, Response, Schema, SimpleObject};
+use linera::{
+    build_claim_all_operation, build_claim_winnings_operation, build_place_bet_operation,
+    build_withdraw_bet_operation, event_key, parse_amount, BetQuote, ClaimablePosition, ConwayBets,
+    ConwayBetsEvent, ConwayBetsParameters, CostEstimate, DivergenceReport, EventKind, FeedBet, LoggedEvent,
+    Market, MarketConfig,
+    MarketCreationData, MarketFilter, MarketId, OpenPosition, Operation, OutcomeExposure, OutcomePayoutRow,
+    Portfolio, ReputationStats, Settlement, SolvencyReport, StreakStats, SyncOutcome, UserPosition, STATE_KEY,
+};
 use linera_sdk::{
     abi::WithServiceAbi,
+    linera_base_types::AccountOwner,
     Service, ServiceRuntime,
 };
 use std::sync::Arc;
@@ -11,6 +19,7 @@ use linera_sdk::views::linera_views::store::ReadableKeyValueStore;
 
 pub struct ConwayBetsService {
     state: Arc<ConwayBets>,
+    runtime: Arc<ServiceRuntime<Self>>,
 }
 
 impl WithServiceAbi for ConwayBetsService {
@@ -19,10 +28,8 @@ impl WithServiceAbi for ConwayBetsService {
 
 linera_sdk::service!(ConwayBetsService);
 
-const STATE_KEY: &[u8] = b"conway_bets_state";
-
 impl Service for ConwayBetsService {
-    type Parameters = ();
+    type Parameters = ConwayBetsParameters;
 
     async fn new(runtime: ServiceRuntime<Self>) -> Self {
         // FIX: Read bytes and deserialize them
@@ -31,20 +38,38 @@ impl Service for ConwayBetsService {
             .await
             .expect("Failed to read state");
 
-        let state: ConwayBets = match state_bytes {
+        let mut state: ConwayBets = match state_bytes {
             Some(bytes) => bcs::from_bytes(&bytes).expect("Failed to deserialize state"),
             None => ConwayBets::default(),
         };
+        state.rebuild_creator_index();
+        let event_keys = state.retained_event_seqs().map(event_key).collect();
+        let events = runtime.key_value_store()
+            .read_multi_values_bytes(event_keys)
+            .await
+            .expect("Failed to read events");
+        state.events = events
+            .into_iter()
+            .flatten()
+            .map(|bytes| bcs::from_bytes(&bytes).expect("Failed to deserialize event"))
+            .collect();
             
         ConwayBetsService {
             state: Arc::new(state),
+            runtime: Arc::new(runtime),
         }
     }
 
     async fn handle_query(&self, query: Request) -> Response {
         let schema = Schema::build(
-            QueryRoot { state: self.state.clone() }, 
-            EmptyMutation, 
+            QueryRoot {
+                state: self.state.clone(),
+                now: self.runtime.system_time().micros() / 1_000_000,
+            },
+            MutationRoot {
+                state: self.state.clone(),
+                runtime: self.runtime.clone(),
+            },
             EmptySubscription
         ).finish();
         schema.execute(query).await
@@ -53,6 +78,8 @@ impl Service for ConwayBetsService {
 
 struct QueryRoot {
     state: Arc<ConwayBets>,
+    // Unix timestamp of the query, in seconds.
+    now: u64,
 }
 
 #[Object]
@@ -68,16 +95,431 @@ impl QueryRoot {
             .collect()
     }
 
+    async fn trending_markets(&self, first: i32) -> Vec<MarketGql> {
+        self.state
+            .trending_markets(first.max(0) as usize, self.now)
+            .into_iter()
+            .map(MarketGql::from)
+            .collect()
+    }
+
     async fn market(&self, id: String) -> Option<MarketGql> {
-        self.state.markets.values()
-            .find(|m| m.id.id.to_string() == id) 
-            .map(|m| MarketGql::from(m))
+        find_market(&self.state, &id).map(MarketGql::from)
+    }
+
+    /// The rules `id` runs under: pricing, fees, limits, oracle and windows.
+    async fn market_config(&self, id: String) -> Option<MarketConfig> {
+        let market = find_market(&self.state, &id)?;
+        self.state.market_config(&market.id)
+    }
+
+    /// Markets matching every field set in `filter`; `min_liquidity` is in
+    /// whole tokens.
+    async fn get_markets_filtered(&self, filter: MarketFilterGql) -> async_graphql::Result<Vec<MarketGql>> {
+        let filter = MarketFilter {
+            creator: filter.creator,
+            resolved: filter.resolved,
+            min_liquidity: filter.min_liquidity.map(|a| parse_amount(&a, TOKEN_DECIMALS)).transpose()?,
+        };
+        Ok(self.state.markets_filtered(&filter).into_iter().map(MarketGql::from).collect())
+    }
+
+    /// Every stored market, unpaginated.
+    async fn get_all_markets(&self) -> Vec<MarketGql> {
+        self.state.markets.values().map(MarketGql::from).collect()
+    }
+
+    async fn get_market(&self, id: String) -> Option<MarketGql> {
+        find_market(&self.state, &id).map(MarketGql::from)
+    }
+
+    /// Hex-encoded state hash of a market hosted on this chain.
+    async fn get_market_state_hash(&self, id: String) -> async_graphql::Result<String> {
+        let market = find_market(&self.state, &id).ok_or("market not found")?;
+        Ok(hex(&self.state.market_state_hash(&market.id)?))
+    }
+
+    /// The pool and hashes two chains compare to tell whether they agree on a market.
+    async fn get_market_state(&self, id: String) -> Option<MarketStateGql> {
+        find_market(&self.state, &id).map(MarketStateGql::from)
+    }
+
+    /// Every position `user` holds, claimed ones included, newest first.
+    /// `since` keeps only bets placed at or after that Unix timestamp.
+    async fn get_user_bets(&self, user: AccountOwner, since: Option<u64>) -> Vec<UserPositionGql> {
+        self.state.user_bets(user, since).into_iter().map(UserPositionGql::from).collect()
+    }
+
+    /// Recent bets across every market, oldest first. Pass the last `seq`
+    /// seen as `after` to page forward.
+    async fn global_bet_feed(&self, first: i32, after: Option<u64>) -> Vec<FeedBetGql> {
+        self.state
+            .global_bet_feed(first.max(0) as usize, after)
+            .into_iter()
+            .map(FeedBetGql::from)
+            .collect()
+    }
+
+    /// Implied probability per outcome, summing to 1: each outcome's share of
+    /// the pool, or its LMSR price on AMM markets. Uniform before any bets.
+    async fn get_market_odds(&self, id: String) -> Vec<f64> {
+        find_market(&self.state, &id).map(Market::implied_probabilities).unwrap_or_default()
+    }
+
+    /// Implied probability per outcome; null where the outcome is too thinly
+    /// funded to price.
+    async fn odds(&self, id: String) -> Option<Vec<Option<f64>>> {
+        let market = find_market(&self.state, &id)?;
+        self.state.odds(&market.id)
+    }
+
+    /// Decimal odds after fees, at `user`'s fee tier when given.
+    async fn net_odds(&self, id: String, outcome_index: u32, user: Option<AccountOwner>) -> Option<f64> {
+        let market = find_market(&self.state, &id)?;
+        self.state.net_odds(&market.id, outcome_index, user)
+    }
+
+    /// What `user` staking `amount` whole tokens on `outcome_index` would get
+    /// if placed now; matches the executed bet exactly.
+    async fn quote_bet(
+        &self,
+        id: String,
+        user: AccountOwner,
+        outcome_index: u32,
+        amount: String,
+    ) -> async_graphql::Result<BetQuote> {
+        let market = find_market(&self.state, &id).ok_or("market not found")?;
+        let amount = parse_amount(&amount, TOKEN_DECIMALS)?;
+        Ok(self.state.quote_bet(market.id, user, outcome_index, amount)?)
+    }
+
+    async fn break_even(&self, user: AccountOwner, id: String, outcome_index: u32) -> Option<f64> {
+        let market = find_market(&self.state, &id)?;
+        self.state.break_even(user, market.id, outcome_index)
+    }
+
+    /// Per outcome, whether its winning would leave `user` ahead on the market.
+    async fn break_even_outcomes(&self, user: AccountOwner, id: String) -> Vec<BreakEvenOutcomeGql> {
+        let Some(market) = find_market(&self.state, &id) else {
+            return Vec::new();
+        };
+        self.state
+            .break_even_outcomes(user, market.id)
+            .into_iter()
+            .map(|(outcome_index, profitable)| BreakEvenOutcomeGql { outcome_index, profitable })
+            .collect()
+    }
+
+    /// Markets `user` has bookmarked, resolved ones included.
+    async fn watchlist(&self, user: AccountOwner) -> Vec<MarketGql> {
+        self.state.watched_markets(user).into_iter().map(MarketGql::from).collect()
+    }
+
+    /// Protocol fees collected so far.
+    async fn get_treasury_balance(&self) -> String {
+        self.state.treasury.to_string()
+    }
+
+    /// Lifecycle events logged after sequence number `since`, oldest first.
+    /// Pass the last `seq` seen to tail the log.
+    async fn get_events(&self, since: u64) -> Vec<EventGql> {
+        self.state.events_since(since).into_iter().map(EventGql::from).collect()
+    }
+
+    /// Open stakes, wins and unclaimed payouts for `user` in one call.
+    async fn get_user_portfolio(&self, user: AccountOwner) -> Portfolio {
+        self.state.portfolio(user)
+    }
+
+    async fn streaks(&self, user: AccountOwner) -> StreakStats {
+        self.state.streaks(user)
+    }
+
+    /// Markets `resolver` has resolved, and how many of those were disputed
+    /// and overturned.
+    async fn resolver_reputation(&self, resolver: AccountOwner) -> ReputationStats {
+        self.state.resolver_reputation(resolver)
+    }
+
+    async fn liquidity_for_price_move(
+        &self,
+        id: String,
+        outcome_index: u32,
+        target_probability: f64,
+    ) -> async_graphql::Result<String> {
+        let market = find_market(&self.state, &id).ok_or("market not found")?;
+        let amount = self.state.liquidity_for_price_move(market.id, outcome_index, target_probability)?;
+        Ok(amount.to_string())
+    }
+
+    async fn time_to_close(&self, id: String) -> Option<i64> {
+        let market = find_market(&self.state, &id)?;
+        self.state.time_to_close(&market.id, self.now)
+    }
+
+    async fn estimated_resolution_delay(&self, id: String) -> Option<u64> {
+        let market = find_market(&self.state, &id)?;
+        self.state.estimated_resolution_delay(&market.id)
+    }
+
+    async fn claimable_positions(&self, first: i32, after: Option<String>) -> Vec<ClaimablePositionGql> {
+        let after = after.and_then(|id| find_market(&self.state, &id)).map(|m| m.id);
+        self.state
+            .claimable_positions(first.max(0) as usize, after)
+            .into_iter()
+            .map(ClaimablePositionGql::from)
+            .collect()
+    }
+
+    async fn market_state_size(&self, id: String) -> Option<usize> {
+        let market = find_market(&self.state, &id)?;
+        self.state.market_state_size(&market.id)
+    }
+
+    async fn solvency_report(&self) -> SolvencyReport {
+        self.state.solvency_report()
+    }
+
+    async fn position_proof(&self, id: String, user: AccountOwner, outcome_index: u32) -> Option<MerkleProofGql> {
+        let market = find_market(&self.state, &id)?;
+        let proof = self.state.position_proof(&market.id, user, outcome_index)?;
+        Some(MerkleProofGql {
+            leaf: hex(&proof.leaf),
+            index: proof.index,
+            siblings: proof.siblings.iter().map(|s| hex(s)).collect(),
+        })
+    }
+
+    async fn settlement_summary(&self, id: String) -> Option<SettlementGql> {
+        let market = find_market(&self.state, &id)?;
+        self.state.settlement_summary(&market.id).map(SettlementGql::from)
+    }
+
+    /// Per outcome, what its backers would share if it won and the payout
+    /// per unit staked.
+    async fn payout_table(&self, id: String) -> Vec<OutcomePayoutRowGql> {
+        let Some(market) = find_market(&self.state, &id) else {
+            return Vec::new();
+        };
+        let rows = self.state.payout_table(&market.id).unwrap_or_default();
+        rows.into_iter().map(OutcomePayoutRowGql::from).collect()
+    }
+
+    async fn open_positions(&self, user: AccountOwner) -> Vec<OpenPositionGql> {
+        self.state.open_positions(user).into_iter().map(OpenPositionGql::from).collect()
+    }
+
+    async fn exposure_by_event(&self, user: AccountOwner, external_event_id: String) -> Vec<OutcomeExposure> {
+        self.state.exposure_by_event(user, &external_event_id)
+    }
+
+    async fn price_history(
+        &self,
+        id: String,
+        outcome_index: u32,
+        from: u64,
+        to: u64,
+        max_points: Option<usize>,
+    ) -> Vec<PricePointGql> {
+        let Some(market) = find_market(&self.state, &id) else {
+            return Vec::new();
+        };
+        self.state
+            .price_history(&market.id, outcome_index, from, to, max_points.unwrap_or(200))
+            .into_iter()
+            .map(|(timestamp, probability)| PricePointGql { timestamp, probability })
+            .collect()
+    }
+
+    /// Change in an outcome's implied probability since the market opened.
+    async fn probability_drift(&self, id: String, outcome_index: u32) -> Option<f64> {
+        let market = find_market(&self.state, &id)?;
+        self.state.probability_drift(&market.id, outcome_index)
+    }
+
+    /// Correlation of two outcomes' odds histories where they overlap.
+    async fn odds_correlation(
+        &self,
+        market_a: String,
+        outcome_a: u32,
+        market_b: String,
+        outcome_b: u32,
+    ) -> Option<f64> {
+        let market_a = find_market(&self.state, &market_a)?;
+        let market_b = find_market(&self.state, &market_b)?;
+        self.state.odds_correlation(&market_a.id, outcome_a, &market_b.id, outcome_b)
+    }
+
+    async fn divergent_markets(&self) -> Vec<DivergenceReportGql> {
+        self.state.divergent_markets().into_iter().map(DivergenceReportGql::from).collect()
+    }
+
+    /// Whether peers' last sync of this market agreed with our state hash;
+    /// null if it hasn't been synced.
+    async fn get_sync_status(&self, id: String) -> Option<SyncOutcome> {
+        let market = find_market(&self.state, &id)?;
+        self.state.sync_status(&market.id)
+    }
+
+    /// Work estimate for an operation given as hex-encoded BCS, as returned
+    /// by the `build*Operation` queries.
+    async fn estimate_cost(&self, operation: String) -> async_graphql::Result<CostEstimate> {
+        let operation: Operation = bcs::from_bytes(&unhex(&operation).ok_or("invalid hex")?)?;
+        Ok(self.state.estimate_cost(&operation))
+    }
+
+    async fn fee_report(&self, from: u64, to: u64, bucket_secs: u64) -> Vec<FeeBucketGql> {
+        self.state
+            .fee_report(from, to, bucket_secs)
+            .into_iter()
+            .map(|(start, total)| FeeBucketGql { start, total: total.to_string() })
+            .collect()
+    }
+
+    async fn resolution_distribution(&self) -> Vec<OutcomeTallyGql> {
+        self.state
+            .resolution_distribution()
+            .into_iter()
+            .map(|(label, wins)| OutcomeTallyGql { label, wins })
+            .collect()
+    }
+
+    /// Stakes per outcome label summed over all open markets.
+    async fn open_interest_by_label(&self) -> Vec<LabelInterestGql> {
+        self.state
+            .open_interest_by_label()
+            .into_iter()
+            .map(|(label, total)| LabelInterestGql { label, total: total.to_string() })
+            .collect()
+    }
+
+    /// Hex-encoded BCS bytes of the operation placing this bet; `amount` is
+    /// in whole tokens, e.g. "2.5".
+    async fn build_place_bet_operation(
+        &self,
+        id: String,
+        user: AccountOwner,
+        outcome_index: u32,
+        amount: String,
+    ) -> async_graphql::Result<String> {
+        let market = find_market(&self.state, &id).ok_or("market not found")?;
+        let amount = parse_amount(&amount, TOKEN_DECIMALS)?;
+        encode_operation(&build_place_bet_operation(market.id, user, outcome_index, amount))
+    }
+
+    async fn build_withdraw_bet_operation(
+        &self,
+        id: String,
+        user: AccountOwner,
+        position_index: usize,
+    ) -> async_graphql::Result<String> {
+        let market = find_market(&self.state, &id).ok_or("market not found")?;
+        encode_operation(&build_withdraw_bet_operation(user, market.id, position_index))
+    }
+
+    async fn build_claim_winnings_operation(
+        &self,
+        id: String,
+        user: AccountOwner,
+    ) -> async_graphql::Result<String> {
+        let market = find_market(&self.state, &id).ok_or("market not found")?;
+        encode_operation(&build_claim_winnings_operation(user, market.id))
+    }
+
+    async fn build_claim_all_operation(
+        &self,
+        ids: Vec<String>,
+        user: AccountOwner,
+    ) -> async_graphql::Result<String> {
+        let market_ids = ids
+            .iter()
+            .map(|id| find_market(&self.state, id).map(|m| m.id).ok_or("market not found"))
+            .collect::<Result<Vec<_>, _>>()?;
+        encode_operation(&build_claim_all_operation(user, market_ids))
+    }
+}
+
+/// Schedules operations on this chain, for clients that write through
+/// GraphQL. Each runs in a later block, where it may still be rejected.
+struct MutationRoot {
+    state: Arc<ConwayBets>,
+    runtime: Arc<ServiceRuntime<ConwayBetsService>>,
+}
+
+#[Object]
+impl MutationRoot {
+    /// Schedules a `CreateMarket` and returns `true`. The market's id isn't
+    /// known until the operation runs; it shows up in `markets` then.
+    async fn create_market(&self, creator: AccountOwner, market: MarketCreationInput) -> bool {
+        let data = MarketCreationData {
+            title: market.title,
+            description: market.description,
+            end_time: market.end_time,
+            outcomes: market.outcomes,
+            resolver: market.resolver,
+            category: market.category,
+            ..MarketCreationData::default()
+        };
+        self.runtime.schedule_operation(&Operation::CreateMarket { creator, data });
+        true
+    }
+
+    /// Schedules a `PlaceBet` of `amount` whole tokens on a market hosted
+    /// here or tracked from another chain, and returns `true`. The position
+    /// and its receipt only exist once the operation runs; they show up in
+    /// `getUserBets` then.
+    async fn place_bet(
+        &self,
+        id: String,
+        user: AccountOwner,
+        outcome_index: u32,
+        amount: String,
+    ) -> async_graphql::Result<bool> {
+        let market_id = find_market_id(&self.state, &id).ok_or("market not found")?;
+        let amount = parse_amount(&amount, TOKEN_DECIMALS)?;
+        self.runtime.schedule_operation(&build_place_bet_operation(market_id, user, outcome_index, amount));
+        Ok(true)
+    }
+}
+
+/// Looks a market up by its full `"<chain_id>:<id>"` form, or by the bare
+/// number for markets hosted here.
+fn find_market<'a>(state: &'a ConwayBets, id: &str) -> Option<&'a Market> {
+    if let Ok(market_id) = id.parse::<MarketId>() {
+        return state.markets.get(&market_id);
     }
+    state.markets.values().find(|m| m.id.id.to_string() == id)
+}
+
+/// Like `find_market`, but also knows markets other chains host that this
+/// one tracks, which can only be named in full.
+fn find_market_id(state: &ConwayBets, id: &str) -> Option<MarketId> {
+    if let Some(market) = find_market(state, id) {
+        return Some(market.id);
+    }
+    id.parse::<MarketId>().ok().filter(|market_id| state.remote_markets.contains_key(market_id))
+}
+
+#[derive(InputObject)]
+struct MarketCreationInput {
+    title: String,
+    description: String,
+    end_time: u64,
+    outcomes: Vec<String>,
+    resolver: Option<AccountOwner>,
+    category: Option<String>,
+}
+
+#[derive(InputObject)]
+struct MarketFilterGql {
+    creator: Option<AccountOwner>,
+    resolved: Option<bool>,
+    min_liquidity: Option<String>,
 }
 
 #[derive(SimpleObject)]
 struct MarketGql {
-    id: String,
+    id: MarketId,
     title: String,
     description: String,
     creator: String,
@@ -86,14 +528,21 @@ struct MarketGql {
     total_liquidity: String,
     is_resolved: bool,
     winning_outcome: Option<u32>,
+    winning_outcomes: Vec<u32>,
     state_hash: String,
+    positions_root: String,
     created_at: u64,
+    // LMSR liquidity parameter `b`; null for pool-priced markets.
+    lmsr_b: Option<String>,
+    creator_fee_bps: u16,
+    // Creator fee taken from the pot so far.
+    creator_fees: String,
 }
 
 impl From<&Market> for MarketGql {
     fn from(m: &Market) -> Self {
         MarketGql {
-            id: m.id.id.to_string(),
+            id: m.id,
             title: m.title.clone(),
             description: m.description.clone(),
             creator: m.creator.to_string(),
@@ -102,8 +551,302 @@ impl From<&Market> for MarketGql {
             total_liquidity: m.total_liquidity.to_string(),
             is_resolved: m.is_resolved,
             winning_outcome: m.winning_outcome,
-            state_hash: m.state_hash.iter().map(|b| format!("{:02x}", b)).collect(),
-            created_at: 0,
+            winning_outcomes: m.winning_outcomes.iter().copied().collect(),
+            state_hash: hex(&m.state_hash),
+            positions_root: hex(&m.positions_root),
+            created_at: m.created_at,
+            lmsr_b: m.lmsr_b.map(|b| b.to_string()),
+            creator_fee_bps: m.creator_fee_bps,
+            creator_fees: m.creator_fees.to_string(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct SettlementGql {
+    market_id: MarketId,
+    winning_outcomes: Vec<u32>,
+    total_pool: String,
+    total_paid: String,
+    fees: String,
+    dust: String,
+    winners: u32,
+}
+
+impl From<Settlement> for SettlementGql {
+    fn from(s: Settlement) -> Self {
+        SettlementGql {
+            market_id: s.market_id,
+            winning_outcomes: s.winning_outcomes,
+            total_pool: s.total_pool.to_string(),
+            total_paid: s.total_paid.to_string(),
+            fees: s.fees.to_string(),
+            dust: s.dust.to_string(),
+            winners: s.winners,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct ClaimablePositionGql {
+    market_id: MarketId,
+    user: AccountOwner,
+    amount: String,
+}
+
+impl From<ClaimablePosition> for ClaimablePositionGql {
+    fn from(c: ClaimablePosition) -> Self {
+        ClaimablePositionGql {
+            market_id: c.market_id,
+            user: c.user,
+            amount: c.amount.to_string(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct OpenPositionGql {
+    market_id: MarketId,
+    outcome_index: u32,
+    label: String,
+    staked: String,
+    shares: String,
+    implied_probability: f64,
+    value: String,
+}
+
+impl From<OpenPosition> for OpenPositionGql {
+    fn from(p: OpenPosition) -> Self {
+        OpenPositionGql {
+            market_id: p.market_id,
+            outcome_index: p.outcome_index,
+            label: p.label,
+            staked: p.staked.to_string(),
+            shares: p.shares.to_string(),
+            implied_probability: p.implied_probability,
+            value: p.value.to_string(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct OutcomePayoutRowGql {
+    outcome_index: u32,
+    label: String,
+    total_payout: String,
+    multiplier: Option<f64>,
+}
+
+impl From<OutcomePayoutRow> for OutcomePayoutRowGql {
+    fn from(r: OutcomePayoutRow) -> Self {
+        OutcomePayoutRowGql {
+            outcome_index: r.outcome_index,
+            label: r.label,
+            total_payout: r.total_payout.to_string(),
+            multiplier: r.multiplier,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct PricePointGql {
+    timestamp: u64,
+    probability: f64,
+}
+
+#[derive(SimpleObject)]
+struct MarketStateGql {
+    market_id: MarketId,
+    state_hash: String,
+    positions_root: String,
+    total_liquidity: String,
+    outcome_shares: Vec<String>,
+    min_bet: String,
+    is_resolved: bool,
+    is_cancelled: bool,
+}
+
+impl From<&Market> for MarketStateGql {
+    fn from(m: &Market) -> Self {
+        MarketStateGql {
+            market_id: m.id,
+            state_hash: hex(&m.state_hash),
+            positions_root: hex(&m.positions_root),
+            total_liquidity: m.total_liquidity.to_string(),
+            outcome_shares: m.outcome_shares.iter().map(|s| s.to_string()).collect(),
+            min_bet: m.min_bet.to_string(),
+            is_resolved: m.is_resolved,
+            is_cancelled: m.is_cancelled,
         }
     }
-}
\ No newline at end of file
+}
+
+#[derive(SimpleObject)]
+struct UserPositionGql {
+    market_id: MarketId,
+    outcome_index: u32,
+    amount: String,
+    shares: String,
+    locked: bool,
+    claimed: bool,
+    placed_at: u64,
+}
+
+impl From<&UserPosition> for UserPositionGql {
+    fn from(p: &UserPosition) -> Self {
+        UserPositionGql {
+            market_id: p.market_id,
+            outcome_index: p.outcome_index,
+            amount: p.amount.to_string(),
+            shares: p.shares.to_string(),
+            locked: p.locked,
+            claimed: p.claimed,
+            placed_at: p.placed_at,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct EventGql {
+    seq: u64,
+    timestamp: u64,
+    kind: EventKind,
+    market_id: MarketId,
+    // The creator, bettor or claimant, for events that have one.
+    user: Option<String>,
+    outcome_index: Option<u32>,
+    winning_outcomes: Vec<u32>,
+    amount: Option<String>,
+}
+
+impl From<&LoggedEvent> for EventGql {
+    fn from(logged: &LoggedEvent) -> Self {
+        let mut gql = EventGql {
+            seq: logged.seq,
+            timestamp: logged.timestamp,
+            kind: logged.event.kind(),
+            market_id: logged.event.market_id(),
+            user: None,
+            outcome_index: None,
+            winning_outcomes: Vec::new(),
+            amount: None,
+        };
+        match &logged.event {
+            ConwayBetsEvent::MarketCreated { creator, .. } => {
+                gql.user = Some(creator.to_string());
+            }
+            ConwayBetsEvent::BetPlaced { user, outcome_index, amount, .. } => {
+                gql.user = Some(user.to_string());
+                gql.outcome_index = Some(*outcome_index);
+                gql.amount = Some(amount.to_string());
+            }
+            ConwayBetsEvent::MarketResolved { winning_outcomes, .. } => {
+                gql.winning_outcomes = winning_outcomes.iter().copied().collect();
+            }
+            ConwayBetsEvent::WinningsClaimed { user, amount, .. } => {
+                gql.user = Some(user.to_string());
+                gql.amount = Some(amount.to_string());
+            }
+            ConwayBetsEvent::MarketCancelled { .. } => {}
+        }
+        gql
+    }
+}
+
+#[derive(SimpleObject)]
+struct FeedBetGql {
+    seq: u64,
+    market_id: MarketId,
+    user: String,
+    outcome_index: u32,
+    amount: String,
+    placed_at: u64,
+}
+
+impl From<&FeedBet> for FeedBetGql {
+    fn from(b: &FeedBet) -> Self {
+        FeedBetGql {
+            seq: b.seq,
+            market_id: b.market_id,
+            user: b.user.to_string(),
+            outcome_index: b.outcome_index,
+            amount: b.amount.to_string(),
+            placed_at: b.placed_at,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct BreakEvenOutcomeGql {
+    outcome_index: u32,
+    profitable: bool,
+}
+
+#[derive(SimpleObject)]
+struct FeeBucketGql {
+    start: u64,
+    total: String,
+}
+
+#[derive(SimpleObject)]
+struct OutcomeTallyGql {
+    label: String,
+    wins: u32,
+}
+
+#[derive(SimpleObject)]
+struct LabelInterestGql {
+    label: String,
+    total: String,
+}
+
+#[derive(SimpleObject)]
+struct DivergenceReportGql {
+    market_id: MarketId,
+    local_hash: String,
+    remote_hash: String,
+    reporting_chain: String,
+    block_height: u64,
+    detected_at: u64,
+}
+
+impl From<DivergenceReport> for DivergenceReportGql {
+    fn from(d: DivergenceReport) -> Self {
+        DivergenceReportGql {
+            market_id: d.market_id,
+            local_hash: hex(&d.local_hash),
+            remote_hash: hex(&d.remote_hash),
+            reporting_chain: d.reporting_chain.to_string(),
+            block_height: d.block_height,
+            detected_at: d.detected_at,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct MerkleProofGql {
+    leaf: String,
+    index: u64,
+    siblings: Vec<String>,
+}
+
+// `Amount` counts attos, 10^-18 of a token.
+const TOKEN_DECIMALS: u8 = 18;
+
+fn encode_operation(operation: &Operation) -> async_graphql::Result<String> {
+    Ok(hex(&bcs::to_bytes(operation)?))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -1,7 +1,14 @@
+use crate::{amm, merkle, merkle::MerkleProof, privacy, ConwayBetsError, MarketError};
+#[cfg(feature = "debug-log")]
+use crate::OPERATION_LOG_LIMIT;
+use async_graphql::{Enum, InputValueError, InputValueResult, Scalar, ScalarType, SimpleObject, Value};
 use linera_sdk::linera_base_types::{AccountOwner, Amount, ChainId};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
-use std::error::Error;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::ops::{Bound, RangeInclusive};
+use std::str::FromStr;
+use sha2::{Digest, Sha256};
 
 // --- Definitions ---
 
@@ -11,14 +18,46 @@ pub struct MarketId {
     pub id: u64,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl fmt::Display for MarketId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.chain_id, self.id)
+    }
+}
+
+impl FromStr for MarketId {
+    type Err = ConwayBetsError;
+
+    /// Parses the `"<chain_id>:<id>"` form `Display` writes.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ConwayBetsError::InvalidMarketId(s.to_string());
+        let (chain_id, id) = s.split_once(':').ok_or_else(invalid)?;
+        Ok(MarketId {
+            chain_id: chain_id.parse().map_err(|_| invalid())?,
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// A GraphQL string in the `"<chain_id>:<id>"` form.
+#[Scalar]
+impl ScalarType for MarketId {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        match &value {
+            Value::String(s) => Ok(s.parse()?),
+            _ => Err(InputValueError::expected_type(value)),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum Operation {
     CreateMarket {
         creator: AccountOwner,
-        title: String,
-        description: String,
-        end_time: u64,
-        outcomes: Vec<String>,
+        data: MarketCreationData,
     },
     PlaceBet {
         market_id: MarketId,
@@ -26,25 +65,397 @@ pub enum Operation {
         outcome_index: u32,
         amount: Amount,
     },
+    // A bet that only goes through while the outcome is priced at or below
+    // the given probability, in basis points (BCS has no floats).
+    ConditionalBet {
+        user: AccountOwner,
+        market_id: MarketId,
+        outcome_index: u32,
+        amount: Amount,
+        max_implied_probability_bps: u16,
+    },
+    SetResolver {
+        creator: AccountOwner,
+        market_id: MarketId,
+        resolver: Option<AccountOwner>,
+    },
+    EditOutcome {
+        creator: AccountOwner,
+        market_id: MarketId,
+        outcome_index: u32,
+        label: String,
+    },
+    Reindex,
+    LockPosition {
+        user: AccountOwner,
+        market_id: MarketId,
+        outcome_index: u32,
+    },
+    UnlockPosition {
+        user: AccountOwner,
+        market_id: MarketId,
+        outcome_index: u32,
+    },
+    WithdrawBet {
+        user: AccountOwner,
+        market_id: MarketId,
+        position_index: usize,
+    },
+    ResolveMarket {
+        resolver: AccountOwner,
+        resolution: ResolutionData,
+    },
+    DelegateResolver {
+        current_resolver: AccountOwner,
+        market_id: MarketId,
+        new_resolver: AccountOwner,
+    },
+    CancelMarket {
+        caller: AccountOwner,
+        market_id: MarketId,
+    },
+    SuspendOutcome {
+        caller: AccountOwner,
+        market_id: MarketId,
+        outcome_index: u32,
+    },
+    // Resolves a scalar market to the bucket containing `value`.
+    ResolveScalar {
+        resolver: AccountOwner,
+        market_id: MarketId,
+        value: i64,
+    },
+    // Resolves to every listed outcome at once; their backers share the pool.
+    ResolveMarketAnyOf {
+        resolver: AccountOwner,
+        market_id: MarketId,
+        winning_outcomes: BTreeSet<u32>,
+    },
+    ClaimWinnings {
+        user: AccountOwner,
+        market_id: MarketId,
+    },
+    // Adds house subsidy to a fixed-odds market, opening it once the minimum is met.
+    FundMarket {
+        creator: AccountOwner,
+        market_id: MarketId,
+        amount: Amount,
+    },
+    // Contests a resolution during its dispute window, freezing claims.
+    ChallengeResolution {
+        challenger: AccountOwner,
+        market_id: MarketId,
+    },
+    // The owner's ruling on a challenge; an overturn applies `Config::post_dispute`.
+    RuleOnDispute {
+        arbiter: AccountOwner,
+        market_id: MarketId,
+        overturn: bool,
+    },
+    // A bet recorded against a commitment instead of the bettor's account.
+    PlaceCommittedBet {
+        user: AccountOwner,
+        market_id: MarketId,
+        outcome_index: u32,
+        amount: Amount,
+        commitment: [u8; 32],
+    },
+    // Reveals a committed bet's secret to pay its winnings to `recipient`.
+    ClaimCommitted {
+        recipient: AccountOwner,
+        market_id: MarketId,
+        secret: [u8; 32],
+    },
+    // Breaks an N-outcome market into N "X vs not X" binaries and freezes it.
+    SplitMarket {
+        creator: AccountOwner,
+        market_id: MarketId,
+    },
+    // Folds a duplicate market into another for the same event. Owner only.
+    MergeMarkets {
+        caller: AccountOwner,
+        source: MarketId,
+        target: MarketId,
+    },
+    // Expires pending cross-chain bets older than `Config::pending_bet_ttl_secs`.
+    TickPending,
+    // Claims every listed market in one go, skipping those with nothing to collect.
+    ClaimAll {
+        user: AccountOwner,
+        market_ids: Vec<MarketId>,
+    },
+    // Moderator decisions on a market awaiting approval.
+    ApproveMarket {
+        moderator: AccountOwner,
+        market_id: MarketId,
+    },
+    RejectMarket {
+        moderator: AccountOwner,
+        market_id: MarketId,
+    },
+    // Moves rounding dust and abandoned balances under `Config::dust_threshold` to
+    // `destination`, e.g. a burn, charity or house account. Owner only.
+    SweepDust {
+        caller: AccountOwner,
+        destination: AccountOwner,
+    },
+    // Bookmarks a market for `user`, or drops the bookmark.
+    Watch {
+        user: AccountOwner,
+        market_id: MarketId,
+    },
+    Unwatch {
+        user: AccountOwner,
+        market_id: MarketId,
+    },
+    // Withdraws a bet sent to another chain's market while it's still unconfirmed there.
+    CancelPendingBet {
+        user: AccountOwner,
+        nonce: u64,
+    },
+    // Adds `additional` to a cross-chain bet the market chain turned away as
+    // underfunded, and sends it again.
+    TopUpAndRetry {
+        user: AccountOwner,
+        nonce: u64,
+        additional: Amount,
+    },
+    // Adds to the pool early-bettor bonuses are paid from.
+    FundRewards {
+        funder: AccountOwner,
+        amount: Amount,
+    },
+    // Markets mirrored from an external feed, created all together or not at
+    // all. Feeders only.
+    BulkCreateMarkets {
+        feeder: AccountOwner,
+        markets: Vec<MarketCreationData>,
+    },
+    // Mints in-app balance. Only the configured owner may use it, and only in
+    // builds with the `faucet` feature; it is always part of the enum so the
+    // encoding of the other operations doesn't depend on the feature.
+    Credit {
+        user: AccountOwner,
+        amount: Amount,
+    },
+}
+
+impl Operation {
+    /// The account the operation acts for. It must be the block's
+    /// authenticated signer; `None` for operations anyone may submit.
+    pub fn acting_owner(&self) -> Option<AccountOwner> {
+        match self {
+            // Credit is checked against the configured owner when executed.
+            Operation::Reindex | Operation::TickPending | Operation::Credit { .. } => None,
+            Operation::CreateMarket { creator, .. }
+            | Operation::SetResolver { creator, .. }
+            | Operation::EditOutcome { creator, .. }
+            | Operation::FundMarket { creator, .. }
+            | Operation::SplitMarket { creator, .. } => Some(*creator),
+            Operation::PlaceBet { user, .. }
+            | Operation::ConditionalBet { user, .. }
+            | Operation::LockPosition { user, .. }
+            | Operation::UnlockPosition { user, .. }
+            | Operation::WithdrawBet { user, .. }
+            | Operation::ClaimWinnings { user, .. }
+            | Operation::PlaceCommittedBet { user, .. }
+            | Operation::ClaimAll { user, .. }
+            | Operation::Watch { user, .. }
+            | Operation::Unwatch { user, .. }
+            | Operation::CancelPendingBet { user, .. }
+            | Operation::TopUpAndRetry { user, .. } => Some(*user),
+            Operation::ResolveMarket { resolver, .. }
+            | Operation::ResolveScalar { resolver, .. }
+            | Operation::ResolveMarketAnyOf { resolver, .. } => Some(*resolver),
+            Operation::DelegateResolver { current_resolver, .. } => Some(*current_resolver),
+            Operation::CancelMarket { caller, .. }
+            | Operation::SuspendOutcome { caller, .. }
+            | Operation::MergeMarkets { caller, .. }
+            | Operation::SweepDust { caller, .. } => Some(*caller),
+            Operation::ChallengeResolution { challenger, .. } => Some(*challenger),
+            Operation::RuleOnDispute { arbiter, .. } => Some(*arbiter),
+            Operation::ClaimCommitted { recipient, .. } => Some(*recipient),
+            Operation::ApproveMarket { moderator, .. } | Operation::RejectMarket { moderator, .. } => {
+                Some(*moderator)
+            }
+            Operation::FundRewards { funder, .. } => Some(*funder),
+            Operation::BulkCreateMarkets { feeder, .. } => Some(*feeder),
+        }
+    }
+
+    /// Fails unless `signer` is the account the operation acts for. The
+    /// accounts named in an operation are only trusted once this passes.
+    pub fn check_signer(&self, signer: Option<AccountOwner>) -> Result<(), ConwayBetsError> {
+        match self.acting_owner() {
+            Some(owner) if signer != Some(owner) => Err(ConwayBetsError::NotAuthorized),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The `PlaceBet` operation a client should submit to stake `amount` on an outcome.
+pub fn build_place_bet_operation(
+    market_id: MarketId,
+    user: AccountOwner,
+    outcome_index: u32,
+    amount: Amount,
+) -> Operation {
+    Operation::PlaceBet { market_id, user, outcome_index, amount }
+}
+
+pub fn build_withdraw_bet_operation(user: AccountOwner, market_id: MarketId, position_index: usize) -> Operation {
+    Operation::WithdrawBet { user, market_id, position_index }
+}
+
+pub fn build_claim_winnings_operation(user: AccountOwner, market_id: MarketId) -> Operation {
+    Operation::ClaimWinnings { user, market_id }
+}
+
+pub fn build_claim_all_operation(user: AccountOwner, market_ids: Vec<MarketId>) -> Operation {
+    Operation::ClaimAll { user, market_ids }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MarketCreationData {
+    pub title: String,
+    pub description: String,
+    pub end_time: u64, // Unix timestamp
+    pub outcomes: Vec<String>,
+    pub min_confirmations: u64,
+    // Account that may resolve alongside the creator, e.g. an oracle.
+    pub resolver: Option<AccountOwner>,
+    // Oracle allowed to resolve if the primary resolver hasn't done so
+    // `fallback_after_secs` after `end_time`.
+    pub fallback_oracle: Option<AccountOwner>,
+    pub fallback_after_secs: u64,
+    // LMSR liquidity parameter; the market is AMM-priced when set.
+    pub lmsr_b: Option<Amount>,
+    // Grows `b` with volume: b = lmsr_b + lmsr_b_growth_bps / 10_000 * total_liquidity.
+    pub lmsr_b_growth_bps: u32,
+    pub category: Option<String>,
+    // Time after resolution during which it may be challenged and winnings stay locked.
+    pub dispute_window_secs: u64,
+    // Below this many distinct bettors the market can only be cancelled.
+    pub min_bettors_to_resolve: Option<u32>,
+    // House subsidy a fixed-odds market needs before it takes bets; zero opens immediately.
+    pub min_pool_to_open: Amount,
+    // Real-world event the market is about; markets sharing it are correlated.
+    pub external_event_id: Option<String>,
+    // Seconds after creation before bets are accepted, so seed prices can't be sniped.
+    pub first_bet_delay_secs: u64,
+    // Makes this a scalar market: the outcomes are equal-width buckets over the range.
+    pub scalar_range: Option<ScalarRange>,
+    // Market this one was derived from, e.g. the parent of a split.
+    pub depends_on: Option<MarketId>,
+    // Cut of the winning pot paid to the creator, in basis points.
+    pub creator_fee_bps: u16,
+    // Smallest stake a bet may place, before fees; zero allows any.
+    pub min_bet: Amount,
+}
+
+/// Numeric range split evenly across a scalar market's outcomes, lowest first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScalarRange {
+    pub min: i64,
+    pub max: i64,
+}
+
+impl ScalarRange {
+    /// Index of the bucket containing `value`. Each bucket includes its lower
+    /// bound; values outside the range land in the edge buckets.
+    pub fn bucket(&self, value: i64, buckets: usize) -> u32 {
+        let last = buckets.saturating_sub(1) as i128;
+        let width = i128::from(self.max) - i128::from(self.min);
+        if width <= 0 {
+            return 0;
+        }
+        let offset = i128::from(value) - i128::from(self.min);
+        (offset * (last + 1) / width).clamp(0, last) as u32
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResolutionData {
+    pub market_id: MarketId,
+    pub winning_outcome: u32,
+    pub resolution_proof: Vec<u8>,
+    // Outcomes called off (e.g. a cancelled leg); their stakes are refunded at par.
+    pub voided_outcomes: BTreeSet<u32>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct BetMessage {
+    pub correlation_id: [u8; 16],
     pub market_id: MarketId,
     pub user: AccountOwner,
     pub outcome_index: u32,
     pub amount: Amount,
+    pub nonce: u64,
+    // Height of the sending chain's block that placed the bet.
+    pub block_height: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum ConwayBetsMessage {
-    Initialize,
+    Initialize {
+        correlation_id: [u8; 16],
+    },
     Bet(BetMessage),
+    // Bets for the same market chain placed in one block, applied in order.
+    BetBatch(Vec<BetMessage>),
     SyncState {
+        correlation_id: [u8; 16],
         market_id: MarketId,
         state_hash: [u8; 32],
         block_height: u64,
     },
+    // Sent by the betting chain once the block carrying bet `nonce` is buried
+    // under its current `block_height`.
+    Confirm {
+        correlation_id: [u8; 16],
+        nonce: u64,
+        block_height: u64,
+    },
+    // Asks the market chain to drop bet `nonce` if it's still pending.
+    CancelBet {
+        correlation_id: [u8; 16],
+        nonce: u64,
+    },
+    // The market chain's replies to the betting chain: bet `nonce` was
+    // applied, or was dropped before it could be.
+    BetFinalized {
+        correlation_id: [u8; 16],
+        nonce: u64,
+    },
+    BetCancelled {
+        correlation_id: [u8; 16],
+        nonce: u64,
+    },
+    // Bet `nonce` failed on the market chain. A retriable one can go through
+    // once its stake is topped up.
+    BetRejected {
+        correlation_id: [u8; 16],
+        nonce: u64,
+        reason: String,
+        retriable: bool,
+    },
+}
+
+impl ConwayBetsMessage {
+    /// Id shared by a message's send and receive logs and any dead letter it causes.
+    pub fn correlation_id(&self) -> [u8; 16] {
+        match self {
+            ConwayBetsMessage::Initialize { correlation_id }
+            | ConwayBetsMessage::SyncState { correlation_id, .. }
+            | ConwayBetsMessage::Confirm { correlation_id, .. }
+            | ConwayBetsMessage::CancelBet { correlation_id, .. }
+            | ConwayBetsMessage::BetFinalized { correlation_id, .. }
+            | ConwayBetsMessage::BetCancelled { correlation_id, .. }
+            | ConwayBetsMessage::BetRejected { correlation_id, .. } => *correlation_id,
+            ConwayBetsMessage::Bet(bet) => bet.correlation_id,
+            ConwayBetsMessage::BetBatch(bets) => bets.first().map_or([0; 16], |bet| bet.correlation_id),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -53,6 +464,19 @@ pub enum Status {
     Pending,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MarketStatus {
+    // Waiting for the house subsidy to reach `min_pool_to_open`.
+    Funding,
+    Open,
+    // Resolution challenged; claims wait for the owner's ruling.
+    Disputed,
+    // Split into binary sub-markets, which now hold its positions.
+    Frozen,
+    // Created under `Config::require_market_approval`; no bets until a moderator approves.
+    PendingApproval,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Receipt {
     pub id: u64,
@@ -71,6 +495,223 @@ pub struct ConwayBets {
     pub user_positions: BTreeMap<AccountOwner, Vec<UserPosition>>,
     pub next_market_id: u64,
     pub next_bet_id: u64,
+    // Keyed by the full MarketId: `id` alone is only unique per chain.
+    pub remote_markets: BTreeMap<MarketId, RemoteMarketRef>,
+    // Block height of the newest `SyncState` applied per reporting chain and
+    // market; older ones arriving late are ignored. Heights are only ordered
+    // within one chain, so each origin is tracked on its own.
+    pub last_synced_height: BTreeMap<(ChainId, MarketId), u64>,
+    // Incoming cross-chain bets awaiting confirmation, keyed by (origin chain, nonce).
+    pub pending_bets: BTreeMap<(ChainId, u64), PendingBet>,
+    // Bets sent to other chains' markets that haven't been acknowledged yet,
+    // keyed by nonce. Their stakes stay reserved in escrow here until then.
+    pub unacked_bets: BTreeMap<u64, BetMessage>,
+    // Sent bets the market chain rejected as underfunded, keyed by nonce.
+    // Their stakes stay reserved until topped up and retried, or cancelled.
+    pub failed_bets: BTreeMap<u64, BetMessage>,
+    // Distinct bettors per market, derived from `user_positions`.
+    pub market_bettors: BTreeMap<MarketId, BTreeSet<AccountOwner>>,
+    pub next_message_seq: u64,
+    // Received messages that failed to apply.
+    pub dead_letters: Vec<DeadLetter>,
+    // Funds the application holds on behalf of bettors.
+    pub escrow_balance: Amount,
+    // In-app token balances; stakes are drawn from here before the caller's account.
+    pub balances: BTreeMap<AccountOwner, Amount>,
+    // Timestamp of the block that last moved each balance.
+    pub balance_updated_at: BTreeMap<AccountOwner, u64>,
+    // Total each user has ever staked, fees included.
+    pub lifetime_volume: BTreeMap<AccountOwner, Amount>,
+    // Fees collected from bets, held in escrow.
+    pub treasury: Amount,
+    // Funds for early-bettor bonuses, held in escrow until paid out.
+    pub reward_pool: Amount,
+    // Each fee collected with its timestamp, oldest first, capped at `Config::max_fee_log_entries`.
+    pub fee_log: VecDeque<(u64, Amount)>,
+    // Recent bets across all markets, oldest first, capped at `Config::max_bet_feed_entries`.
+    pub bet_feed: VecDeque<FeedBet>,
+    // Market lifecycle events, oldest first, capped at `Config::max_events`.
+    // Each is stored under its own `event_key`, so the log doesn't grow the
+    // state: the contract holds only those logged since it loaded, the
+    // service those it read back.
+    #[serde(skip)]
+    pub events: VecDeque<LoggedEvent>,
+    pub next_event_seq: u64,
+    // Bets placed against a commitment, keyed by it; see `privacy`.
+    pub committed_stakes: BTreeMap<[u8; 32], CommittedStake>,
+    // Nullifiers of committed bets already claimed.
+    pub spent_nullifiers: BTreeSet<[u8; 32]>,
+    // Local markets another chain last reported a different state hash for.
+    pub divergences: BTreeMap<MarketId, DivergenceReport>,
+    // Winnings released early to each user from markets still in their
+    // dispute window or disputed; deducted from their final claim.
+    pub interim_payouts: BTreeMap<(AccountOwner, MarketId), Amount>,
+    // Markets each user has bookmarked.
+    pub watchlist: BTreeMap<AccountOwner, BTreeSet<MarketId>>,
+    // Markets each account created, in creation order; cancelled ones are
+    // dropped. Derived from `markets`, so it isn't stored: loading rebuilds it.
+    #[serde(skip)]
+    pub creator_markets: BTreeMap<AccountOwner, Vec<MarketId>>,
+    // Track record of each account that has resolved a market.
+    pub resolver_reputations: BTreeMap<AccountOwner, ReputationStats>,
+    // How long after `end_time` markets got resolved, by creator and by category.
+    pub creator_resolution_delays: BTreeMap<AccountOwner, DelayStats>,
+    pub category_resolution_delays: BTreeMap<String, DelayStats>,
+    // Markets created so far in the current block.
+    pub block_creations: BlockCounter,
+    pub config: Config,
+    // The last `OPERATION_LOG_LIMIT` applied operations with their sequence
+    // number, block and signer, for replay. Stored under `OPERATION_LOG_KEY`
+    // so the state encoding is the same with and without the feature.
+    #[cfg(feature = "debug-log")]
+    #[serde(skip)]
+    pub operation_log: Vec<(u64, BlockContext, Option<AccountOwner>, Operation)>,
+    #[serde(skip)]
+    pub block: BlockContext,
+    // Bets for remote markets placed since the last flush, by destination chain.
+    #[serde(skip)]
+    pub outgoing_bets: BTreeMap<ChainId, Vec<BetMessage>>,
+    // Messages waiting for the contract to send them, in the order queued.
+    #[serde(skip)]
+    pub outbox: Vec<(ChainId, ConwayBetsMessage)>,
+}
+
+/// Application-wide settings, provided at instantiation.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Account allowed to run administrative operations; none when unset.
+    pub owner: Option<AccountOwner>,
+    // Upper bound on markets/positions a single operation may touch.
+    pub max_work_per_operation: u64,
+    pub max_markets_per_block: u64,
+    // Odds snapshots kept per market; the oldest is dropped past this.
+    pub max_price_observations: usize,
+    // Fee taken from each stake, before any volume discount.
+    pub fee_basis_points: u16,
+    // Discounted fees for bettors whose lifetime volume reaches each tier.
+    pub volume_fee_tiers: Vec<FeeTier>,
+    // What happens to a market whose resolution is overturned.
+    pub post_dispute: PostDisputeAction,
+    pub max_fee_log_entries: usize,
+    pub max_bet_feed_entries: usize,
+    pub max_events: usize,
+    // Outcomes that must stay open to bets; suspensions stop short of this.
+    pub min_live_outcomes: usize,
+    // How long a cross-chain bet may wait for confirmation; forever when unset.
+    pub pending_bet_ttl_secs: Option<u64>,
+    // Grace past `end_time` for cross-chain bets, whose sending block may have
+    // been timestamped slightly behind this chain's clock.
+    pub clock_skew_tolerance_secs: u64,
+    // Time for a market's trending score to halve without new bets.
+    pub trending_half_life_secs: u64,
+    // Outcomes with fewer shares than this show no odds; too thin to mean anything.
+    pub min_liquidity_for_odds: Amount,
+    // New markets wait for an owner or moderator to approve them before taking bets.
+    pub require_market_approval: bool,
+    pub moderators: BTreeSet<AccountOwner>,
+    // Accounts allowed to bulk-create markets from an external feed.
+    pub feeders: BTreeSet<AccountOwner>,
+    // Locked from the creator while a market awaits approval.
+    pub creation_deposit: Amount,
+    // In-app balances below this are swept by `SweepDust` once they've sat
+    // untouched for `dust_idle_secs`; zero sweeps none.
+    pub dust_threshold: Amount,
+    pub dust_idle_secs: u64,
+    // Share of winnings a claim releases while the resolution can still be
+    // challenged; the rest follows once claims open. Zero makes such claims fail.
+    // Opt-in: it takes effect only with `post_dispute` set to `Cancel`, since a
+    // reopened market could be re-resolved against the claimant. Never pays
+    // beyond the claimant's stake, so the refund at par still covers it.
+    pub interim_payout_bps: u16,
+    // Each market's first `early_liquidity_cap` of stakes earns a bonus of
+    // `early_bonus_bps` from `ConwayBets::reward_pool` once it resolves.
+    pub early_liquidity_cap: Amount,
+    pub early_bonus_bps: u16,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PostDisputeAction {
+    // Back to unresolved, so the resolver can submit the correct outcome.
+    #[default]
+    ReopenForResolution,
+    Cancel,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FeeTier {
+    pub min_volume: Amount,
+    pub fee_basis_points: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            owner: None,
+            max_work_per_operation: 10_000,
+            max_markets_per_block: 100,
+            max_price_observations: 1_000,
+            fee_basis_points: 0,
+            volume_fee_tiers: Vec::new(),
+            post_dispute: PostDisputeAction::default(),
+            max_fee_log_entries: 10_000,
+            max_bet_feed_entries: 1_000,
+            max_events: 10_000,
+            min_live_outcomes: 2,
+            pending_bet_ttl_secs: None,
+            clock_skew_tolerance_secs: 0,
+            trending_half_life_secs: 3_600,
+            min_liquidity_for_odds: Amount::ZERO,
+            require_market_approval: false,
+            moderators: BTreeSet::new(),
+            feeders: BTreeSet::new(),
+            creation_deposit: Amount::ZERO,
+            dust_threshold: Amount::ZERO,
+            dust_idle_secs: 90 * 24 * 3_600,
+            interim_payout_bps: 0,
+            early_liquidity_cap: Amount::ZERO,
+            early_bonus_bps: 0,
+        }
+    }
+}
+
+/// Counts the markets and positions an operation touches against a limit.
+pub struct WorkMeter {
+    used: u64,
+    limit: u64,
+}
+
+impl WorkMeter {
+    pub fn new(limit: u64) -> Self {
+        Self { used: 0, limit }
+    }
+
+    pub fn charge(&mut self, units: u64) -> Result<(), ConwayBetsError> {
+        self.used = self.used.saturating_add(units);
+        if self.used > self.limit {
+            return Err(ConwayBetsError::WorkLimitExceeded { limit: self.limit });
+        }
+        Ok(())
+    }
+}
+
+/// Per-block values supplied by the runtime before each operation or message.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct BlockContext {
+    pub timestamp: u64, // Unix timestamp
+    pub block_height: u64,
+    // Chain executing the block; new markets are created on it.
+    pub chain_id: ChainId,
+}
+
+impl Default for BlockContext {
+    fn default() -> Self {
+        Self {
+            timestamp: 0,
+            block_height: 0,
+            chain_id: ChainId([0; 4].into()),
+        }
+    }
 }
 
 // --------------------------------
@@ -82,121 +723,6792 @@ pub struct Market {
     pub title: String,
     pub description: String,
     pub end_time: u64, // Unix timestamp
+    // Append-only: positions and messages refer to outcomes by index, so an
+    // entry is never removed or reordered. Suspension only flags it.
     pub outcomes: Vec<String>,
     pub total_liquidity: Amount,
     pub is_resolved: bool,
+    // Set when a single outcome won; `winning_outcomes` covers any-of resolutions too.
     pub winning_outcome: Option<u32>,
+    pub winning_outcomes: BTreeSet<u32>,
+    pub voided_outcomes: BTreeSet<u32>,
+    // Commits to the market's fields and `positions_root`; see `compute_state_hash`.
     pub state_hash: [u8; 32],
+    // Merkle root of the market's positions, which `position_proof`s verify against.
+    pub positions_root: [u8; 32],
+    // Block depth a cross-chain bet needs on its origin chain before it counts.
+    pub min_confirmations: u64,
+    // Account allowed to resolve; the creator when unset.
+    pub resolver: Option<AccountOwner>,
+    // Set on the first bet, after which the resolver is fixed.
+    pub resolver_locked: bool,
+    pub outcome_edit_log: Vec<OutcomeEdit>,
+    pub resolver_delegations: Vec<ResolverDelegation>,
+    pub fallback_oracle: Option<AccountOwner>,
+    pub fallback_after_secs: u64,
+    pub resolved_at: Option<u64>,
+    // Who submitted the standing resolution.
+    pub resolved_by: Option<AccountOwner>,
+    // Current liquidity parameter, and the value it started from.
+    pub lmsr_b: Option<Amount>,
+    pub lmsr_b_base: Option<Amount>,
+    pub lmsr_b_growth_bps: u32,
+    // Shares outstanding per outcome.
+    pub outcome_shares: Vec<Amount>,
+    // Stakes per outcome; sums to `total_liquidity`.
+    pub outcome_pools: Vec<Amount>,
+    pub category: Option<String>,
+    pub dispute_window_secs: u64,
+    pub min_bettors_to_resolve: Option<u32>,
+    // Called off before resolution; every stake is refunded.
+    pub is_cancelled: bool,
+    // Outcomes closed to new bets, still indexed and resolvable as before.
+    pub suspended_outcomes: BTreeSet<u32>,
+    // Winnings and refunds already collected out of `total_liquidity`.
+    pub paid_out: Amount,
+    pub status: MarketStatus,
+    // House funds backing payouts, held apart from `total_liquidity`.
+    pub subsidy: Amount,
+    pub min_pool_to_open: Amount,
+    pub external_event_id: Option<String>,
+    pub created_at: u64,
+    pub first_bet_delay_secs: u64,
+    // Odds after each bet, oldest first, capped at `Config::max_price_observations`.
+    pub price_observations: VecDeque<PriceObservation>,
+    pub scalar_range: Option<ScalarRange>,
+    // Raw value a scalar market was resolved with, kept for transparency.
+    pub resolved_value: Option<i64>,
+    // Set on a market cancelled by merging it into another; its positions live there now.
+    pub merged_into: Option<MarketId>,
+    pub merged_from: Vec<MarketId>,
+    // Bets seen, in millionths, decayed as of `last_activity`; see `trending_score`.
+    pub activity_score: u64,
+    pub last_activity: u64,
+    pub depends_on: Option<MarketId>,
+    // Held from the creator until a moderator rules on the market.
+    pub creation_deposit: Amount,
+    // Whether the rounding dust left by its payouts has been swept.
+    pub dust_swept: bool,
+    pub creator_fee_bps: u16,
+    // Creator fee paid out of the pot, once claims open.
+    pub creator_fees: Amount,
+    pub min_bet: Amount,
 }
 
-impl Market {
-    pub fn new(chain_id: ChainId) -> MarketId {
-        MarketId { chain_id, id: 0 } 
-    }
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct UserPosition {
+/// A bet as it appears in the platform-wide feed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeedBet {
+    // Position in the feed, increasing by one per bet; the paging cursor.
+    pub seq: u64,
     pub market_id: MarketId,
+    pub user: AccountOwner,
     pub outcome_index: u32,
     pub amount: Amount,
-    pub state_hash: [u8; 32],
+    pub placed_at: u64,
 }
 
-impl ConwayBets {
-    // Helper to access chain_id
-    fn context(&self) -> ContextStub {
-        ContextStub { chain_id: ChainId([0; 4].into()) }
+/// A step in a market's life, logged for indexers to tail.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConwayBetsEvent {
+    MarketCreated {
+        market_id: MarketId,
+        creator: AccountOwner,
+    },
+    BetPlaced {
+        market_id: MarketId,
+        user: AccountOwner,
+        outcome_index: u32,
+        amount: Amount,
+    },
+    MarketResolved {
+        market_id: MarketId,
+        winning_outcomes: BTreeSet<u32>,
+    },
+    WinningsClaimed {
+        market_id: MarketId,
+        user: AccountOwner,
+        amount: Amount,
+    },
+    MarketCancelled {
+        market_id: MarketId,
+    },
+}
+
+/// Which step of a market's life a `ConwayBetsEvent` records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enum)]
+pub enum EventKind {
+    MarketCreated,
+    BetPlaced,
+    MarketResolved,
+    WinningsClaimed,
+    MarketCancelled,
+}
+
+impl ConwayBetsEvent {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            ConwayBetsEvent::MarketCreated { .. } => EventKind::MarketCreated,
+            ConwayBetsEvent::BetPlaced { .. } => EventKind::BetPlaced,
+            ConwayBetsEvent::MarketResolved { .. } => EventKind::MarketResolved,
+            ConwayBetsEvent::WinningsClaimed { .. } => EventKind::WinningsClaimed,
+            ConwayBetsEvent::MarketCancelled { .. } => EventKind::MarketCancelled,
+        }
     }
 
-    // Helper to send messages
-    fn send_message(&self, _dest: ChainId, _msg: ConwayBetsMessage) {
-        // Placeholder
+    pub fn market_id(&self) -> MarketId {
+        match self {
+            ConwayBetsEvent::MarketCreated { market_id, .. }
+            | ConwayBetsEvent::BetPlaced { market_id, .. }
+            | ConwayBetsEvent::MarketResolved { market_id, .. }
+            | ConwayBetsEvent::WinningsClaimed { market_id, .. }
+            | ConwayBetsEvent::MarketCancelled { market_id } => *market_id,
+        }
     }
+}
 
-    // Helper to lock funds
-    async fn lock_funds(&self, _user: AccountOwner, _amount: Amount) -> Result<(), Box<dyn Error>> {
-        Ok(())
+/// An event with its position in the log and the block time it happened.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoggedEvent {
+    pub seq: u64,
+    pub timestamp: u64,
+    pub event: ConwayBetsEvent,
+}
+
+/// Implied probabilities at a point in time, in parts per million (BCS has no
+/// floats).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PriceObservation {
+    pub timestamp: u64,
+    pub probabilities_ppm: Vec<u32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ResolverDelegation {
+    pub from: AccountOwner,
+    pub to: AccountOwner,
+    pub delegated_at: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OutcomeEdit {
+    pub outcome_index: u32,
+    pub old_label: String,
+    pub new_label: String,
+    pub edited_at: u64,
+}
+
+impl Market {
+    pub fn new(chain_id: ChainId) -> MarketId {
+        MarketId { chain_id, id: 0 } 
     }
 
-    // Helper to init state
-    async fn initialize_market_state(&self, _market_id: &MarketId) -> [u8; 32] {
-        [0; 32]
+    /// Current odds per outcome: LMSR prices for AMM markets, otherwise each
+    /// outcome's share of the staked shares. Uniform before any bets.
+    pub fn implied_probabilities(&self) -> Vec<f64> {
+        if let Some(b) = self.lmsr_b {
+            return amm::price(&self.outcome_shares, u128::from(b) as f64);
+        }
+        let total: u128 = self.outcome_shares.iter().map(|s| u128::from(*s)).sum();
+        if total == 0 {
+            let count = self.outcome_shares.len();
+            return vec![1.0 / count as f64; count];
+        }
+        self.outcome_shares
+            .iter()
+            .map(|s| u128::from(*s) as f64 / total as f64)
+            .collect()
     }
 
-    pub async fn create_market(
-        &mut self,
-        creator: AccountOwner,
-        title: String,
-        description: String,
-        end_time: u64,
-        outcomes: Vec<String>,
-    ) {
-        self.next_market_id += 1;
-        let market_id = MarketId { 
-            chain_id: self.context().chain_id, 
-            id: self.next_market_id 
+    /// Shares a net stake of `amount` on `outcome_index` buys right now: as
+    /// many as `amount` covers at LMSR cost for AMM markets, otherwise one
+    /// share per atto. Quotes and placed bets both go through here, so they
+    /// always agree to the unit.
+    pub fn fill(&self, outcome_index: u32, amount: Amount) -> Amount {
+        let Some(b) = self.lmsr_b else {
+            return amount;
         };
-        
-        let state_hash = self.initialize_market_state(&market_id).await;
+        amm::shares_for_cost(&self.outcome_shares, outcome_index as usize, amount, u128::from(b) as f64)
+    }
 
-        let market = Market {
-            id: market_id,
-            creator,
-            title,
-            description,
-            end_time,
-            outcomes,
-            // Fix: Use Amount::ZERO instead of Amount::from(0)
-            total_liquidity: Amount::ZERO,
-            is_resolved: false,
-            winning_outcome: None,
-            state_hash,
+    /// Recomputes `b` from the growth schedule. Pools are scaled by the same
+    /// factor so every `q_i / b`, and with it every price, stays put.
+    fn rebalance_liquidity(&mut self) {
+        let (Some(base), Some(old_b)) = (self.lmsr_b_base, self.lmsr_b) else {
+            return;
         };
+        let growth = mul_div(u128::from(self.total_liquidity), u128::from(self.lmsr_b_growth_bps), 10_000);
+        let new_b = u128::from(base).saturating_add(growth);
+        let old_b = u128::from(old_b);
+        if new_b == old_b || old_b == 0 {
+            return;
+        }
+        for shares in &mut self.outcome_shares {
+            *shares = Amount::from_attos(mul_div(u128::from(*shares), new_b, old_b));
+        }
+        self.lmsr_b = Some(Amount::from_attos(new_b));
+    }
 
-        self.markets.insert(market_id, market);
-        self.send_message(market_id.chain_id, ConwayBetsMessage::Initialize);
+    /// Recent betting activity at `now`: each bet counts 1, halving every
+    /// `half_life_secs` since.
+    pub fn trending_score(&self, now: u64, half_life_secs: u64) -> f64 {
+        let elapsed = now.saturating_sub(self.last_activity) as f64;
+        let decay = 0.5f64.powf(elapsed / half_life_secs.max(1) as f64);
+        self.activity_score as f64 / 1_000_000.0 * decay
     }
 
-    pub async fn place_bet(
-        &mut self,
-        market_id: MarketId,
-        user: AccountOwner,
-        outcome_index: u32,
-        amount: Amount,
-    ) -> Result<Receipt, Box<dyn Error>> {
-        let state_hash = self.markets.get(&market_id)
-            .ok_or("MarketNotFound")?
-            .state_hash;
+    fn record_activity(&mut self, now: u64, half_life_secs: u64) {
+        let score = self.trending_score(now, half_life_secs) + 1.0;
+        self.activity_score = (score * 1_000_000.0).round() as u64;
+        self.last_activity = now;
+    }
 
-        self.lock_funds(user, amount).await?;
+    /// Adds a stake to the pool and the shares it fills to the outcome, then
+    /// re-prices. Returns the shares.
+    fn add_stake(&mut self, outcome_index: u32, amount: Amount, now: u64, max_price_observations: usize) -> Amount {
+        let filled = self.fill(outcome_index, amount);
+        self.total_liquidity = self.total_liquidity.saturating_add(amount);
+        if let Some(pool) = self.outcome_pools.get_mut(outcome_index as usize) {
+            *pool = pool.saturating_add(amount);
+        }
+        if let Some(shares) = self.outcome_shares.get_mut(outcome_index as usize) {
+            *shares = shares.saturating_add(filled);
+        }
+        self.rebalance_liquidity();
+        self.record_price(now, max_price_observations);
+        self.resolver_locked = true;
+        filled
+    }
 
-        let bet_message = BetMessage {
-            market_id,
-            user,
-            outcome_index,
-            amount,
-        };
-        self.send_message(market_id.chain_id, ConwayBetsMessage::Bet(bet_message));
+    fn record_price(&mut self, timestamp: u64, capacity: usize) {
+        let probabilities_ppm = self
+            .implied_probabilities()
+            .iter()
+            .map(|p| (p * 1_000_000.0).round() as u32)
+            .collect();
+        self.price_observations.push_back(PriceObservation { timestamp, probabilities_ppm });
+        while self.price_observations.len() > capacity {
+            self.price_observations.pop_front();
+        }
+    }
 
-        let position = UserPosition {
-            market_id,
-            outcome_index,
-            amount,
-            state_hash,
-        };
-        self.user_positions.entry(user).or_insert(Vec::new()).push(position);
+    /// Whether winnings can be collected at `now`: cancelled, or resolved and
+    /// past the dispute window.
+    pub fn claims_open(&self, now: u64) -> bool {
+        if self.status == MarketStatus::Disputed {
+            return false;
+        }
+        if self.is_cancelled {
+            return true;
+        }
+        self.resolved_at
+            .is_some_and(|resolved_at| now >= resolved_at.saturating_add(self.dispute_window_secs))
+    }
 
-        self.next_bet_id += 1;
-        Ok(Receipt::new(self.next_bet_id, Status::Finalized))
+    pub fn is_winning(&self, outcome_index: u32) -> bool {
+        self.is_resolved && self.winning_outcomes.contains(&outcome_index)
+    }
+
+    /// Whether `account` may resolve the market at time `now`: the creator
+    /// and the appointed resolver always can, unless resolution was delegated
+    /// away from the creator; the fallback oracle only once they defaulted.
+    pub fn can_resolve(&self, account: AccountOwner, now: u64) -> bool {
+        if Some(account) == self.resolver || (account == self.creator && self.resolver_delegations.is_empty()) {
+            return true;
+        }
+        self.fallback_oracle == Some(account)
+            && now >= self.end_time.saturating_add(self.fallback_after_secs)
     }
 }
 
-struct ContextStub {
-    chain_id: ChainId,
+/// A count that starts over whenever the block height changes.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct BlockCounter {
+    pub block_height: u64,
+    pub count: u64,
 }
-impl ContextStub {
-    fn chain_id(&self) -> ChainId {
-        self.chain_id
+
+impl BlockCounter {
+    /// Counts one more event in `block_height` unless that would exceed `limit`.
+    fn try_increment(&mut self, block_height: u64, limit: u64) -> bool {
+        if self.block_height != block_height {
+            *self = BlockCounter { block_height, count: 0 };
+        }
+        if self.count >= limit {
+            return false;
+        }
+        self.count += 1;
+        true
+    }
+
+    /// Events still allowed in `block_height`.
+    fn remaining(&self, block_height: u64, limit: u64) -> u64 {
+        if self.block_height != block_height {
+            return limit;
+        }
+        limit.saturating_sub(self.count)
     }
-}
\ No newline at end of file
+}
+
+/// Running total of observed resolution delays, in seconds.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct DelayStats {
+    pub total_secs: u64,
+    pub count: u64,
+}
+
+impl DelayStats {
+    // Fewer samples than this are too noisy to quote as an estimate.
+    const MIN_SAMPLES: u64 = 3;
+
+    fn record(&mut self, delay_secs: u64) {
+        self.total_secs = self.total_secs.saturating_add(delay_secs);
+        self.count += 1;
+    }
+
+    fn mean(&self) -> Option<u64> {
+        (self.count >= Self::MIN_SAMPLES).then(|| self.total_secs / self.count)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub correlation_id: [u8; 16],
+    pub origin: ChainId,
+    pub reason: String,
+    pub received_at: u64,
+}
+
+/// A cross-chain bet held in reserve until its origin block is deep enough.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PendingBet {
+    pub bet: BetMessage,
+    pub received_at: u64,
+}
+
+/// Last known state of a market hosted on another chain.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RemoteMarketRef {
+    pub market_id: MarketId,
+    pub state_hash: [u8; 32],
+    pub block_height: u64,
+}
+
+/// A sync from `reporting_chain` that disagreed with a local market's state.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DivergenceReport {
+    pub market_id: MarketId,
+    pub local_hash: [u8; 32],
+    pub remote_hash: [u8; 32],
+    pub reporting_chain: ChainId,
+    // Height of the reporting chain's block the remote hash was taken at.
+    pub block_height: u64,
+    pub detected_at: u64,
+}
+
+/// How a resolved market's pool is paid out to the winning side.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Settlement {
+    pub market_id: MarketId,
+    pub winning_outcomes: Vec<u32>,
+    pub total_pool: Amount,
+    pub total_paid: Amount,
+    pub fees: Amount,
+    // Rounding remainder left after paying every winner their floored share.
+    pub dust: Amount,
+    pub winners: u32,
+}
+
+/// Escrowed funds compared with everything they are meant to cover.
+#[derive(Clone, Debug, Default, PartialEq, Eq, SimpleObject)]
+pub struct SolvencyReport {
+    pub market_liquidity: Amount,
+    pub treasury: Amount,
+    // Stakes of cross-chain bets still awaiting confirmation.
+    pub reserved: Amount,
+    pub escrowed: Amount,
+    pub surplus: Amount,
+    pub deficit: Amount,
+}
+
+impl SolvencyReport {
+    pub fn is_balanced(&self) -> bool {
+        self.surplus == Amount::ZERO && self.deficit == Amount::ZERO
+    }
+}
+
+/// A user's combined holding on one outcome label across an event's markets.
+#[derive(Clone, Debug, PartialEq, Eq, SimpleObject)]
+pub struct OutcomeExposure {
+    pub label: String,
+    pub staked: Amount,
+    pub shares: Amount,
+    pub markets: u32,
+}
+
+/// A user's holding on one outcome of an unresolved market, marked to the
+/// current odds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpenPosition {
+    pub market_id: MarketId,
+    pub outcome_index: u32,
+    pub label: String,
+    pub staked: Amount,
+    pub shares: Amount,
+    pub implied_probability: f64,
+    // Shares valued at the outcome's implied probability.
+    pub value: Amount,
+}
+
+/// What one outcome winning would pay out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutcomePayoutRow {
+    pub outcome_index: u32,
+    pub label: String,
+    // Shared by the outcome's backers, after refunds and the creator's fee.
+    pub total_payout: Amount,
+    // Payout per unit staked on the outcome; `None` if nobody backed it.
+    pub multiplier: Option<f64>,
+}
+
+/// Narrows `markets_filtered`; unset fields match every market.
+#[derive(Clone, Debug, Default)]
+pub struct MarketFilter {
+    pub creator: Option<AccountOwner>,
+    pub resolved: Option<bool>,
+    pub min_liquidity: Option<Amount>,
+}
+
+/// What applying a `SyncState` message did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enum)]
+pub enum SyncOutcome {
+    // The reported hash matches our copy of a hosted market.
+    InSync,
+    // The reported hash differs from our copy; the market is flagged.
+    Diverged,
+    // A market hosted elsewhere had its mirrored hash refreshed.
+    Updated,
+    // No newer than a sync already applied, so ignored.
+    Stale,
+}
+
+/// How a market prices and settles its outcomes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Enum)]
+pub enum MarketKind {
+    Parimutuel,
+    Amm,
+    Scalar,
+}
+
+/// The outcome of a bet if it were placed now.
+#[derive(Clone, Debug, PartialEq, SimpleObject)]
+pub struct BetQuote {
+    pub fee: Amount,
+    /// What's staked after the fee.
+    pub stake: Amount,
+    pub shares: Amount,
+    /// Stake paid per share.
+    pub average_price: f64,
+}
+
+/// The rules a market runs under, gathered for clients to render.
+#[derive(Clone, Debug, PartialEq, Eq, SimpleObject)]
+pub struct MarketConfig {
+    pub kind: MarketKind,
+    pub fee_basis_points: u16,
+    pub lmsr_b: Option<Amount>,
+    pub min_pool_to_open: Amount,
+    pub min_bet: Amount,
+    pub early_liquidity_cap: Amount,
+    pub early_bonus_bps: u16,
+    pub min_confirmations: u64,
+    pub min_bettors_to_resolve: Option<u32>,
+    // Whoever may resolve now: the appointed resolver, else the creator.
+    pub resolver: AccountOwner,
+    pub fallback_oracle: Option<AccountOwner>,
+    pub fallback_after_secs: u64,
+    pub first_bet_delay_secs: u64,
+    pub dispute_window_secs: u64,
+    pub interim_payout_bps: u16,
+}
+
+/// Rough work an operation would do, for clients budgeting transactions.
+#[derive(Clone, Debug, Default, PartialEq, Eq, SimpleObject)]
+pub struct CostEstimate {
+    pub markets_touched: u64,
+    pub positions_scanned: u64,
+    pub messages_emitted: u64,
+}
+
+impl CostEstimate {
+    pub fn total(&self) -> u64 {
+        self.markets_touched + self.positions_scanned + self.messages_emitted
+    }
+}
+
+/// How a resolver's rulings have held up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, SimpleObject)]
+pub struct ReputationStats {
+    pub markets_resolved: u32,
+    // Resolutions of theirs that bettors challenged.
+    pub disputes_raised: u32,
+    // Challenges that got their resolution overturned.
+    pub disputes_lost: u32,
+}
+
+/// A user's standing across every market they've bet on.
+#[derive(Clone, Debug, Default, PartialEq, Eq, SimpleObject)]
+pub struct Portfolio {
+    /// Staked on markets still taking bets.
+    pub total_staked: Amount,
+    /// Distinct (market, outcome) holdings on those markets.
+    pub open_positions: u32,
+    /// Resolved markets where they backed a winning outcome.
+    pub resolved_wins: u32,
+    /// What claiming every market with claims open would pay them now.
+    pub claimable: Amount,
+}
+
+/// Win/loss runs over a user's resolved positions, in resolution order.
+#[derive(Clone, Debug, Default, PartialEq, Eq, SimpleObject)]
+pub struct StreakStats {
+    pub current_wins: u32,
+    pub current_losses: u32,
+    pub longest_wins: u32,
+    pub longest_losses: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UserPosition {
+    pub market_id: MarketId,
+    pub outcome_index: u32,
+    pub amount: Amount,
+    // Payout units the stake bought, each worth one unit of the pool if the
+    // outcome wins. Credited 1:1 with the stake until markets price outcomes.
+    pub shares: Amount,
+    pub state_hash: [u8; 32],
+    // Pledged elsewhere as collateral; can't be withdrawn while set.
+    pub locked: bool,
+    pub claimed: bool,
+    // Part of `amount` within the market's first `Config::early_liquidity_cap`.
+    pub early_stake: Amount,
+    // Timestamp of the block that placed the bet.
+    pub placed_at: u64,
+}
+
+/// What a `ClaimAll` collected, with each market's own result in request order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BatchClaim {
+    pub total: Amount,
+    pub results: Vec<(MarketId, Result<Amount, ConwayBetsError>)>,
+}
+
+/// A stake whose owner is only known through the secret behind its commitment.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommittedStake {
+    pub market_id: MarketId,
+    pub outcome_index: u32,
+    pub amount: Amount,
+    pub shares: Amount,
+}
+
+/// Winnings a user can collect from one market right now.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClaimablePosition {
+    pub market_id: MarketId,
+    pub user: AccountOwner,
+    pub amount: Amount,
+}
+
+impl ConwayBets {
+    // Queues a message for the contract to send once the block's work is done
+    fn send_message(&mut self, dest: ChainId, msg: ConwayBetsMessage) {
+        log::info!("sending message {:02x?} to {dest}", msg.correlation_id());
+        self.outbox.push((dest, msg));
+    }
+
+    /// Chain-unique id for the next outgoing message: this chain's id
+    /// prefix followed by a sequence number.
+    fn next_correlation_id(&mut self) -> [u8; 16] {
+        self.next_message_seq += 1;
+        let chain_bytes = bcs::to_bytes(&self.block.chain_id).expect("ChainId serializes");
+        let mut correlation_id = [0u8; 16];
+        correlation_id[..8].copy_from_slice(&chain_bytes[..8]);
+        correlation_id[8..].copy_from_slice(&self.next_message_seq.to_be_bytes());
+        correlation_id
+    }
+
+    // Helper to move `amount` from `user`'s balance into escrow
+    async fn lock_funds(&mut self, user: AccountOwner, amount: Amount) -> Result<(), ConwayBetsError> {
+        let balance = self.balances.get(&user).copied().unwrap_or_default();
+        if balance < amount {
+            return Err(ConwayBetsError::InsufficientFunds);
+        }
+        if balance == amount {
+            self.balances.remove(&user);
+        } else {
+            self.balances.insert(user, balance.saturating_sub(amount));
+        }
+        self.balance_updated_at.insert(user, self.block.timestamp);
+        self.escrow_balance = self.escrow_balance.saturating_add(amount);
+        Ok(())
+    }
+
+    // Helper to pay a market's funds out of escrow into `recipient`'s balance
+    async fn unlock_funds(&mut self, market_id: MarketId, recipient: AccountOwner, amount: Amount) {
+        if amount == Amount::ZERO {
+            return;
+        }
+        if let Some(market) = self.markets.get_mut(&market_id) {
+            market.paid_out = market.paid_out.saturating_add(amount);
+        }
+        self.escrow_balance = self.escrow_balance.saturating_sub(amount);
+        self.credit(recipient, amount);
+        self.refresh_state_hash(&market_id);
+    }
+
+    /// Adds `amount` to `user`'s in-app balance out of thin air. Only reachable
+    /// as an operation with the `faucet` feature, signed by the owner.
+    pub fn credit(&mut self, user: AccountOwner, amount: Amount) -> Amount {
+        self.balance_updated_at.insert(user, self.block.timestamp);
+        let balance = self.balances.entry(user).or_insert(Amount::ZERO);
+        *balance = balance.saturating_add(amount);
+        *balance
+    }
+
+    /// Applies an operation submitted to this chain by `signer`, the block's
+    /// authenticated signer. An operation acting for anyone else is refused.
+    /// On error the state may be partially updated, so the caller must reject
+    /// the whole operation rather than keep it.
+    pub async fn execute(
+        &mut self,
+        signer: Option<AccountOwner>,
+        operation: Operation,
+    ) -> Result<(), ConwayBetsError> {
+        operation.check_signer(signer)?;
+        #[cfg(feature = "debug-log")]
+        {
+            let sequence = self.operation_log.last().map_or(1, |(sequence, ..)| sequence + 1);
+            self.operation_log.push((sequence, self.block, signer, operation.clone()));
+            let excess = self.operation_log.len().saturating_sub(OPERATION_LOG_LIMIT);
+            self.operation_log.drain(..excess);
+        }
+        match operation {
+            Operation::CreateMarket { creator, data } => {
+                self.create_market(creator, data).await?;
+            }
+            Operation::PlaceBet { market_id, user, outcome_index, amount } => {
+                self.place_bet(market_id, user, outcome_index, amount).await?;
+            }
+            Operation::ConditionalBet { user, market_id, outcome_index, amount, max_implied_probability_bps } => {
+                let max_implied_probability = f64::from(max_implied_probability_bps) / 10_000.0;
+                self.conditional_bet(user, market_id, outcome_index, amount, max_implied_probability).await?;
+            }
+            Operation::SetResolver { creator, market_id, resolver } => {
+                self.set_resolver(creator, market_id, resolver)?;
+            }
+            Operation::EditOutcome { creator, market_id, outcome_index, label } => {
+                self.edit_outcome(creator, market_id, outcome_index, label)?;
+            }
+            Operation::Reindex => {
+                self.reindex()?;
+            }
+            Operation::LockPosition { user, market_id, outcome_index } => {
+                self.set_position_lock(user, market_id, outcome_index, true)?;
+            }
+            Operation::UnlockPosition { user, market_id, outcome_index } => {
+                self.set_position_lock(user, market_id, outcome_index, false)?;
+            }
+            Operation::WithdrawBet { user, market_id, position_index } => {
+                self.withdraw_bet(user, market_id, position_index).await?;
+            }
+            Operation::ResolveMarket { resolver, resolution } => {
+                self.resolve_market(resolver, resolution)?;
+            }
+            Operation::DelegateResolver { current_resolver, market_id, new_resolver } => {
+                self.delegate_resolver(current_resolver, market_id, new_resolver)?;
+            }
+            Operation::CancelMarket { caller, market_id } => {
+                self.cancel_market(caller, market_id)?;
+            }
+            Operation::SuspendOutcome { caller, market_id, outcome_index } => {
+                self.suspend_outcome(caller, market_id, outcome_index)?;
+            }
+            Operation::ResolveScalar { resolver, market_id, value } => {
+                self.resolve_scalar(resolver, market_id, value)?;
+            }
+            Operation::ResolveMarketAnyOf { resolver, market_id, winning_outcomes } => {
+                self.resolve_market_any_of(resolver, market_id, winning_outcomes)?;
+            }
+            Operation::ClaimWinnings { user, market_id } => {
+                self.claim_winnings(user, market_id).await?;
+            }
+            Operation::FundMarket { creator, market_id, amount } => {
+                self.fund_market(creator, market_id, amount).await?;
+            }
+            Operation::ChallengeResolution { challenger, market_id } => {
+                self.challenge_resolution(challenger, market_id)?;
+            }
+            Operation::RuleOnDispute { arbiter, market_id, overturn } => {
+                self.rule_on_dispute(arbiter, market_id, overturn)?;
+            }
+            Operation::PlaceCommittedBet { user, market_id, outcome_index, amount, commitment } => {
+                self.place_committed_bet(user, market_id, outcome_index, amount, commitment).await?;
+            }
+            Operation::ClaimCommitted { recipient, market_id, secret } => {
+                self.claim_committed(recipient, market_id, secret).await?;
+            }
+            Operation::SplitMarket { creator, market_id } => {
+                self.split_market(creator, market_id).await?;
+            }
+            Operation::MergeMarkets { caller, source, target } => {
+                self.merge_markets(caller, source, target)?;
+            }
+            Operation::TickPending => {
+                self.expire_pending_bets();
+            }
+            Operation::ClaimAll { user, market_ids } => {
+                // Markets that can't be claimed are reported per market, not failed.
+                self.claim_all(user, market_ids).await;
+            }
+            Operation::ApproveMarket { moderator, market_id } => {
+                self.approve_market(moderator, market_id)?;
+            }
+            Operation::RejectMarket { moderator, market_id } => {
+                self.reject_market(moderator, market_id)?;
+            }
+            Operation::SweepDust { caller, destination } => {
+                self.sweep_dust(caller, destination).await?;
+            }
+            Operation::Watch { user, market_id } => {
+                self.watch(user, market_id)?;
+            }
+            Operation::Unwatch { user, market_id } => {
+                self.unwatch(user, market_id);
+            }
+            Operation::CancelPendingBet { user, nonce } => {
+                self.cancel_pending_bet(user, nonce)?;
+            }
+            Operation::TopUpAndRetry { user, nonce, additional } => {
+                self.top_up_and_retry(user, nonce, additional).await?;
+            }
+            Operation::FundRewards { funder, amount } => {
+                self.fund_rewards(funder, amount).await?;
+            }
+            Operation::BulkCreateMarkets { feeder, markets } => {
+                self.bulk_create_markets(feeder, markets).await?;
+            }
+            Operation::Credit { user, amount } => {
+                if !cfg!(feature = "faucet") || signer.is_none() || signer != self.config.owner {
+                    return Err(ConwayBetsError::NotAuthorized);
+                }
+                self.credit(user, amount);
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuilds state by re-applying a captured `operation_log` to a fresh
+    /// instance with `config`, under the blocks it was recorded in. Only
+    /// reproduces the live state while the log still starts at sequence 1.
+    #[cfg(feature = "debug-log")]
+    pub async fn replay(
+        config: &Config,
+        log: &[(u64, BlockContext, Option<AccountOwner>, Operation)],
+    ) -> ConwayBets {
+        let mut state = ConwayBets { config: config.clone(), ..ConwayBets::default() };
+        for (_, block, signer, operation) in log {
+            state.block = *block;
+            // A logged operation that failed was rejected with its block.
+            let _ = state.execute(*signer, operation.clone()).await;
+        }
+        state
+    }
+
+    pub async fn create_market(
+        &mut self,
+        creator: AccountOwner,
+        data: MarketCreationData,
+    ) -> Result<MarketId, ConwayBetsError> {
+        self.insert_market(creator, data, self.config.require_market_approval).await
+    }
+
+    /// Creates every market in `markets` for `feeder`, or none of them if
+    /// any is invalid or they don't all fit in this block's quota. Feed
+    /// markets skip approval.
+    pub async fn bulk_create_markets(
+        &mut self,
+        feeder: AccountOwner,
+        markets: Vec<MarketCreationData>,
+    ) -> Result<Vec<MarketId>, ConwayBetsError> {
+        if !self.config.feeders.contains(&feeder) {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        for data in &markets {
+            self.validate_market(data)?;
+        }
+        let remaining = self.block_creations.remaining(self.block.block_height, self.config.max_markets_per_block);
+        if remaining < markets.len() as u64 {
+            return Err(ConwayBetsError::BlockQuotaExceeded);
+        }
+
+        let mut market_ids = Vec::with_capacity(markets.len());
+        for data in markets {
+            market_ids.push(self.insert_market(feeder, data, false).await?);
+        }
+        Ok(market_ids)
+    }
+
+    async fn insert_market(
+        &mut self,
+        creator: AccountOwner,
+        data: MarketCreationData,
+        pending_approval: bool,
+    ) -> Result<MarketId, ConwayBetsError> {
+        self.validate_market(&data)?;
+        let block_height = self.block.block_height;
+        if !self.block_creations.try_increment(block_height, self.config.max_markets_per_block) {
+            return Err(ConwayBetsError::BlockQuotaExceeded);
+        }
+
+        self.next_market_id += 1;
+        let market_id = MarketId { 
+            chain_id: self.block.chain_id,
+            id: self.next_market_id 
+        };
+        
+        let outcome_count = data.outcomes.len();
+        let creation_deposit = if pending_approval { self.config.creation_deposit } else { Amount::ZERO };
+        self.lock_funds(creator, creation_deposit).await?;
+
+        let market = Market {
+            id: market_id,
+            creator,
+            title: data.title,
+            description: data.description,
+            end_time: data.end_time,
+            outcomes: data.outcomes,
+            // Fix: Use Amount::ZERO instead of Amount::from(0)
+            total_liquidity: Amount::ZERO,
+            is_resolved: false,
+            winning_outcome: None,
+            winning_outcomes: BTreeSet::new(),
+            voided_outcomes: BTreeSet::new(),
+            state_hash: [0; 32],
+            positions_root: [0; 32],
+            min_confirmations: data.min_confirmations,
+            resolver: data.resolver,
+            resolver_locked: false,
+            outcome_edit_log: Vec::new(),
+            resolver_delegations: Vec::new(),
+            fallback_oracle: data.fallback_oracle,
+            fallback_after_secs: data.fallback_after_secs,
+            resolved_at: None,
+            resolved_by: None,
+            lmsr_b: data.lmsr_b,
+            lmsr_b_base: data.lmsr_b,
+            lmsr_b_growth_bps: data.lmsr_b_growth_bps,
+            outcome_shares: vec![Amount::ZERO; outcome_count],
+            outcome_pools: vec![Amount::ZERO; outcome_count],
+            category: data.category,
+            dispute_window_secs: data.dispute_window_secs,
+            min_bettors_to_resolve: data.min_bettors_to_resolve,
+            is_cancelled: false,
+            suspended_outcomes: BTreeSet::new(),
+            paid_out: Amount::ZERO,
+            status: if pending_approval {
+                MarketStatus::PendingApproval
+            } else if data.min_pool_to_open > Amount::ZERO {
+                MarketStatus::Funding
+            } else {
+                MarketStatus::Open
+            },
+            subsidy: Amount::ZERO,
+            min_pool_to_open: data.min_pool_to_open,
+            external_event_id: data.external_event_id,
+            created_at: self.block.timestamp,
+            first_bet_delay_secs: data.first_bet_delay_secs,
+            price_observations: VecDeque::new(),
+            scalar_range: data.scalar_range,
+            resolved_value: None,
+            merged_into: None,
+            merged_from: Vec::new(),
+            activity_score: 0,
+            last_activity: self.block.timestamp,
+            depends_on: data.depends_on,
+            creation_deposit,
+            dust_swept: false,
+            creator_fee_bps: data.creator_fee_bps,
+            creator_fees: Amount::ZERO,
+            min_bet: data.min_bet,
+        };
+
+        self.markets.insert(market_id, market);
+        self.creator_markets.entry(creator).or_default().push(market_id);
+        self.record_event(ConwayBetsEvent::MarketCreated { market_id, creator });
+        self.refresh_state_hash(&market_id);
+        let correlation_id = self.next_correlation_id();
+        self.send_message(market_id.chain_id, ConwayBetsMessage::Initialize { correlation_id });
+        Ok(market_id)
+    }
+
+    pub async fn place_bet(
+        &mut self,
+        market_id: MarketId,
+        user: AccountOwner,
+        outcome_index: u32,
+        amount: Amount,
+    ) -> Result<Receipt, ConwayBetsError> {
+        if amount == Amount::ZERO {
+            return Err(ConwayBetsError::BetTooSmall);
+        }
+        if !self.markets.contains_key(&market_id) && self.remote_markets.contains_key(&market_id) {
+            return self.place_remote_bet(market_id, user, outcome_index, amount).await;
+        }
+        let market = self.check_accepts_bets(market_id, outcome_index, 0)?;
+        if amount < market.min_bet {
+            return Err(ConwayBetsError::BetTooSmall);
+        }
+        let (state_hash, liquidity_before) = (market.state_hash, market.total_liquidity);
+
+        self.lock_funds(user, amount).await?;
+        let amount = self.take_fee(user, amount);
+        let early_stake = amount.min(self.config.early_liquidity_cap.saturating_sub(liquidity_before));
+
+        let bet_message = BetMessage {
+            correlation_id: self.next_correlation_id(),
+            market_id,
+            user,
+            outcome_index,
+            amount,
+            nonce: self.next_bet_id + 1,
+            block_height: self.block.block_height,
+        };
+        let shares = self.apply_bet(&bet_message);
+        // Already applied here; only a market hosted elsewhere needs to hear of it.
+        if market_id.chain_id != self.block.chain_id {
+            self.send_message(market_id.chain_id, ConwayBetsMessage::Bet(bet_message));
+        }
+
+        let position = UserPosition {
+            market_id,
+            outcome_index,
+            amount,
+            shares,
+            state_hash,
+            locked: false,
+            claimed: false,
+            early_stake,
+            placed_at: self.block.timestamp,
+        };
+        self.user_positions.entry(user).or_insert(Vec::new()).push(position);
+        self.refresh_state_hash(&market_id);
+
+        self.next_bet_id += 1;
+        Ok(Receipt::new(self.next_bet_id, Status::Finalized))
+    }
+
+    /// Sends a bet to the chain hosting `market_id`, reserving the stake here
+    /// until that chain acknowledges it. The market chain does all the
+    /// validation.
+    async fn place_remote_bet(
+        &mut self,
+        market_id: MarketId,
+        user: AccountOwner,
+        outcome_index: u32,
+        amount: Amount,
+    ) -> Result<Receipt, ConwayBetsError> {
+        self.lock_funds(user, amount).await?;
+        let amount = self.take_fee(user, amount);
+
+        self.next_bet_id += 1;
+        let bet_message = BetMessage {
+            correlation_id: self.next_correlation_id(),
+            market_id,
+            user,
+            outcome_index,
+            amount,
+            nonce: self.next_bet_id,
+            block_height: self.block.block_height,
+        };
+        self.unacked_bets.insert(bet_message.nonce, bet_message.clone());
+        self.outgoing_bets.entry(market_id.chain_id).or_default().push(bet_message);
+        Ok(Receipt::new(self.next_bet_id, Status::Pending))
+    }
+
+    /// Queues the bets buffered for each chain, one message per destination:
+    /// a lone bet goes as is, several as a `BetBatch`.
+    pub fn flush_bet_batches(&mut self) {
+        for (destination, mut bets) in std::mem::take(&mut self.outgoing_bets) {
+            let message = if bets.len() == 1 {
+                ConwayBetsMessage::Bet(bets.remove(0))
+            } else {
+                ConwayBetsMessage::BetBatch(bets)
+            };
+            self.send_message(destination, message);
+        }
+    }
+
+    /// Asks the market chain to drop `user`'s unacknowledged bet `nonce`. The
+    /// reserved stake comes back to their balance once it confirms; a bet
+    /// awaiting a retry is dropped and refunded straight away.
+    pub fn cancel_pending_bet(&mut self, user: AccountOwner, nonce: u64) -> Result<(), ConwayBetsError> {
+        if let Some(failed) = self.failed_bets.get(&nonce) {
+            if failed.user != user {
+                return Err(ConwayBetsError::NotAuthorized);
+            }
+            let amount = failed.amount;
+            self.failed_bets.remove(&nonce);
+            self.escrow_balance = self.escrow_balance.saturating_sub(amount);
+            self.credit(user, amount);
+            return Ok(());
+        }
+        let bet = self.unacked_bets.get(&nonce).ok_or(ConwayBetsError::BetNotPending)?;
+        if bet.user != user {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        let destination = bet.market_id.chain_id;
+        let correlation_id = self.next_correlation_id();
+        self.send_message(destination, ConwayBetsMessage::CancelBet { correlation_id, nonce });
+        Ok(())
+    }
+
+    /// Handles the market chain rejecting bet `nonce`. One a top-up could fix
+    /// keeps its reservation for `top_up_and_retry`; any other is refunded.
+    fn reject_bet(&mut self, nonce: u64, retriable: bool) {
+        if !retriable {
+            self.acknowledge_bet(nonce, true);
+            return;
+        }
+        if let Some(bet) = self.unacked_bets.remove(&nonce) {
+            self.failed_bets.insert(nonce, bet);
+        }
+    }
+
+    /// Reserves `additional` more from `user` for their rejected bet `nonce`
+    /// and sends it to the market chain again with the larger stake.
+    pub async fn top_up_and_retry(
+        &mut self,
+        user: AccountOwner,
+        nonce: u64,
+        additional: Amount,
+    ) -> Result<Receipt, ConwayBetsError> {
+        let failed = self.failed_bets.get(&nonce).ok_or(ConwayBetsError::BetNotRetriable)?;
+        if failed.user != user {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        self.lock_funds(user, additional).await?;
+        let Some(mut bet) = self.failed_bets.remove(&nonce) else {
+            return Err(ConwayBetsError::BetNotRetriable);
+        };
+        bet.amount = bet.amount.saturating_add(additional);
+        bet.correlation_id = self.next_correlation_id();
+        bet.block_height = self.block.block_height;
+        self.unacked_bets.insert(nonce, bet.clone());
+        self.send_message(bet.market_id.chain_id, ConwayBetsMessage::Bet(bet));
+        Ok(Receipt::new(nonce, Status::Pending))
+    }
+
+    /// Clears an acknowledged bet `nonce` from the reservations. A cancelled
+    /// bet's stake returns to the bettor's balance; a finalized one's is now
+    /// held by the market chain.
+    fn acknowledge_bet(&mut self, nonce: u64, cancelled: bool) {
+        let Some(bet) = self.unacked_bets.remove(&nonce) else {
+            return;
+        };
+        self.escrow_balance = self.escrow_balance.saturating_sub(bet.amount);
+        if cancelled {
+            self.credit(bet.user, bet.amount);
+        }
+    }
+
+    fn validate_market(&self, data: &MarketCreationData) -> Result<(), MarketError> {
+        if data.title.trim().is_empty() {
+            return Err(MarketError::EmptyTitle);
+        }
+        if data.outcomes.len() < 2 {
+            return Err(MarketError::TooFewOutcomes);
+        }
+        let mut labels = BTreeSet::new();
+        if let Some(duplicate) = data.outcomes.iter().find(|label| !labels.insert(label.as_str())) {
+            return Err(MarketError::DuplicateOutcome(duplicate.clone()));
+        }
+        if data.end_time <= self.block.timestamp {
+            return Err(MarketError::EndTimeInPast);
+        }
+        if data.creator_fee_bps > 10_000 {
+            return Err(MarketError::CreatorFeeTooHigh);
+        }
+        Ok(())
+    }
+
+    /// Checks that `market_id` takes bets on `outcome_index` right now.
+    /// `skew_secs` extends the deadline for bets stamped on another chain's clock.
+    fn check_accepts_bets(
+        &self,
+        market_id: MarketId,
+        outcome_index: u32,
+        skew_secs: u64,
+    ) -> Result<&Market, ConwayBetsError> {
+        let market = self.markets.get(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        // Operations apply in block order, so a resolution earlier in the same
+        // block already closes the market to this bet.
+        if market.is_resolved {
+            return Err(ConwayBetsError::AlreadyResolved);
+        }
+        if self.block.timestamp >= market.end_time.saturating_add(skew_secs) {
+            return Err(ConwayBetsError::MarketClosed);
+        }
+        if market.is_cancelled {
+            return Err(ConwayBetsError::MarketCancelled);
+        }
+        match market.status {
+            MarketStatus::Funding => return Err(ConwayBetsError::MarketFunding),
+            MarketStatus::PendingApproval => return Err(ConwayBetsError::AwaitingApproval),
+            MarketStatus::Frozen => return Err(ConwayBetsError::MarketFrozen),
+            MarketStatus::Open | MarketStatus::Disputed => {}
+        }
+        if self.block.timestamp < market.created_at.saturating_add(market.first_bet_delay_secs) {
+            return Err(ConwayBetsError::BettingNotOpen);
+        }
+        if outcome_index as usize >= market.outcome_pools.len() {
+            return Err(ConwayBetsError::OutcomeOutOfRange);
+        }
+        if market.suspended_outcomes.contains(&outcome_index) {
+            return Err(ConwayBetsError::OutcomeSuspended);
+        }
+        Ok(market)
+    }
+
+    /// Takes the fee out of a stake and counts it towards `user`'s volume,
+    /// returning what's left to bet.
+    fn take_fee(&mut self, user: AccountOwner, amount: Amount) -> Amount {
+        let fee = self.fee_for(user, amount);
+        self.accrue_fee(fee);
+        let volume = self.lifetime_volume.entry(user).or_insert(Amount::ZERO);
+        *volume = volume.saturating_add(amount);
+        amount.saturating_sub(fee)
+    }
+
+    fn accrue_fee(&mut self, fee: Amount) {
+        if fee == Amount::ZERO {
+            return;
+        }
+        self.treasury = self.treasury.saturating_add(fee);
+        self.fee_log.push_back((self.block.timestamp, fee));
+        while self.fee_log.len() > self.config.max_fee_log_entries {
+            self.fee_log.pop_front();
+        }
+    }
+
+    fn fee_for(&self, user: AccountOwner, amount: Amount) -> Amount {
+        let fee_basis_points = u128::from(self.fee_basis_points_for(user));
+        Amount::from_attos(mul_div(u128::from(amount), fee_basis_points, 10_000))
+    }
+
+    /// What `user` staking `amount` on `outcome_index` would pay and get if
+    /// placed now. Shares come from the same integer fill `place_bet` uses,
+    /// and the price is derived from them, so the quote is exact.
+    pub fn quote_bet(
+        &self,
+        market_id: MarketId,
+        user: AccountOwner,
+        outcome_index: u32,
+        amount: Amount,
+    ) -> Result<BetQuote, ConwayBetsError> {
+        let market = self.check_accepts_bets(market_id, outcome_index, 0)?;
+        if amount < market.min_bet {
+            return Err(ConwayBetsError::BetTooSmall);
+        }
+        let fee = self.fee_for(user, amount);
+        let stake = amount.saturating_sub(fee);
+        let shares = market.fill(outcome_index, stake);
+        let average_price = match u128::from(shares) {
+            0 => 0.0,
+            shares => u128::from(stake) as f64 / shares as f64,
+        };
+        Ok(BetQuote { fee, stake, shares, average_price })
+    }
+
+    /// Installs the instantiation `config` with the protocol fee fixed by the
+    /// application parameters. A config may leave the fee at zero to inherit
+    /// it but not ask for a different one, and no rate may take a whole stake.
+    pub fn configure(&mut self, mut config: Config, fee_bps: u16) -> Result<(), ConwayBetsError> {
+        if config.fee_basis_points != 0 && config.fee_basis_points != fee_bps {
+            return Err(ConwayBetsError::FeeMismatch);
+        }
+        config.fee_basis_points = fee_bps;
+        let tiers = config.volume_fee_tiers.iter().map(|tier| tier.fee_basis_points);
+        if std::iter::once(fee_bps).chain(tiers).any(|bps| bps >= 10_000) {
+            return Err(ConwayBetsError::FeeTooHigh);
+        }
+        self.config = config;
+        Ok(())
+    }
+
+    /// Fee rate for `user`'s next bet: the deepest volume tier their lifetime
+    /// volume so far has reached, else the base rate. Capped at 100%.
+    pub fn fee_basis_points_for(&self, user: AccountOwner) -> u16 {
+        let volume = self.lifetime_volume.get(&user).copied().unwrap_or_default();
+        self.config
+            .volume_fee_tiers
+            .iter()
+            .filter(|tier| volume >= tier.min_volume)
+            .max_by_key(|tier| tier.min_volume)
+            .map_or(self.config.fee_basis_points, |tier| tier.fee_basis_points)
+            .min(10_000)
+    }
+
+    /// A market's pricing, fees, limits, oracle and windows in one place.
+    pub fn market_config(&self, market_id: &MarketId) -> Option<MarketConfig> {
+        let market = self.markets.get(market_id)?;
+        let kind = if market.scalar_range.is_some() {
+            MarketKind::Scalar
+        } else if market.lmsr_b.is_some() {
+            MarketKind::Amm
+        } else {
+            MarketKind::Parimutuel
+        };
+        Some(MarketConfig {
+            kind,
+            fee_basis_points: self.config.fee_basis_points.min(10_000),
+            lmsr_b: market.lmsr_b,
+            min_pool_to_open: market.min_pool_to_open,
+            min_bet: market.min_bet,
+            early_liquidity_cap: self.config.early_liquidity_cap,
+            early_bonus_bps: self.config.early_bonus_bps,
+            min_confirmations: market.min_confirmations,
+            min_bettors_to_resolve: market.min_bettors_to_resolve,
+            resolver: market.resolver.unwrap_or(market.creator),
+            fallback_oracle: market.fallback_oracle,
+            fallback_after_secs: market.fallback_after_secs,
+            first_bet_delay_secs: market.first_bet_delay_secs,
+            dispute_window_secs: market.dispute_window_secs,
+            interim_payout_bps: self.interim_payout_bps(),
+        })
+    }
+
+    /// Places a bet only if the outcome's implied probability is at most
+    /// `max_implied_probability`; otherwise nothing is locked.
+    pub async fn conditional_bet(
+        &mut self,
+        user: AccountOwner,
+        market_id: MarketId,
+        outcome_index: u32,
+        amount: Amount,
+        max_implied_probability: f64,
+    ) -> Result<Receipt, ConwayBetsError> {
+        let market = self.markets.get(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        let probability = *market
+            .implied_probabilities()
+            .get(outcome_index as usize)
+            .ok_or(ConwayBetsError::OutcomeOutOfRange)?;
+        if probability > max_implied_probability {
+            return Err(ConwayBetsError::ConditionNotMet);
+        }
+        self.place_bet(market_id, user, outcome_index, amount).await
+    }
+
+    /// Settles a market on `resolution.winning_outcome`. Returns `Ok(false)`
+    /// if the market was already resolved.
+    pub fn resolve_market(
+        &mut self,
+        resolver: AccountOwner,
+        resolution: ResolutionData,
+    ) -> Result<bool, ConwayBetsError> {
+        let winning_outcomes = BTreeSet::from([resolution.winning_outcome]);
+        self.settle(resolver, resolution.market_id, winning_outcomes, resolution.voided_outcomes)
+    }
+
+    /// Resolves a scalar market from the oracle's raw reading, picking the
+    /// winning bucket here rather than trusting the oracle to.
+    pub fn resolve_scalar(
+        &mut self,
+        resolver: AccountOwner,
+        market_id: MarketId,
+        value: i64,
+    ) -> Result<bool, ConwayBetsError> {
+        let market = self.markets.get(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        let range = market.scalar_range.ok_or(ConwayBetsError::NotScalarMarket)?;
+        let bucket = range.bucket(value, market.outcomes.len());
+        let settled = self.settle(resolver, market_id, BTreeSet::from([bucket]), BTreeSet::new())?;
+        if settled {
+            if let Some(market) = self.markets.get_mut(&market_id) {
+                market.resolved_value = Some(value);
+            }
+        }
+        Ok(settled)
+    }
+
+    /// Resolves a market to several outcomes at once: stakes on any of them
+    /// win and split the whole pool pro-rata, regardless of which one they backed.
+    pub fn resolve_market_any_of(
+        &mut self,
+        resolver: AccountOwner,
+        market_id: MarketId,
+        winning_outcomes: BTreeSet<u32>,
+    ) -> Result<bool, ConwayBetsError> {
+        self.settle(resolver, market_id, winning_outcomes, BTreeSet::new())
+    }
+
+    fn settle(
+        &mut self,
+        resolver: AccountOwner,
+        market_id: MarketId,
+        winning_outcomes: BTreeSet<u32>,
+        voided_outcomes: BTreeSet<u32>,
+    ) -> Result<bool, ConwayBetsError> {
+        let now = self.block.timestamp;
+        let market = self.markets.get_mut(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        if market.is_resolved {
+            return Ok(false);
+        }
+        if market.is_cancelled {
+            return Err(ConwayBetsError::MarketCancelled);
+        }
+        if market.status == MarketStatus::Frozen {
+            return Err(ConwayBetsError::MarketFrozen);
+        }
+        if !market.can_resolve(resolver, now) {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        if let Some(required) = market.min_bettors_to_resolve {
+            let actual = self.market_bettors.get(&market_id).map_or(0, |b| b.len() as u32);
+            if actual < required {
+                return Err(ConwayBetsError::NotEnoughBettors { required, actual });
+            }
+        }
+        let last = *winning_outcomes.last().ok_or(ConwayBetsError::EmptyResolution)?;
+        let outcome_count = market.outcomes.len();
+        if last as usize >= outcome_count || voided_outcomes.iter().any(|i| *i as usize >= outcome_count) {
+            return Err(ConwayBetsError::OutcomeOutOfRange);
+        }
+        if !winning_outcomes.is_disjoint(&voided_outcomes) {
+            return Err(ConwayBetsError::VoidedWinner);
+        }
+        market.is_resolved = true;
+        market.winning_outcome = match winning_outcomes.len() {
+            1 => Some(last),
+            _ => None,
+        };
+        market.winning_outcomes = winning_outcomes.clone();
+        market.voided_outcomes = voided_outcomes;
+        market.resolved_at = Some(now);
+        market.resolved_by = Some(resolver);
+        self.resolver_reputations.entry(resolver).or_default().markets_resolved += 1;
+
+        let delay = now.saturating_sub(market.end_time);
+        self.creator_resolution_delays.entry(market.creator).or_default().record(delay);
+        if let Some(category) = &market.category {
+            self.category_resolution_delays.entry(category.clone()).or_default().record(delay);
+        }
+        self.return_creator_funds(market_id);
+        self.record_event(ConwayBetsEvent::MarketResolved { market_id, winning_outcomes });
+        self.refresh_state_hash(&market_id);
+        Ok(true)
+    }
+
+    /// Applies a message received from `origin`. Failures are kept as dead
+    /// letters under the message's correlation id rather than failing the block.
+    pub fn execute_message(&mut self, origin: ChainId, message: ConwayBetsMessage) {
+        let correlation_id = message.correlation_id();
+        log::info!("received message {correlation_id:02x?} from {origin}");
+        match message {
+            ConwayBetsMessage::Bet(bet) => {
+                let nonce = bet.nonce;
+                if let Err(error) = self.receive_bet(origin, bet) {
+                    self.record_failure(correlation_id, origin, &error);
+                    self.send_bet_rejected(origin, nonce, &error);
+                }
+            }
+            ConwayBetsMessage::BetBatch(bets) => {
+                self.receive_bet_batch(origin, bets);
+            }
+            ConwayBetsMessage::Confirm { nonce, block_height, .. } => {
+                if self.confirm_bet(origin, nonce, block_height).is_none() {
+                    self.record_failure(correlation_id, origin, &ConwayBetsError::BetNotPending);
+                }
+            }
+            ConwayBetsMessage::CancelBet { nonce, .. } => {
+                if let Err(error) = self.cancel_received_bet(origin, nonce) {
+                    self.record_failure(correlation_id, origin, &error);
+                }
+            }
+            ConwayBetsMessage::BetFinalized { nonce, .. } => self.acknowledge_bet(nonce, false),
+            ConwayBetsMessage::BetCancelled { nonce, .. } => self.acknowledge_bet(nonce, true),
+            ConwayBetsMessage::BetRejected { nonce, retriable, .. } => self.reject_bet(nonce, retriable),
+            ConwayBetsMessage::SyncState { market_id, state_hash, block_height, .. } => {
+                self.on_sync(origin, market_id, state_hash, block_height);
+            }
+            ConwayBetsMessage::Initialize { .. } => {}
+        }
+    }
+
+    fn record_failure(&mut self, correlation_id: [u8; 16], origin: ChainId, error: &ConwayBetsError) {
+        log::warn!("message {correlation_id:02x?} from {origin} failed: {error}");
+        self.dead_letters.push(DeadLetter {
+            correlation_id,
+            origin,
+            reason: error.to_string(),
+            received_at: self.block.timestamp,
+        });
+    }
+
+    /// Accepts a bet sent from `origin`, staging it as pending if the market
+    /// requires confirmations.
+    pub fn receive_bet(&mut self, origin: ChainId, bet: BetMessage) -> Result<Status, ConwayBetsError> {
+        let skew_secs = self.config.clock_skew_tolerance_secs;
+        let market = self.check_accepts_bets(bet.market_id, bet.outcome_index, skew_secs)?;
+        let (min_bet, min_confirmations) = (market.min_bet, market.min_confirmations);
+        // Nothing was reserved for it, e.g. another bet drained the balance first.
+        if bet.amount == Amount::ZERO {
+            return Err(ConwayBetsError::InsufficientFunds);
+        }
+        // The betting chain can't see the minimum, so it's only enforced here.
+        if bet.amount < min_bet {
+            return Err(ConwayBetsError::BetTooSmall);
+        }
+        // The stake travels with the message; staged bets hold it in reserve.
+        self.escrow_balance = self.escrow_balance.saturating_add(bet.amount);
+        if min_confirmations > 0 {
+            let received_at = self.block.timestamp;
+            self.pending_bets.insert((origin, bet.nonce), PendingBet { bet, received_at });
+            return Ok(Status::Pending);
+        }
+        self.apply_received_bet(&bet);
+        self.send_bet_finalized(origin, bet.nonce);
+        Ok(Status::Finalized)
+    }
+
+    /// Applies bets from `origin` in order, stopping at the first that fails.
+    /// That one is rejected as a lone bet would be; the rest are rejected
+    /// outright so `origin` refunds them. Returns how many were applied.
+    pub fn receive_bet_batch(&mut self, origin: ChainId, bets: Vec<BetMessage>) -> usize {
+        let total = bets.len();
+        let mut bets = bets.into_iter();
+        let mut applied = 0;
+        for bet in bets.by_ref() {
+            let (correlation_id, nonce) = (bet.correlation_id, bet.nonce);
+            if let Err(error) = self.receive_bet(origin, bet) {
+                log::warn!("batch from {origin} stopped after {applied} of {total} bets");
+                self.record_failure(correlation_id, origin, &error);
+                self.send_bet_rejected(origin, nonce, &error);
+                break;
+            }
+            applied += 1;
+        }
+        for skipped in bets {
+            self.send_bet_rejected(origin, skipped.nonce, &ConwayBetsError::BatchAborted);
+        }
+        applied
+    }
+
+    /// Tells `origin` its bet `nonce` was refused. A bet whose reservation
+    /// raced to nothing may be topped up and retried; one that fails
+    /// validation, e.g. under the market's minimum, is refunded.
+    fn send_bet_rejected(&mut self, origin: ChainId, nonce: u64, error: &ConwayBetsError) {
+        let reply = ConwayBetsMessage::BetRejected {
+            correlation_id: self.next_correlation_id(),
+            nonce,
+            reason: error.to_string(),
+            retriable: *error == ConwayBetsError::InsufficientFunds,
+        };
+        self.send_message(origin, reply);
+    }
+
+    fn send_bet_finalized(&mut self, origin: ChainId, nonce: u64) {
+        let correlation_id = self.next_correlation_id();
+        self.send_message(origin, ConwayBetsMessage::BetFinalized { correlation_id, nonce });
+    }
+
+    /// Drops bet `nonce` from `origin` if it's still pending, releasing its
+    /// reserve, and tells `origin`. A finalized bet can't be cancelled.
+    pub fn cancel_received_bet(&mut self, origin: ChainId, nonce: u64) -> Result<Amount, ConwayBetsError> {
+        let pending = self.pending_bets.remove(&(origin, nonce)).ok_or(ConwayBetsError::BetNotPending)?;
+        self.escrow_balance = self.escrow_balance.saturating_sub(pending.bet.amount);
+        let correlation_id = self.next_correlation_id();
+        self.send_message(origin, ConwayBetsMessage::BetCancelled { correlation_id, nonce });
+        Ok(pending.bet.amount)
+    }
+
+    /// Finalizes a pending bet once `block_height` is deep enough past the
+    /// block that placed it. Returns `None` if no such bet is pending.
+    pub fn confirm_bet(&mut self, origin: ChainId, nonce: u64, block_height: u64) -> Option<Status> {
+        let bet = &self.pending_bets.get(&(origin, nonce))?.bet;
+        let required = self.markets.get(&bet.market_id).map_or(0, |m| m.min_confirmations);
+        if block_height.saturating_sub(bet.block_height) < required {
+            return Some(Status::Pending);
+        }
+        let pending = self.pending_bets.remove(&(origin, nonce))?;
+        self.apply_received_bet(&pending.bet);
+        self.send_bet_finalized(origin, nonce);
+        Some(Status::Finalized)
+    }
+
+    /// Drops pending bets that have waited longer than the configured TTL,
+    /// releasing their reserved stakes and telling each origin chain so it
+    /// refunds the bettor. Each is kept as a dead letter with a timeout
+    /// reason. Returns how many expired.
+    pub fn expire_pending_bets(&mut self) -> usize {
+        let Some(ttl) = self.config.pending_bet_ttl_secs else {
+            return 0;
+        };
+        let now = self.block.timestamp;
+        let expired: Vec<(ChainId, u64)> = self
+            .pending_bets
+            .iter()
+            .filter(|(_, pending)| now.saturating_sub(pending.received_at) > ttl)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in &expired {
+            let Some(pending) = self.pending_bets.remove(key) else {
+                continue;
+            };
+            self.escrow_balance = self.escrow_balance.saturating_sub(pending.bet.amount);
+            let reason = format!("pending bet timed out after {ttl}s");
+            self.dead_letters.push(DeadLetter {
+                correlation_id: pending.bet.correlation_id,
+                origin: key.0,
+                reason: reason.clone(),
+                received_at: pending.received_at,
+            });
+            let reply = ConwayBetsMessage::BetRejected {
+                correlation_id: self.next_correlation_id(),
+                nonce: pending.bet.nonce,
+                reason,
+                retriable: false,
+            };
+            self.send_message(key.0, reply);
+        }
+        expired.len()
+    }
+
+    /// Applies a bet placed on another chain and records the bettor's
+    /// position here, where the market will settle it.
+    fn apply_received_bet(&mut self, bet: &BetMessage) {
+        let Some(market) = self.markets.get(&bet.market_id) else {
+            return;
+        };
+        let (state_hash, liquidity_before) = (market.state_hash, market.total_liquidity);
+        let early_stake = bet.amount.min(self.config.early_liquidity_cap.saturating_sub(liquidity_before));
+        let shares = self.apply_bet(bet);
+        let position = UserPosition {
+            market_id: bet.market_id,
+            outcome_index: bet.outcome_index,
+            amount: bet.amount,
+            shares,
+            state_hash,
+            locked: false,
+            claimed: false,
+            early_stake,
+            placed_at: self.block.timestamp,
+        };
+        self.user_positions.entry(bet.user).or_insert(Vec::new()).push(position);
+        self.refresh_state_hash(&bet.market_id);
+    }
+
+    /// Stakes `bet` on its market, returning the shares it filled.
+    fn apply_bet(&mut self, bet: &BetMessage) -> Amount {
+        let mut shares = Amount::ZERO;
+        if let Some(market) = self.markets.get_mut(&bet.market_id) {
+            let (now, max_price_observations) = (self.block.timestamp, self.config.max_price_observations);
+            shares = market.add_stake(bet.outcome_index, bet.amount, now, max_price_observations);
+            market.record_activity(now, self.config.trending_half_life_secs);
+        }
+        self.market_bettors.entry(bet.market_id).or_default().insert(bet.user);
+        self.bet_feed.push_back(FeedBet {
+            seq: self.bet_feed.back().map_or(1, |last| last.seq + 1),
+            market_id: bet.market_id,
+            user: bet.user,
+            outcome_index: bet.outcome_index,
+            amount: bet.amount,
+            placed_at: self.block.timestamp,
+        });
+        while self.bet_feed.len() > self.config.max_bet_feed_entries {
+            self.bet_feed.pop_front();
+        }
+        self.record_event(ConwayBetsEvent::BetPlaced {
+            market_id: bet.market_id,
+            user: bet.user,
+            outcome_index: bet.outcome_index,
+            amount: bet.amount,
+        });
+        shares
+    }
+
+    fn record_event(&mut self, event: ConwayBetsEvent) {
+        self.next_event_seq += 1;
+        self.events.push_back(LoggedEvent { seq: self.next_event_seq, timestamp: self.block.timestamp, event });
+        let oldest = *self.retained_event_seqs().start();
+        while self.events.front().is_some_and(|e| e.seq < oldest) {
+            self.events.pop_front();
+        }
+    }
+
+    /// Sequence numbers of the last `Config::max_events` events logged,
+    /// whether or not they're in memory; empty before the first.
+    pub fn retained_event_seqs(&self) -> RangeInclusive<u64> {
+        let kept = u64::try_from(self.config.max_events).unwrap_or(u64::MAX);
+        self.next_event_seq.saturating_sub(kept) + 1..=self.next_event_seq
+    }
+
+    /// Logged events with a sequence number above `since`, oldest first.
+    /// Indexers pass the last `seq` they saw; older events may have aged out.
+    pub fn events_since(&self, since: u64) -> Vec<&LoggedEvent> {
+        self.events.iter().filter(|e| e.seq > since).collect()
+    }
+
+    /// Up to `first` bets from the platform-wide feed, oldest first: the
+    /// earliest with a `seq` above `after` when given, else the most recent.
+    /// Bets in the same block share a timestamp, so pages go by `seq`.
+    pub fn global_bet_feed(&self, first: usize, after: Option<u64>) -> Vec<&FeedBet> {
+        match after {
+            Some(after) => self.bet_feed.iter().filter(|b| b.seq > after).take(first).collect(),
+            None => self.bet_feed.iter().skip(self.bet_feed.len().saturating_sub(first)).collect(),
+        }
+    }
+
+    /// Stakes on an outcome against `commitment` rather than `user`'s account.
+    /// `user` still pays, but no position ties them to the stake; whoever
+    /// knows the secret behind the commitment collects its payout.
+    pub async fn place_committed_bet(
+        &mut self,
+        user: AccountOwner,
+        market_id: MarketId,
+        outcome_index: u32,
+        amount: Amount,
+        commitment: [u8; 32],
+    ) -> Result<(), ConwayBetsError> {
+        self.check_accepts_bets(market_id, outcome_index, 0)?;
+        if self.committed_stakes.contains_key(&commitment) {
+            return Err(ConwayBetsError::DuplicateCommitment);
+        }
+
+        self.lock_funds(user, amount).await?;
+        let amount = self.take_fee(user, amount);
+        let (now, max_price_observations) = (self.block.timestamp, self.config.max_price_observations);
+        let mut shares = Amount::ZERO;
+        if let Some(market) = self.markets.get_mut(&market_id) {
+            shares = market.add_stake(outcome_index, amount, now, max_price_observations);
+        }
+        let stake = CommittedStake { market_id, outcome_index, amount, shares };
+        self.committed_stakes.insert(commitment, stake);
+        Ok(())
+    }
+
+    /// Pays a committed bet's refund and winnings to `recipient` once claims
+    /// are open. The secret's nullifier is spent, so a second claim fails.
+    pub async fn claim_committed(
+        &mut self,
+        recipient: AccountOwner,
+        market_id: MarketId,
+        secret: [u8; 32],
+    ) -> Result<Amount, ConwayBetsError> {
+        let nullifier = privacy::nullifier(&secret);
+        if self.spent_nullifiers.contains(&nullifier) {
+            return Err(ConwayBetsError::NullifierSpent);
+        }
+        let commitment = privacy::commitment(&secret);
+        let stake = self.committed_stakes.get(&commitment).ok_or(ConwayBetsError::UnknownCommitment)?;
+        if stake.market_id != market_id {
+            return Err(ConwayBetsError::UnknownCommitment);
+        }
+        let market = self.markets.get(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        if !market.claims_open(self.block.timestamp) {
+            return Err(ConwayBetsError::ClaimsNotOpen);
+        }
+        let split = self.pool_split(market);
+        let holder = Holder::Commitment(commitment);
+        let payout = Amount::from_attos(
+            split.refunds.get(&holder).copied().unwrap_or_default()
+                + split.winnings.get(&holder).copied().unwrap_or_default(),
+        );
+
+        self.spent_nullifiers.insert(nullifier);
+        self.unlock_funds(market_id, recipient, payout).await;
+        log::info!("committed bet on market {} paid {payout} to {recipient}", market_id.id);
+        Ok(payout)
+    }
+
+    /// Tops up a fixed-odds market's subsidy, opening it for bets once the
+    /// subsidy reaches `min_pool_to_open`.
+    pub async fn fund_market(
+        &mut self,
+        creator: AccountOwner,
+        market_id: MarketId,
+        amount: Amount,
+    ) -> Result<MarketStatus, ConwayBetsError> {
+        let market = self.markets.get(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        if market.creator != creator {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        if market.is_resolved || market.is_cancelled {
+            return Err(ConwayBetsError::MarketClosed);
+        }
+
+        self.lock_funds(creator, amount).await?;
+
+        let market = self.markets.get_mut(&market_id).expect("market checked above");
+        market.subsidy = market.subsidy.saturating_add(amount);
+        if market.status == MarketStatus::Funding && market.subsidy >= market.min_pool_to_open {
+            market.status = MarketStatus::Open;
+        }
+        let status = market.status;
+        self.refresh_state_hash(&market_id);
+        Ok(status)
+    }
+
+    fn is_moderator(&self, caller: AccountOwner) -> bool {
+        self.config.owner == Some(caller) || self.config.moderators.contains(&caller)
+    }
+
+    /// Lets a market awaiting approval go live. Its creation deposit becomes
+    /// subsidy, and it opens for bets unless that still falls short of
+    /// `min_pool_to_open`.
+    pub fn approve_market(
+        &mut self,
+        moderator: AccountOwner,
+        market_id: MarketId,
+    ) -> Result<MarketStatus, ConwayBetsError> {
+        if !self.is_moderator(moderator) {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        let market = self.markets.get_mut(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        if market.is_cancelled || market.status != MarketStatus::PendingApproval {
+            return Err(ConwayBetsError::NotPendingApproval);
+        }
+        let deposit = std::mem::replace(&mut market.creation_deposit, Amount::ZERO);
+        market.subsidy = market.subsidy.saturating_add(deposit);
+        market.status = if market.subsidy >= market.min_pool_to_open {
+            MarketStatus::Open
+        } else {
+            MarketStatus::Funding
+        };
+        let status = market.status;
+        self.refresh_state_hash(&market_id);
+        Ok(status)
+    }
+
+    /// Turns down a market awaiting approval: it's cancelled and its creation
+    /// deposit, with any subsidy, goes back to the creator's balance.
+    pub fn reject_market(
+        &mut self,
+        moderator: AccountOwner,
+        market_id: MarketId,
+    ) -> Result<Amount, ConwayBetsError> {
+        if !self.is_moderator(moderator) {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        let market = self.markets.get_mut(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        if market.is_cancelled || market.status != MarketStatus::PendingApproval {
+            return Err(ConwayBetsError::NotPendingApproval);
+        }
+        self.mark_cancelled(market_id);
+        let refund = self.return_creator_funds(market_id);
+        self.refresh_state_hash(&market_id);
+        Ok(refund)
+    }
+
+    /// Hands resolution to `resolver` (or back to the creator with `None`).
+    /// Only allowed before the market takes its first bet.
+    pub fn set_resolver(
+        &mut self,
+        creator: AccountOwner,
+        market_id: MarketId,
+        resolver: Option<AccountOwner>,
+    ) -> Result<(), ConwayBetsError> {
+        let market = self.markets.get_mut(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        if market.creator != creator {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        if market.resolver_locked {
+            return Err(ConwayBetsError::ResolverLocked);
+        }
+        market.resolver = resolver;
+        Ok(())
+    }
+
+    /// Calls a market off so every stake can be reclaimed at par. Open to the
+    /// creator or the current resolver while the market is unresolved.
+    pub fn cancel_market(&mut self, caller: AccountOwner, market_id: MarketId) -> Result<(), ConwayBetsError> {
+        let market = self.markets.get_mut(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        if caller != market.creator && Some(caller) != market.resolver {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        if market.is_resolved {
+            return Err(ConwayBetsError::AlreadyResolved);
+        }
+        if market.is_cancelled {
+            return Err(ConwayBetsError::MarketCancelled);
+        }
+        self.mark_cancelled(market_id);
+        self.return_creator_funds(market_id);
+        self.refresh_state_hash(&market_id);
+        Ok(())
+    }
+
+    /// Flags `market_id` cancelled, drops it from its creator's index and
+    /// records the event. Every way a market gets called off goes through here.
+    fn mark_cancelled(&mut self, market_id: MarketId) {
+        let Some(market) = self.markets.get_mut(&market_id) else {
+            return;
+        };
+        market.is_cancelled = true;
+        let creator = market.creator;
+        if let Some(ids) = self.creator_markets.get_mut(&creator) {
+            ids.retain(|id| *id != market_id);
+            if ids.is_empty() {
+                self.creator_markets.remove(&creator);
+            }
+        }
+        self.record_event(ConwayBetsEvent::MarketCancelled { market_id });
+    }
+
+    /// Refills `creator_markets` from `markets`. Called on every load, since
+    /// the index isn't part of the stored state.
+    pub fn rebuild_creator_index(&mut self) {
+        self.creator_markets.clear();
+        for market in self.markets.values().filter(|m| !m.is_cancelled) {
+            self.creator_markets.entry(market.creator).or_default().push(market.id);
+        }
+    }
+
+    /// Gives the creator back their creation deposit and the subsidy they
+    /// funded the market with, once it's resolved, cancelled or split. Bets
+    /// are never paid out of either.
+    fn return_creator_funds(&mut self, market_id: MarketId) -> Amount {
+        let Some(market) = self.markets.get_mut(&market_id) else {
+            return Amount::ZERO;
+        };
+        let creator = market.creator;
+        let deposit = std::mem::replace(&mut market.creation_deposit, Amount::ZERO);
+        let subsidy = std::mem::replace(&mut market.subsidy, Amount::ZERO);
+        let returned = deposit.saturating_add(subsidy);
+        self.escrow_balance = self.escrow_balance.saturating_sub(returned);
+        self.credit(creator, returned);
+        returned
+    }
+
+    /// Opens a dispute on a resolution still inside its dispute window. Only
+    /// bettors on the market can challenge.
+    pub fn challenge_resolution(
+        &mut self,
+        challenger: AccountOwner,
+        market_id: MarketId,
+    ) -> Result<(), ConwayBetsError> {
+        let now = self.block.timestamp;
+        let market = self.markets.get_mut(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        let is_bettor = self.market_bettors.get(&market_id).is_some_and(|b| b.contains(&challenger));
+        if !is_bettor {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        let in_window = market
+            .resolved_at
+            .is_some_and(|resolved_at| now < resolved_at.saturating_add(market.dispute_window_secs));
+        if !market.is_resolved || !in_window || market.status == MarketStatus::Disputed {
+            return Err(ConwayBetsError::NotDisputable);
+        }
+        market.status = MarketStatus::Disputed;
+        if let Some(resolver) = market.resolved_by {
+            self.resolver_reputations.entry(resolver).or_default().disputes_raised += 1;
+        }
+        Ok(())
+    }
+
+    /// `resolver`'s track record; all zero if they've never resolved a market.
+    pub fn resolver_reputation(&self, resolver: AccountOwner) -> ReputationStats {
+        self.resolver_reputations.get(&resolver).copied().unwrap_or_default()
+    }
+
+    /// Settles an open dispute. Upholding the resolution releases claims as
+    /// before; overturning it reopens or cancels the market per
+    /// `Config::post_dispute`.
+    pub fn rule_on_dispute(
+        &mut self,
+        arbiter: AccountOwner,
+        market_id: MarketId,
+        overturn: bool,
+    ) -> Result<(), ConwayBetsError> {
+        if self.config.owner != Some(arbiter) {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        let market = self.markets.get_mut(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        if market.status != MarketStatus::Disputed {
+            return Err(ConwayBetsError::NotDisputed);
+        }
+        market.status = MarketStatus::Open;
+        if !overturn {
+            return Ok(());
+        }
+        market.is_resolved = false;
+        market.winning_outcome = None;
+        market.winning_outcomes.clear();
+        market.voided_outcomes.clear();
+        market.resolved_at = None;
+        market.resolved_value = None;
+        if let Some(resolver) = market.resolved_by.take() {
+            self.resolver_reputations.entry(resolver).or_default().disputes_lost += 1;
+        }
+        if self.config.post_dispute == PostDisputeAction::Cancel {
+            self.mark_cancelled(market_id);
+        }
+        self.refresh_state_hash(&market_id);
+        Ok(())
+    }
+
+    /// Replaces a market of three or more outcomes with one binary market per
+    /// outcome, "X" vs "Not X", each depending on the original. A stake on
+    /// outcome X moves to "X" in that outcome's binary, along with its
+    /// liquidity, and its holder also backs "Not Y" in every other binary with
+    /// the same shares but no stake. So each binary's pot is what was staked
+    /// on its outcome, and goes to everyone else if that outcome loses.
+    /// Committed stakes move to "X" only. The original is frozen. Returns the
+    /// binaries in outcome order.
+    pub async fn split_market(
+        &mut self,
+        creator: AccountOwner,
+        market_id: MarketId,
+    ) -> Result<Vec<MarketId>, ConwayBetsError> {
+        let parent = self.markets.get(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        if parent.creator != creator {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        if parent.is_resolved {
+            return Err(ConwayBetsError::AlreadyResolved);
+        }
+        if parent.is_cancelled {
+            return Err(ConwayBetsError::MarketCancelled);
+        }
+        if parent.status == MarketStatus::Frozen {
+            return Err(ConwayBetsError::MarketFrozen);
+        }
+        if parent.outcomes.len() <= 2 {
+            return Err(ConwayBetsError::NotSplittable);
+        }
+        let remaining = self.block_creations.remaining(self.block.block_height, self.config.max_markets_per_block);
+        if remaining < parent.outcomes.len() as u64 {
+            return Err(ConwayBetsError::BlockQuotaExceeded);
+        }
+
+        let parent = parent.clone();
+        let mut binaries = Vec::with_capacity(parent.outcomes.len());
+        for label in &parent.outcomes {
+            let data = MarketCreationData {
+                title: format!("{}: {label}", parent.title),
+                description: parent.description.clone(),
+                end_time: parent.end_time,
+                outcomes: vec![label.clone(), format!("Not {label}")],
+                min_confirmations: parent.min_confirmations,
+                fallback_oracle: parent.fallback_oracle,
+                fallback_after_secs: parent.fallback_after_secs,
+                lmsr_b: parent.lmsr_b,
+                category: parent.category.clone(),
+                dispute_window_secs: parent.dispute_window_secs,
+                external_event_id: parent.external_event_id.clone(),
+                depends_on: Some(market_id),
+                ..MarketCreationData::default()
+            };
+            // The parent was already approved; its binaries don't queue again.
+            let binary = self.insert_market(creator, data, false).await?;
+            if let Some(market) = self.markets.get_mut(&binary) {
+                market.resolver = parent.resolver;
+            }
+            binaries.push(binary);
+        }
+
+        for (user, positions) in &mut self.user_positions {
+            let mut opposing = Vec::new();
+            for position in positions.iter_mut().filter(|p| p.market_id == market_id) {
+                let outcome = position.outcome_index as usize;
+                for (_, other) in binaries.iter().enumerate().filter(|(index, _)| *index != outcome) {
+                    opposing.push(UserPosition {
+                        market_id: *other,
+                        outcome_index: 1,
+                        amount: Amount::ZERO,
+                        early_stake: Amount::ZERO,
+                        ..position.clone()
+                    });
+                    if let Some(market) = self.markets.get_mut(other) {
+                        market.outcome_shares[1] = market.outcome_shares[1].saturating_add(position.shares);
+                        market.resolver_locked = true;
+                    }
+                    self.market_bettors.entry(*other).or_default().insert(*user);
+                }
+                let binary = binaries[outcome];
+                position.market_id = binary;
+                position.outcome_index = 0;
+                if let Some(market) = self.markets.get_mut(&binary) {
+                    market.total_liquidity = market.total_liquidity.saturating_add(position.amount);
+                    market.outcome_pools[0] = market.outcome_pools[0].saturating_add(position.amount);
+                    market.outcome_shares[0] = market.outcome_shares[0].saturating_add(position.shares);
+                    market.resolver_locked = true;
+                }
+                self.market_bettors.entry(binary).or_default().insert(*user);
+            }
+            positions.extend(opposing);
+        }
+        for stake in self.committed_stakes.values_mut().filter(|s| s.market_id == market_id) {
+            let binary = binaries[stake.outcome_index as usize];
+            stake.market_id = binary;
+            stake.outcome_index = 0;
+            if let Some(market) = self.markets.get_mut(&binary) {
+                market.total_liquidity = market.total_liquidity.saturating_add(stake.amount);
+                market.outcome_pools[0] = market.outcome_pools[0].saturating_add(stake.amount);
+                market.outcome_shares[0] = market.outcome_shares[0].saturating_add(stake.shares);
+                market.resolver_locked = true;
+            }
+        }
+        for pending in self.pending_bets.values_mut() {
+            if pending.bet.market_id == market_id {
+                pending.bet.market_id = binaries[pending.bet.outcome_index as usize];
+                pending.bet.outcome_index = 0;
+            }
+        }
+        self.market_bettors.remove(&market_id);
+
+        self.return_creator_funds(market_id);
+        let parent = self.markets.get_mut(&market_id).expect("market checked above");
+        parent.status = MarketStatus::Frozen;
+        parent.total_liquidity = Amount::ZERO;
+        parent.outcome_pools = vec![Amount::ZERO; parent.outcomes.len()];
+        parent.outcome_shares = vec![Amount::ZERO; parent.outcomes.len()];
+        self.refresh_state_hash(&market_id);
+        for binary in &binaries {
+            self.refresh_state_hash(binary);
+        }
+        Ok(binaries)
+    }
+
+    /// Moves every position, pending bet and unit of liquidity from `source`
+    /// into `target`, then cancels `source`. Both must be open and list the
+    /// same outcomes in the same order.
+    pub fn merge_markets(
+        &mut self,
+        caller: AccountOwner,
+        source: MarketId,
+        target: MarketId,
+    ) -> Result<(), ConwayBetsError> {
+        if self.config.owner != Some(caller) {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        let from = self.markets.get(&source).ok_or(ConwayBetsError::MarketNotFound)?;
+        let into = self.markets.get(&target).ok_or(ConwayBetsError::MarketNotFound)?;
+        for market in [from, into] {
+            if market.is_resolved {
+                return Err(ConwayBetsError::AlreadyResolved);
+            }
+            if market.is_cancelled {
+                return Err(ConwayBetsError::MarketCancelled);
+            }
+        }
+        if source == target || from.outcomes != into.outcomes {
+            return Err(ConwayBetsError::OutcomesMismatch);
+        }
+
+        let from = self.markets.get_mut(&source).expect("market checked above");
+        let liquidity = std::mem::replace(&mut from.total_liquidity, Amount::ZERO);
+        let subsidy = std::mem::replace(&mut from.subsidy, Amount::ZERO);
+        let shares = std::mem::replace(&mut from.outcome_shares, vec![Amount::ZERO; from.outcomes.len()]);
+        let pools = std::mem::replace(&mut from.outcome_pools, vec![Amount::ZERO; from.outcomes.len()]);
+        from.merged_into = Some(target);
+
+        let into = self.markets.get_mut(&target).expect("market checked above");
+        into.total_liquidity = into.total_liquidity.saturating_add(liquidity);
+        into.subsidy = into.subsidy.saturating_add(subsidy);
+        for (pool, added) in into.outcome_shares.iter_mut().zip(shares) {
+            *pool = pool.saturating_add(added);
+        }
+        for (pool, added) in into.outcome_pools.iter_mut().zip(pools) {
+            *pool = pool.saturating_add(added);
+        }
+        into.merged_from.push(source);
+
+        for position in self.user_positions.values_mut().flatten() {
+            if position.market_id == source {
+                position.market_id = target;
+            }
+        }
+        for pending in self.pending_bets.values_mut() {
+            if pending.bet.market_id == source {
+                pending.bet.market_id = target;
+            }
+        }
+        if let Some(bettors) = self.market_bettors.remove(&source) {
+            self.market_bettors.entry(target).or_default().extend(bettors);
+        }
+        self.mark_cancelled(source);
+        self.refresh_state_hash(&source);
+        self.refresh_state_hash(&target);
+        Ok(())
+    }
+
+    /// Closes one outcome to new bets. The outcome keeps its index and label,
+    /// and existing positions on it settle as usual.
+    pub fn suspend_outcome(
+        &mut self,
+        caller: AccountOwner,
+        market_id: MarketId,
+        outcome_index: u32,
+    ) -> Result<(), ConwayBetsError> {
+        let market = self.markets.get_mut(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        if caller != market.creator && Some(caller) != market.resolver {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        if outcome_index as usize >= market.outcomes.len() {
+            return Err(ConwayBetsError::OutcomeOutOfRange);
+        }
+        let min_live = self.config.min_live_outcomes;
+        let live = market.outcomes.len() - market.suspended_outcomes.len();
+        if !market.suspended_outcomes.contains(&outcome_index) && live <= min_live {
+            return Err(ConwayBetsError::TooManySuspensions { min_live });
+        }
+        market.suspended_outcomes.insert(outcome_index);
+        self.refresh_state_hash(&market_id);
+        Ok(())
+    }
+
+    /// Hands the current resolver's authority to `new_resolver`, who then
+    /// resolves alone. Subject to the same lock as `set_resolver`.
+    pub fn delegate_resolver(
+        &mut self,
+        current_resolver: AccountOwner,
+        market_id: MarketId,
+        new_resolver: AccountOwner,
+    ) -> Result<(), ConwayBetsError> {
+        let delegated_at = self.block.timestamp;
+        let market = self.markets.get_mut(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        if market.resolver.unwrap_or(market.creator) != current_resolver {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        if market.is_resolved {
+            return Err(ConwayBetsError::AlreadyResolved);
+        }
+        if market.resolver_locked {
+            return Err(ConwayBetsError::ResolverLocked);
+        }
+        market.resolver = Some(new_resolver);
+        market.resolver_delegations.push(ResolverDelegation {
+            from: current_resolver,
+            to: new_resolver,
+            delegated_at,
+        });
+        Ok(())
+    }
+
+    /// Relabels an outcome. Labels are frozen once the market has liquidity,
+    /// and every earlier change is kept in the market's `outcome_edit_log`.
+    pub fn edit_outcome(
+        &mut self,
+        creator: AccountOwner,
+        market_id: MarketId,
+        outcome_index: u32,
+        label: String,
+    ) -> Result<(), ConwayBetsError> {
+        let timestamp = self.block.timestamp;
+        let market = self.markets.get_mut(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        if market.creator != creator {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        if market.total_liquidity > Amount::ZERO {
+            return Err(ConwayBetsError::OutcomesFrozen);
+        }
+        let slot = market
+            .outcomes
+            .get_mut(outcome_index as usize)
+            .ok_or(ConwayBetsError::OutcomeOutOfRange)?;
+        let old_label = std::mem::replace(slot, label.clone());
+        market.outcome_edit_log.push(OutcomeEdit {
+            outcome_index,
+            old_label,
+            new_label: label,
+            edited_at: timestamp,
+        });
+        Ok(())
+    }
+
+    /// Summarizes the payout of a resolved market; `None` while unresolved.
+    pub fn settlement_summary(&self, market_id: &MarketId) -> Option<Settlement> {
+        let market = self.markets.get(market_id)?;
+        if !market.is_resolved {
+            return None;
+        }
+        let split = self.pool_split(market);
+        let total_paid: u128 = split.refunds.values().sum::<u128>() + split.winnings.values().sum::<u128>();
+
+        Some(Settlement {
+            market_id: *market_id,
+            winning_outcomes: market.winning_outcomes.iter().copied().collect(),
+            total_pool: market.total_liquidity,
+            total_paid: Amount::from_attos(total_paid),
+            fees: Amount::from_attos(split.fees),
+            dust: Amount::from_attos(split.dust),
+            winners: split.winnings.len() as u32,
+        })
+    }
+
+    /// Rebuilds `market_bettors` from `user_positions`. Leaves the index
+    /// untouched if the scan would exceed the configured work limit.
+    pub fn reindex(&mut self) -> Result<(), ConwayBetsError> {
+        let mut meter = WorkMeter::new(self.config.max_work_per_operation);
+        let mut market_bettors: BTreeMap<MarketId, BTreeSet<AccountOwner>> = BTreeMap::new();
+        for (user, positions) in &self.user_positions {
+            meter.charge(positions.len() as u64)?;
+            for position in positions {
+                market_bettors.entry(position.market_id).or_default().insert(*user);
+            }
+        }
+        self.market_bettors = market_bettors;
+        Ok(())
+    }
+
+    /// Locks or unlocks all of `user`'s positions on one outcome of a market.
+    pub fn set_position_lock(
+        &mut self,
+        user: AccountOwner,
+        market_id: MarketId,
+        outcome_index: u32,
+        locked: bool,
+    ) -> Result<(), ConwayBetsError> {
+        let mut matching = self
+            .user_positions
+            .get_mut(&user)
+            .into_iter()
+            .flatten()
+            .filter(|p| p.market_id == market_id && p.outcome_index == outcome_index)
+            .peekable();
+        if matching.peek().is_none() {
+            return Err(ConwayBetsError::PositionNotFound);
+        }
+        for position in matching {
+            position.locked = locked;
+        }
+        Ok(())
+    }
+
+    /// Pulls an open bet back out of a market before it closes, returning the
+    /// stake to `user`'s balance.
+    pub async fn withdraw_bet(
+        &mut self,
+        user: AccountOwner,
+        market_id: MarketId,
+        position_index: usize,
+    ) -> Result<Amount, ConwayBetsError> {
+        let market = self.markets.get(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        if market.is_resolved {
+            return Err(ConwayBetsError::AlreadyResolved);
+        }
+        if market.is_cancelled {
+            return Err(ConwayBetsError::MarketCancelled);
+        }
+        if self.block.timestamp >= market.end_time {
+            return Err(ConwayBetsError::MarketClosed);
+        }
+
+        let position = self
+            .user_positions
+            .get(&user)
+            .and_then(|positions| positions.get(position_index))
+            .filter(|p| p.market_id == market_id)
+            .ok_or(ConwayBetsError::PositionNotFound)?;
+        if position.claimed {
+            return Err(ConwayBetsError::AlreadyClaimed);
+        }
+        if position.locked {
+            return Err(ConwayBetsError::PositionLocked);
+        }
+        self.check_pool_covers_withdrawal(market, user, position_index, position.amount)?;
+
+        let positions = self.user_positions.get_mut(&user).ok_or(ConwayBetsError::PositionNotFound)?;
+        let position = positions.remove(position_index);
+        if !positions.iter().any(|p| p.market_id == market_id) {
+            if let Some(bettors) = self.market_bettors.get_mut(&market_id) {
+                bettors.remove(&user);
+            }
+        }
+
+        if let Some(market) = self.markets.get_mut(&market_id) {
+            market.total_liquidity = market.total_liquidity.saturating_sub(position.amount);
+            if let Some(pool) = market.outcome_pools.get_mut(position.outcome_index as usize) {
+                *pool = pool.saturating_sub(position.amount);
+            }
+            if let Some(shares) = market.outcome_shares.get_mut(position.outcome_index as usize) {
+                *shares = shares.saturating_sub(position.shares);
+            }
+        }
+        self.escrow_balance = self.escrow_balance.saturating_sub(position.amount);
+        self.credit(user, position.amount);
+        self.refresh_state_hash(&market_id);
+        Ok(position.amount)
+    }
+
+    /// Checks that taking `amount` out of `market` for `user`'s position
+    /// `position_index` leaves enough to cover every other open position,
+    /// committed stakes included: their stakes in a parimutuel market, or for
+    /// an AMM the largest payout any one outcome could owe less the `b * ln(n)`
+    /// loss the market maker absorbs. Bets still waiting on confirmations are
+    /// reserved on top, so the pool isn't left short when they land.
+    fn check_pool_covers_withdrawal(
+        &self,
+        market: &Market,
+        user: AccountOwner,
+        position_index: usize,
+        amount: Amount,
+    ) -> Result<(), ConwayBetsError> {
+        let others = self.user_positions.iter().flat_map(|(owner, positions)| {
+            positions
+                .iter()
+                .enumerate()
+                .filter(move |(i, _)| *owner != user || *i != position_index)
+                .map(|(_, p)| p)
+        });
+        let others = others
+            .filter(|p| p.market_id == market.id && !p.claimed)
+            .map(|p| (p.outcome_index, p.amount, p.shares));
+        let committed = self
+            .committed_stakes
+            .values()
+            .filter(|s| s.market_id == market.id)
+            .map(|s| (s.outcome_index, s.amount, s.shares));
+        let others = others.chain(committed);
+        let staked = match market.lmsr_b {
+            None => others.map(|(_, amount, _)| u128::from(amount)).sum::<u128>(),
+            Some(b) => {
+                let mut payouts = vec![0u128; market.outcomes.len()];
+                for (outcome_index, _, shares) in others {
+                    if let Some(payout) = payouts.get_mut(outcome_index as usize) {
+                        *payout += u128::from(shares);
+                    }
+                }
+                let max_loss = u128::from(b) as f64 * (market.outcomes.len() as f64).ln();
+                payouts.into_iter().max().unwrap_or(0).saturating_sub(max_loss as u128)
+            }
+        };
+        let reserved: u128 = self
+            .pending_bets
+            .values()
+            .filter(|pending| pending.bet.market_id == market.id)
+            .map(|pending| u128::from(pending.bet.amount))
+            .sum();
+        let required = staked + reserved;
+        let pool = market.total_liquidity.saturating_add(market.subsidy);
+        match u128::from(pool).checked_sub(u128::from(market.paid_out) + u128::from(amount)) {
+            Some(left) if left >= required => Ok(()),
+            _ => Err(ConwayBetsError::WouldUnderflowPool),
+        }
+    }
+
+    /// Implied probability at which `user`'s position on an outcome is worth
+    /// exactly what they staked, i.e. their average entry price.
+    pub fn break_even(&self, user: AccountOwner, market_id: MarketId, outcome_index: u32) -> Option<f64> {
+        let (stake, shares) = self
+            .user_positions
+            .get(&user)?
+            .iter()
+            .filter(|p| p.market_id == market_id && p.outcome_index == outcome_index)
+            .fold((0u128, 0u128), |(stake, shares), p| {
+                (stake + u128::from(p.amount), shares + u128::from(p.shares))
+            });
+        if shares == 0 {
+            return None;
+        }
+        Some(stake as f64 / shares as f64)
+    }
+
+    /// For each outcome of a market, whether its winning would pay `user`
+    /// more than everything they've staked on the market, at the current
+    /// pool and stakes.
+    pub fn break_even_outcomes(&self, user: AccountOwner, market_id: MarketId) -> Vec<(u32, bool)> {
+        let Some(market) = self.markets.get(&market_id) else {
+            return Vec::new();
+        };
+        let outcome_count = market.outcomes.len();
+        let mut totals = vec![0u128; outcome_count];
+        let mut own = vec![0u128; outcome_count];
+        let committed = self.committed_stakes.values().map(|s| (None, s.market_id, s.outcome_index, s.amount));
+        let positions = self.user_positions.iter().flat_map(|(holder, positions)| {
+            positions.iter().map(move |p| (Some(*holder), p.market_id, p.outcome_index, p.amount))
+        });
+        for (holder, position_market, outcome_index, amount) in positions.chain(committed) {
+            let outcome = outcome_index as usize;
+            if position_market != market_id || outcome >= outcome_count {
+                continue;
+            }
+            totals[outcome] += u128::from(amount);
+            if holder == Some(user) {
+                own[outcome] += u128::from(amount);
+            }
+        }
+
+        let staked: u128 = own.iter().sum();
+        let pool = u128::from(market.total_liquidity);
+        (0..outcome_count)
+            .map(|outcome| {
+                let payout = if own[outcome] == 0 { 0 } else { mul_div(own[outcome], pool, totals[outcome]) };
+                (outcome as u32, payout > staked)
+            })
+            .collect()
+    }
+
+    /// What `user` is owed from a resolved or cancelled market's pool, refunds
+    /// of voided stakes included; zero while the market is open.
+    pub fn payout(&self, user: AccountOwner, market_id: &MarketId) -> Amount {
+        let Some(market) = self.markets.get(market_id).filter(|m| m.is_resolved || m.is_cancelled) else {
+            return Amount::ZERO;
+        };
+        let split = self.pool_split(market);
+        let holder = Holder::User(user);
+        let refund = split.refunds.get(&holder).copied().unwrap_or_default();
+        let winnings = split.winnings.get(&holder).copied().unwrap_or_default();
+        Amount::from_attos(refund + winnings)
+    }
+
+    /// Collects `user`'s payout from a market whose claims are open and marks
+    /// their positions on it claimed, adding the early-bettor bonus on a
+    /// resolved market. Claiming again yields zero. While a resolved market
+    /// is in its dispute window or disputed, releases only the interim share
+    /// of the payout, and fails if there is none: interim payouts are off
+    /// unless `Config::post_dispute` is `Cancel`. Fails while the market is
+    /// unresolved.
+    pub async fn claim_winnings(
+        &mut self,
+        user: AccountOwner,
+        market_id: MarketId,
+    ) -> Result<Amount, ConwayBetsError> {
+        let market = self.markets.get(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        if !market.claims_open(self.block.timestamp) {
+            if !market.is_resolved || self.interim_payout_bps() == 0 {
+                return Err(ConwayBetsError::ClaimsNotOpen);
+            }
+            return Ok(self.claim_interim(user, market_id).await);
+        }
+        let resolved = market.is_resolved;
+        if resolved {
+            self.collect_creator_fee(market_id).await;
+        }
+        let interim = self.interim_payouts.remove(&(user, market_id)).unwrap_or_default();
+        let payout = self.payout(user, &market_id).saturating_sub(interim);
+        let market = &self.markets[&market_id];
+        let mut unclaimed = self
+            .user_positions
+            .get_mut(&user)
+            .into_iter()
+            .flatten()
+            .filter(|p| p.market_id == market_id && !p.claimed)
+            .peekable();
+        if unclaimed.peek().is_none() {
+            return Ok(Amount::ZERO);
+        }
+        // Only winning stakes earn the bonus.
+        let mut early_stake = Amount::ZERO;
+        for position in unclaimed {
+            position.claimed = true;
+            if market.is_winning(position.outcome_index) {
+                early_stake = early_stake.saturating_add(position.early_stake);
+            }
+        }
+
+        self.unlock_funds(market_id, user, payout).await;
+        let bonus = if resolved { self.pay_early_bonus(user, early_stake) } else { Amount::ZERO };
+        let amount = payout.saturating_add(bonus);
+        self.record_event(ConwayBetsEvent::WinningsClaimed { market_id, user, amount });
+        Ok(amount)
+    }
+
+    /// Pays the creator their cut of a resolved market's pot, the first time
+    /// anyone claims from it.
+    async fn collect_creator_fee(&mut self, market_id: MarketId) {
+        let Some(market) = self.markets.get(&market_id).filter(|m| m.creator_fees == Amount::ZERO) else {
+            return;
+        };
+        let (creator, fee) = (market.creator, Amount::from_attos(self.pool_split(market).fees));
+        if fee == Amount::ZERO {
+            return;
+        }
+        if let Some(market) = self.markets.get_mut(&market_id) {
+            market.creator_fees = fee;
+        }
+        self.unlock_funds(market_id, creator, fee).await;
+    }
+
+    /// Adds `amount` from `funder` to the reward pool.
+    pub async fn fund_rewards(&mut self, funder: AccountOwner, amount: Amount) -> Result<Amount, ConwayBetsError> {
+        self.lock_funds(funder, amount).await?;
+        self.reward_pool = self.reward_pool.saturating_add(amount);
+        Ok(self.reward_pool)
+    }
+
+    /// Pays `user` the bonus on `early_stake` out of the reward pool, as much
+    /// as is left of it.
+    fn pay_early_bonus(&mut self, user: AccountOwner, early_stake: Amount) -> Amount {
+        let earned = mul_div(u128::from(early_stake), u128::from(self.config.early_bonus_bps), 10_000);
+        let bonus = Amount::from_attos(earned).min(self.reward_pool);
+        self.reward_pool = self.reward_pool.saturating_sub(bonus);
+        self.escrow_balance = self.escrow_balance.saturating_sub(bonus);
+        self.credit(user, bonus);
+        bonus
+    }
+
+    /// Share of a contested market's payout released early, in basis points.
+    /// Zero under `ReopenForResolution`, as a reopened market can be
+    /// re-resolved against an interim winner.
+    fn interim_payout_bps(&self) -> u16 {
+        match self.config.post_dispute {
+            PostDisputeAction::Cancel => self.config.interim_payout_bps.min(10_000),
+            PostDisputeAction::ReopenForResolution => 0,
+        }
+    }
+
+    /// Releases `user`'s interim share of a contested market's payout, up to
+    /// their stake on it, less whatever was released before.
+    async fn claim_interim(&mut self, user: AccountOwner, market_id: MarketId) -> Amount {
+        let payout = u128::from(self.payout(user, &market_id));
+        let share_bps = u128::from(self.interim_payout_bps());
+        let stake = self
+            .user_positions
+            .get(&user)
+            .into_iter()
+            .flatten()
+            .filter(|p| p.market_id == market_id)
+            .fold(Amount::ZERO, |total, p| total.saturating_add(p.amount));
+        let due = Amount::from_attos(mul_div(payout, share_bps, 10_000)).min(stake);
+        let paid = self.interim_payouts.get(&(user, market_id)).copied().unwrap_or_default();
+        let release = due.saturating_sub(paid);
+        if release == Amount::ZERO {
+            return Amount::ZERO;
+        }
+        self.interim_payouts.insert((user, market_id), due);
+        self.unlock_funds(market_id, user, release).await;
+        release
+    }
+
+    /// Claims each market in `market_ids` for `user`. A market that can't be
+    /// claimed is recorded as such and skipped; it doesn't stop the batch.
+    pub async fn claim_all(&mut self, user: AccountOwner, market_ids: Vec<MarketId>) -> BatchClaim {
+        let mut total = Amount::ZERO;
+        let mut results = Vec::with_capacity(market_ids.len());
+        for market_id in market_ids {
+            let result = self.claim_winnings(user, market_id).await;
+            if let Ok(amount) = result {
+                total = total.saturating_add(amount);
+            }
+            results.push((market_id, result));
+        }
+        BatchClaim { total, results }
+    }
+
+    /// Adds a market to `user`'s watchlist. Returns whether it was new there.
+    pub fn watch(&mut self, user: AccountOwner, market_id: MarketId) -> Result<bool, ConwayBetsError> {
+        if !self.markets.contains_key(&market_id) {
+            return Err(ConwayBetsError::MarketNotFound);
+        }
+        Ok(self.watchlist.entry(user).or_default().insert(market_id))
+    }
+
+    /// Removes a market from `user`'s watchlist. Returns whether it was there.
+    pub fn unwatch(&mut self, user: AccountOwner, market_id: MarketId) -> bool {
+        let Some(watched) = self.watchlist.get_mut(&user) else {
+            return false;
+        };
+        let removed = watched.remove(&market_id);
+        if watched.is_empty() {
+            self.watchlist.remove(&user);
+        }
+        removed
+    }
+
+    /// The markets on `user`'s watchlist, resolved ones included, skipping
+    /// any no longer stored.
+    pub fn watched_markets(&self, user: AccountOwner) -> Vec<&Market> {
+        self.watchlist
+            .get(&user)
+            .into_iter()
+            .flatten()
+            .filter_map(|market_id| self.markets.get(market_id))
+            .collect()
+    }
+
+    /// Credits `destination` with the payout rounding dust of every market
+    /// whose claims are open, plus every in-app balance below
+    /// `Config::dust_threshold` left untouched for `Config::dust_idle_secs`.
+    /// Returns the total swept.
+    pub async fn sweep_dust(
+        &mut self,
+        caller: AccountOwner,
+        destination: AccountOwner,
+    ) -> Result<Amount, ConwayBetsError> {
+        if self.config.owner != Some(caller) {
+            return Err(ConwayBetsError::NotAuthorized);
+        }
+        let now = self.block.timestamp;
+        let market_dust: Vec<(MarketId, Amount)> = self
+            .markets
+            .values()
+            .filter(|m| !m.dust_swept && m.claims_open(now))
+            .map(|m| (m.id, Amount::from_attos(self.pool_split(m).dust)))
+            .collect();
+        let mut swept = Amount::ZERO;
+        for (market_id, dust) in market_dust {
+            self.unlock_funds(market_id, destination, dust).await;
+            if let Some(market) = self.markets.get_mut(&market_id) {
+                market.dust_swept = true;
+            }
+            swept = swept.saturating_add(dust);
+        }
+
+        let (threshold, idle_secs) = (self.config.dust_threshold, self.config.dust_idle_secs);
+        let updated_at = &self.balance_updated_at;
+        let mut abandoned = Vec::new();
+        let mut small_balances = Amount::ZERO;
+        self.balances.retain(|user, balance| {
+            let idle = now.saturating_sub(updated_at.get(user).copied().unwrap_or_default()) >= idle_secs;
+            if *user == destination || *balance >= threshold || !idle {
+                return true;
+            }
+            abandoned.push(*user);
+            small_balances = small_balances.saturating_add(*balance);
+            false
+        });
+        for user in abandoned {
+            self.balance_updated_at.remove(&user);
+        }
+        self.credit(destination, small_balances);
+        Ok(swept.saturating_add(small_balances))
+    }
+
+    /// Unclaimed winnings across all users, by market in id order, starting
+    /// after `after`. Pages end on a market boundary once at least `first`
+    /// entries are collected, so the last entry's market is the next cursor.
+    pub fn claimable_positions(&self, first: usize, after: Option<MarketId>) -> Vec<ClaimablePosition> {
+        let now = self.block.timestamp;
+        let start = match after {
+            Some(after) => Bound::Excluded(after),
+            None => Bound::Unbounded,
+        };
+        let mut claimable = Vec::new();
+        for market in self.markets.range((start, Bound::Unbounded)).map(|(_, m)| m) {
+            if claimable.len() >= first {
+                break;
+            }
+            if !market.claims_open(now) {
+                continue;
+            }
+            let split = self.pool_split(market);
+            for (user, positions) in &self.user_positions {
+                let unclaimed = positions.iter().any(|p| p.market_id == market.id && !p.claimed);
+                let holder = Holder::User(*user);
+                let amount = split.refunds.get(&holder).copied().unwrap_or_default()
+                    + split.winnings.get(&holder).copied().unwrap_or_default();
+                if unclaimed && amount > 0 {
+                    claimable.push(ClaimablePosition {
+                        market_id: market.id,
+                        user: *user,
+                        amount: Amount::from_attos(amount),
+                    });
+                }
+            }
+        }
+        claimable
+    }
+
+    /// Divides a resolved market's pool: voided stakes are refunded at par, the
+    /// creator's fee comes off the rest, and winners share what's left
+    /// pro-rata to their winning stake, or to their winning shares in an AMM
+    /// market, where a cheap early buy is worth more than a dear late one.
+    fn pool_split(&self, market: &Market) -> PoolSplit {
+        self.split_pool(market, market.is_resolved.then_some(&market.winning_outcomes))
+    }
+
+    /// `pool_split` as if the market had resolved to `winning_outcomes`, or
+    /// with nothing winning while it's `None`.
+    fn split_pool(&self, market: &Market, winning_outcomes: Option<&BTreeSet<u32>>) -> PoolSplit {
+        let positions = self.user_positions.iter().flat_map(|(user, positions)| {
+            positions.iter().map(move |p| (Holder::User(*user), p.market_id, p.outcome_index, p.amount, p.shares))
+        });
+        let committed = self.committed_stakes.iter().map(|(commitment, s)| {
+            (Holder::Commitment(*commitment), s.market_id, s.outcome_index, s.amount, s.shares)
+        });
+        let stakes: Vec<_> = positions.chain(committed).filter(|(_, id, ..)| *id == market.id).collect();
+        // A result nobody backed voids the market, so every stake comes back at par.
+        let unbacked = winning_outcomes.is_some_and(|w| {
+            !stakes.iter().any(|(_, _, outcome, _, shares)| {
+                w.contains(outcome) && !market.voided_outcomes.contains(outcome) && *shares > Amount::ZERO
+            })
+        });
+        let void = market.is_cancelled || unbacked;
+        let mut refunds: BTreeMap<Holder, u128> = BTreeMap::new();
+        let mut weights: BTreeMap<Holder, u128> = BTreeMap::new();
+        let mut winning_stake = 0;
+        for (holder, _, outcome_index, amount, shares) in stakes {
+            let amount = u128::from(amount);
+            if void || market.voided_outcomes.contains(&outcome_index) {
+                *refunds.entry(holder).or_default() += amount;
+            } else if winning_outcomes.is_some_and(|w| w.contains(&outcome_index)) {
+                // One share per atto staked unless an AMM priced it, or a split
+                // gave the holder shares on the other side of a binary.
+                *weights.entry(holder).or_default() += u128::from(shares);
+                winning_stake += amount;
+            }
+        }
+
+        let refunded: u128 = refunds.values().sum();
+        let pot = u128::from(market.total_liquidity).saturating_sub(refunded);
+        let fees = if winning_outcomes.is_some() && !void {
+            mul_div(pot, u128::from(market.creator_fee_bps.min(10_000)), 10_000)
+        } else {
+            0
+        };
+        let pool = pot - fees;
+        let total_weight: u128 = weights.values().sum();
+        let winnings: BTreeMap<Holder, u128> = weights
+            .into_iter()
+            .map(|(holder, weight)| (holder, mul_div(weight, pool, total_weight)))
+            .collect();
+        // Only rounding leaves dust; a pot nobody won isn't dust.
+        let dust = if total_weight == 0 { 0 } else { pool - winnings.values().sum::<u128>() };
+        PoolSplit { refunds, winnings, winning_stake, fees, dust }
+    }
+
+    /// What each outcome would pay its backers if it alone won, using the same
+    /// split as a real resolution. `None` for an unknown market.
+    pub fn payout_table(&self, market_id: &MarketId) -> Option<Vec<OutcomePayoutRow>> {
+        let market = self.markets.get(market_id)?;
+        let rows = (0..market.outcomes.len() as u32)
+            .map(|outcome_index| {
+                let split = self.split_pool(market, Some(&BTreeSet::from([outcome_index])));
+                let paid: u128 = split.winnings.values().sum();
+                OutcomePayoutRow {
+                    outcome_index,
+                    label: market.outcomes[outcome_index as usize].clone(),
+                    total_payout: Amount::from_attos(paid),
+                    multiplier: (split.winning_stake > 0).then(|| paid as f64 / split.winning_stake as f64),
+                }
+            })
+            .collect();
+        Some(rows)
+    }
+
+    /// Stake needed to push an AMM market's outcome up to `target_probability`.
+    pub fn liquidity_for_price_move(
+        &self,
+        market_id: MarketId,
+        outcome_index: u32,
+        target_probability: f64,
+    ) -> Result<Amount, ConwayBetsError> {
+        let market = self.markets.get(&market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        let b = u128::from(market.lmsr_b.ok_or(ConwayBetsError::NotAmmMarket)?) as f64;
+        let outcome = outcome_index as usize;
+        if outcome >= market.outcome_shares.len() {
+            return Err(ConwayBetsError::OutcomeOutOfRange);
+        }
+        let shares = amm::shares_for_price(&market.outcome_shares, outcome, target_probability, b)
+            .ok_or(ConwayBetsError::InvalidTargetProbability)?;
+        Ok(amm::cost(&market.outcome_shares, outcome, shares, b))
+    }
+
+    /// Typical wait between close and resolution for markets like this one,
+    /// from its category's history or else its creator's.
+    pub fn estimated_resolution_delay(&self, market_id: &MarketId) -> Option<u64> {
+        let market = self.markets.get(market_id)?;
+        market
+            .category
+            .as_ref()
+            .and_then(|category| self.category_resolution_delays.get(category)?.mean())
+            .or_else(|| self.creator_resolution_delays.get(&market.creator)?.mean())
+    }
+
+    /// The hash peers compare in `SyncState` messages to check they agree on
+    /// a hosted market.
+    pub fn market_state_hash(&self, market_id: &MarketId) -> Result<[u8; 32], ConwayBetsError> {
+        let market = self.markets.get(market_id).ok_or(ConwayBetsError::MarketNotFound)?;
+        Ok(market.state_hash)
+    }
+
+    /// BCS-encoded size of a market plus every position on it, for
+    /// forecasting storage growth.
+    pub fn market_state_size(&self, market_id: &MarketId) -> Option<usize> {
+        let market = self.markets.get(market_id)?;
+        let positions: Vec<&UserPosition> = self
+            .user_positions
+            .values()
+            .flatten()
+            .filter(|p| p.market_id == *market_id)
+            .collect();
+        let market_size = bcs::serialized_size(market).expect("Market serializes");
+        let positions_size = bcs::serialized_size(&positions).expect("UserPosition serializes");
+        Some(market_size + positions_size)
+    }
+
+    /// Checks that escrow covers all unclaimed market liquidity plus reserved
+    /// stakes.
+    pub fn solvency_report(&self) -> SolvencyReport {
+        let market_liquidity = self.markets.values().fold(Amount::ZERO, |total, m| {
+            total
+                .saturating_add(m.total_liquidity.saturating_sub(m.paid_out))
+                .saturating_add(m.subsidy)
+                .saturating_add(m.creation_deposit)
+        });
+        let reserved = self
+            .pending_bets
+            .values()
+            .map(|pending| &pending.bet)
+            .chain(self.unacked_bets.values())
+            .chain(self.failed_bets.values())
+            .fold(Amount::ZERO, |total, bet| total.saturating_add(bet.amount));
+        let owed = market_liquidity
+            .saturating_add(reserved)
+            .saturating_add(self.treasury)
+            .saturating_add(self.reward_pool);
+        SolvencyReport {
+            market_liquidity,
+            treasury: self.treasury,
+            reserved,
+            escrowed: self.escrow_balance,
+            surplus: self.escrow_balance.saturating_sub(owed),
+            deficit: owed.saturating_sub(self.escrow_balance),
+        }
+    }
+
+    /// `user`'s holdings in markets that are neither resolved nor cancelled,
+    /// one entry per outcome, by market then outcome.
+    pub fn open_positions(&self, user: AccountOwner) -> Vec<OpenPosition> {
+        let mut holdings: BTreeMap<(MarketId, u32), (Amount, Amount)> = BTreeMap::new();
+        for position in self.user_positions.get(&user).into_iter().flatten() {
+            let (staked, shares) = holdings.entry((position.market_id, position.outcome_index)).or_default();
+            *staked = staked.saturating_add(position.amount);
+            *shares = shares.saturating_add(position.shares);
+        }
+        holdings
+            .into_iter()
+            .filter_map(|((market_id, outcome_index), (staked, shares))| {
+                let market = self.markets.get(&market_id).filter(|m| !m.is_resolved && !m.is_cancelled)?;
+                let label = market.outcomes.get(outcome_index as usize)?.clone();
+                let implied_probability = market.implied_probabilities()[outcome_index as usize];
+                let ppm = (implied_probability * 1_000_000.0).round() as u128;
+                Some(OpenPosition {
+                    market_id,
+                    outcome_index,
+                    label,
+                    staked,
+                    shares,
+                    implied_probability,
+                    value: Amount::from_attos(mul_div(u128::from(shares), ppm, 1_000_000)),
+                })
+            })
+            .collect()
+    }
+
+    /// `user`'s open positions on every market tied to `external_event_id`,
+    /// summed per outcome label, by label.
+    pub fn exposure_by_event(&self, user: AccountOwner, external_event_id: &str) -> Vec<OutcomeExposure> {
+        let mut exposure: BTreeMap<&str, (Amount, Amount, BTreeSet<MarketId>)> = BTreeMap::new();
+        for position in self.user_positions.get(&user).into_iter().flatten() {
+            let Some(market) = self.markets.get(&position.market_id) else {
+                continue;
+            };
+            if market.external_event_id.as_deref() != Some(external_event_id) || position.claimed {
+                continue;
+            }
+            let Some(label) = market.outcomes.get(position.outcome_index as usize) else {
+                continue;
+            };
+            let (staked, shares, markets) = exposure.entry(label.as_str()).or_default();
+            *staked = staked.saturating_add(position.amount);
+            *shares = shares.saturating_add(position.shares);
+            markets.insert(market.id);
+        }
+        exposure
+            .into_iter()
+            .map(|(label, (staked, shares, markets))| OutcomeExposure {
+                label: label.to_string(),
+                staked,
+                shares,
+                markets: markets.len() as u32,
+            })
+            .collect()
+    }
+
+    /// An outcome's implied probability over `[from, to]`, oldest first. Longer
+    /// series are thinned to `max_points` evenly spaced observations, keeping
+    /// the first and last.
+    pub fn price_history(
+        &self,
+        market_id: &MarketId,
+        outcome_index: u32,
+        from: u64,
+        to: u64,
+        max_points: usize,
+    ) -> Vec<(u64, f64)> {
+        let Some(market) = self.markets.get(market_id) else {
+            return Vec::new();
+        };
+        let points: Vec<(u64, f64)> = market
+            .price_observations
+            .iter()
+            .filter(|o| (from..=to).contains(&o.timestamp))
+            .filter_map(|o| {
+                let ppm = *o.probabilities_ppm.get(outcome_index as usize)?;
+                Some((o.timestamp, f64::from(ppm) / 1_000_000.0))
+            })
+            .collect();
+        match max_points {
+            0 => Vec::new(),
+            1 => points.last().copied().into_iter().collect(),
+            _ if points.len() <= max_points => points,
+            _ => (0..max_points)
+                .map(|i| points[i * (points.len() - 1) / (max_points - 1)])
+                .collect(),
+        }
+    }
+
+    /// How far an outcome's implied probability has moved since the market
+    /// opened: the latest observation less the first one kept. `None` before
+    /// any observations.
+    pub fn probability_drift(&self, market_id: &MarketId, outcome_index: u32) -> Option<f64> {
+        let observations = &self.markets.get(market_id)?.price_observations;
+        let ppm = |o: &PriceObservation| o.probabilities_ppm.get(outcome_index as usize).copied();
+        let opening = ppm(observations.front()?)?;
+        let current = ppm(observations.back()?)?;
+        Some((f64::from(current) - f64::from(opening)) / 1_000_000.0)
+    }
+
+    /// Pearson correlation of two outcomes' implied probabilities over the
+    /// window both markets have observations for. Each series is sampled at
+    /// every observation time in that window, holding its last value between
+    /// its own observations. `None` with fewer than two samples or a series
+    /// that doesn't move.
+    pub fn odds_correlation(
+        &self,
+        market_a: &MarketId,
+        outcome_a: u32,
+        market_b: &MarketId,
+        outcome_b: u32,
+    ) -> Option<f64> {
+        let series_a = &self.markets.get(market_a)?.price_observations;
+        let series_b = &self.markets.get(market_b)?.price_observations;
+        let start = series_a.front()?.timestamp.max(series_b.front()?.timestamp);
+        let end = series_a.back()?.timestamp.min(series_b.back()?.timestamp);
+        let times: BTreeSet<u64> = series_a
+            .iter()
+            .chain(series_b)
+            .map(|o| o.timestamp)
+            .filter(|t| (start..=end).contains(t))
+            .collect();
+        let value_at = |series: &VecDeque<PriceObservation>, outcome: u32, time: u64| -> Option<f64> {
+            let observation = series.iter().rev().find(|o| o.timestamp <= time)?;
+            Some(f64::from(*observation.probabilities_ppm.get(outcome as usize)?))
+        };
+        let samples: Vec<(f64, f64)> = times
+            .into_iter()
+            .map(|t| Some((value_at(series_a, outcome_a, t)?, value_at(series_b, outcome_b, t)?)))
+            .collect::<Option<_>>()?;
+        if samples.len() < 2 {
+            return None;
+        }
+
+        let n = samples.len() as f64;
+        let mean_a = samples.iter().map(|(a, _)| a).sum::<f64>() / n;
+        let mean_b = samples.iter().map(|(_, b)| b).sum::<f64>() / n;
+        let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+        for (a, b) in &samples {
+            covariance += (a - mean_a) * (b - mean_b);
+            variance_a += (a - mean_a).powi(2);
+            variance_b += (b - mean_b).powi(2);
+        }
+        if variance_a == 0.0 || variance_b == 0.0 {
+            return None;
+        }
+        Some(covariance / (variance_a * variance_b).sqrt())
+    }
+
+    /// Estimates the work `operation` would do against the current state
+    /// without executing it. Counts are upper bounds: an operation that fails
+    /// early does less.
+    pub fn estimate_cost(&self, operation: &Operation) -> CostEstimate {
+        // Payouts and state hashes are computed over every stored position.
+        let all_positions = self.user_positions.values().map(|p| p.len() as u64).sum::<u64>();
+        let user_positions = |user: &AccountOwner| self.user_positions.get(user).map_or(0, |p| p.len() as u64);
+        let (markets_touched, positions_scanned, messages_emitted) = match operation {
+            Operation::CreateMarket { .. } => (1, 0, 1),
+            Operation::PlaceBet { .. } | Operation::ConditionalBet { .. } => (1, all_positions, 1),
+            Operation::PlaceCommittedBet { .. } => (1, 0, 0),
+            Operation::ClaimCommitted { .. } => (1, all_positions, 0),
+            Operation::SetResolver { .. }
+            | Operation::EditOutcome { .. }
+            | Operation::DelegateResolver { .. }
+            | Operation::CancelMarket { .. }
+            | Operation::SuspendOutcome { .. }
+            | Operation::FundMarket { .. }
+            | Operation::ApproveMarket { .. }
+            | Operation::Watch { .. }
+            | Operation::Unwatch { .. }
+            | Operation::RejectMarket { .. }
+            | Operation::ChallengeResolution { .. }
+            | Operation::RuleOnDispute { .. }
+            | Operation::ResolveMarket { .. }
+            | Operation::ResolveScalar { .. }
+            | Operation::ResolveMarketAnyOf { .. } => (1, 0, 0),
+            Operation::Reindex => (0, all_positions, 0),
+            Operation::TickPending => (0, self.pending_bets.len() as u64, 0),
+            Operation::CancelPendingBet { .. } | Operation::TopUpAndRetry { .. } => (0, 0, 1),
+            Operation::FundRewards { .. } => (0, 0, 0),
+            Operation::BulkCreateMarkets { markets, .. } => (markets.len() as u64, 0, markets.len() as u64),
+            Operation::LockPosition { user, .. } | Operation::UnlockPosition { user, .. } => {
+                (1, user_positions(user), 0)
+            }
+            Operation::WithdrawBet { .. } | Operation::ClaimWinnings { .. } => (1, all_positions, 0),
+            Operation::ClaimAll { market_ids, .. } => {
+                let markets = market_ids.len() as u64;
+                (markets, markets * all_positions, 0)
+            }
+            Operation::MergeMarkets { .. } => (2, all_positions, 0),
+            Operation::SweepDust { .. } => {
+                let markets = self.markets.len() as u64;
+                (markets, markets * all_positions, 0)
+            }
+            Operation::SplitMarket { market_id, .. } => {
+                let outcomes = self.markets.get(market_id).map_or(0, |m| m.outcomes.len() as u64);
+                (outcomes + 1, all_positions * (outcomes + 1), outcomes)
+            }
+            Operation::Credit { .. } => (0, 0, 0),
+        };
+        CostEstimate { markets_touched, positions_scanned, messages_emitted }
+    }
+
+    /// Fees collected over `[from, to)`, in consecutive buckets of
+    /// `bucket_secs` keyed by their start; the last bucket is cut short at
+    /// `to`. Buckets with no fees report zero. Only fees still in the log count.
+    pub fn fee_report(&self, from: u64, to: u64, bucket_secs: u64) -> Vec<(u64, Amount)> {
+        if bucket_secs == 0 || to <= from {
+            return Vec::new();
+        }
+        let buckets = (to - from).div_ceil(bucket_secs);
+        let mut report: Vec<(u64, Amount)> =
+            (0..buckets).map(|i| (from + i * bucket_secs, Amount::ZERO)).collect();
+        for (timestamp, fee) in self.fee_log.iter().filter(|(t, _)| (from..to).contains(t)) {
+            let total = &mut report[((timestamp - from) / bucket_secs) as usize].1;
+            *total = total.saturating_add(*fee);
+        }
+        report
+    }
+
+    /// Seconds from `now` until the market closes, negative once it has.
+    /// `None` for resolved or cancelled markets.
+    pub fn time_to_close(&self, market_id: &MarketId, now: u64) -> Option<i64> {
+        let market = self.markets.get(market_id).filter(|m| !m.is_resolved && !m.is_cancelled)?;
+        Some(i64::try_from(market.end_time).ok()? - i64::try_from(now).ok()?)
+    }
+
+    /// A market's implied probabilities for display, with `None` for outcomes
+    /// below `Config::min_liquidity_for_odds`.
+    pub fn odds(&self, market_id: &MarketId) -> Option<Vec<Option<f64>>> {
+        let market = self.markets.get(market_id)?;
+        let min_liquidity = self.config.min_liquidity_for_odds;
+        let odds = market
+            .implied_probabilities()
+            .into_iter()
+            .zip(&market.outcome_shares)
+            .map(|(probability, shares)| (*shares >= min_liquidity).then_some(probability))
+            .collect();
+        Some(odds)
+    }
+
+    /// Decimal odds on an outcome (payout per unit staked if it wins) after
+    /// the fee `user` would pay, or the base fee without a user. `None` if
+    /// the outcome is out of range or priced at zero.
+    pub fn net_odds(&self, market_id: &MarketId, outcome_index: u32, user: Option<AccountOwner>) -> Option<f64> {
+        let market = self.markets.get(market_id)?;
+        let probability = *market.implied_probabilities().get(outcome_index as usize)?;
+        if probability <= 0.0 {
+            return None;
+        }
+        let fee_basis_points = match user {
+            Some(user) => self.fee_basis_points_for(user),
+            None => self.config.fee_basis_points.min(10_000),
+        };
+        Some((1.0 - f64::from(fee_basis_points) / 10_000.0) / probability)
+    }
+
+    /// Up to `first` markets still taking bets, hottest first by decayed
+    /// betting activity at `now`.
+    pub fn trending_markets(&self, first: usize, now: u64) -> Vec<&Market> {
+        let half_life = self.config.trending_half_life_secs;
+        let mut markets: Vec<(f64, &Market)> = self
+            .markets
+            .values()
+            .filter(|m| !m.is_resolved && !m.is_cancelled)
+            .map(|m| (m.trending_score(now, half_life), m))
+            .collect();
+        markets.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        markets.into_iter().take(first).map(|(_, m)| m).collect()
+    }
+
+    /// Markets that aren't cancelled and match every field set in `filter`,
+    /// in id order. Filtering by creator reads their markets from the index
+    /// instead of scanning all, which gives the same set.
+    pub fn markets_filtered(&self, filter: &MarketFilter) -> Vec<&Market> {
+        let candidates: Vec<&Market> = match filter.creator {
+            Some(creator) => {
+                let ids = self.creator_markets.get(&creator).into_iter().flatten();
+                ids.filter_map(|id| self.markets.get(id)).collect()
+            }
+            None => self.markets.values().filter(|m| !m.is_cancelled).collect(),
+        };
+        candidates
+            .into_iter()
+            .filter(|m| filter.resolved.is_none_or(|resolved| m.is_resolved == resolved))
+            .filter(|m| filter.min_liquidity.is_none_or(|min| m.total_liquidity >= min))
+            .collect()
+    }
+
+    /// How often each outcome label has won across resolved markets, by
+    /// label. Each winner of an any-of resolution counts once.
+    pub fn resolution_distribution(&self) -> Vec<(String, u32)> {
+        let mut tallies: BTreeMap<String, u32> = BTreeMap::new();
+        for market in self.markets.values().filter(|m| m.is_resolved) {
+            for &outcome in &market.winning_outcomes {
+                if let Some(label) = market.outcomes.get(outcome as usize) {
+                    *tallies.entry(label.clone()).or_default() += 1;
+                }
+            }
+        }
+        tallies.into_iter().collect()
+    }
+
+    /// Stakes on each outcome label summed across markets still taking bets,
+    /// e.g. everything riding on "Yes" platform-wide.
+    pub fn open_interest_by_label(&self) -> Vec<(String, Amount)> {
+        let mut totals: BTreeMap<String, Amount> = BTreeMap::new();
+        for market in self.markets.values().filter(|m| !m.is_resolved && !m.is_cancelled) {
+            for (label, pool) in market.outcomes.iter().zip(&market.outcome_pools) {
+                let total = totals.entry(label.clone()).or_default();
+                *total = total.saturating_add(*pool);
+            }
+        }
+        totals.into_iter().collect()
+    }
+
+    /// `user`'s positions newest first, claimed ones included; only those
+    /// placed at or after `since` when given.
+    pub fn user_bets(&self, user: AccountOwner, since: Option<u64>) -> Vec<&UserPosition> {
+        let mut bets: Vec<&UserPosition> = self
+            .user_positions
+            .get(&user)
+            .into_iter()
+            .flatten()
+            .rev()
+            .filter(|p| since.is_none_or(|since| p.placed_at >= since))
+            .collect();
+        // Stable, so bets from the same block stay latest first.
+        bets.sort_by_key(|p| std::cmp::Reverse(p.placed_at));
+        bets
+    }
+
+    pub fn portfolio(&self, user: AccountOwner) -> Portfolio {
+        let open = self.open_positions(user);
+        let positions: Vec<&UserPosition> = self.user_positions.get(&user).into_iter().flatten().collect();
+        let won: BTreeSet<MarketId> = positions
+            .iter()
+            .filter(|p| self.markets.get(&p.market_id).is_some_and(|m| m.is_winning(p.outcome_index)))
+            .map(|p| p.market_id)
+            .collect();
+        let unclaimed: BTreeSet<MarketId> = positions.iter().filter(|p| !p.claimed).map(|p| p.market_id).collect();
+        let holder = Holder::User(user);
+        let claimable: u128 = unclaimed
+            .iter()
+            .filter_map(|market_id| self.markets.get(market_id))
+            .filter(|m| m.claims_open(self.block.timestamp))
+            .map(|m| {
+                let split = self.pool_split(m);
+                split.refunds.get(&holder).copied().unwrap_or_default()
+                    + split.winnings.get(&holder).copied().unwrap_or_default()
+            })
+            .sum();
+        Portfolio {
+            total_staked: open.iter().fold(Amount::ZERO, |total, p| total.saturating_add(p.staked)),
+            open_positions: open.len() as u32,
+            resolved_wins: won.len() as u32,
+            claimable: Amount::from_attos(claimable),
+        }
+    }
+
+    pub fn streaks(&self, user: AccountOwner) -> StreakStats {
+        let mut results: Vec<(u64, MarketId, bool)> = self
+            .user_positions
+            .get(&user)
+            .into_iter()
+            .flatten()
+            .filter_map(|p| {
+                let market = self.markets.get(&p.market_id)?;
+                let resolved_at = market.resolved_at?;
+                if market.voided_outcomes.contains(&p.outcome_index) {
+                    return None;
+                }
+                Some((resolved_at, p.market_id, market.is_winning(p.outcome_index)))
+            })
+            .collect();
+        results.sort_by_key(|(resolved_at, market_id, _)| (*resolved_at, *market_id));
+
+        let mut stats = StreakStats::default();
+        for (_, _, won) in results {
+            if won {
+                stats.current_wins += 1;
+                stats.current_losses = 0;
+                stats.longest_wins = stats.longest_wins.max(stats.current_wins);
+            } else {
+                stats.current_losses += 1;
+                stats.current_wins = 0;
+                stats.longest_losses = stats.longest_losses.max(stats.current_losses);
+            }
+        }
+        stats
+    }
+
+    /// One Merkle leaf per (user, outcome) holding on the market, in key order.
+    fn position_leaves(&self, market_id: &MarketId) -> Vec<((AccountOwner, u32), [u8; 32])> {
+        let mut holdings: BTreeMap<(AccountOwner, u32), Amount> = BTreeMap::new();
+        for (user, positions) in &self.user_positions {
+            for position in positions.iter().filter(|p| p.market_id == *market_id) {
+                let total = holdings.entry((*user, position.outcome_index)).or_insert(Amount::ZERO);
+                *total = total.saturating_add(position.amount);
+            }
+        }
+        holdings
+            .into_iter()
+            .map(|(key, amount)| {
+                let bytes = bcs::to_bytes(&(key.0, key.1, amount)).expect("position leaf serializes");
+                (key, merkle::hash_leaf(&bytes))
+            })
+            .collect()
+    }
+
+    fn positions_root(&self, market_id: &MarketId) -> [u8; 32] {
+        let leaves: Vec<[u8; 32]> = self.position_leaves(market_id).into_iter().map(|(_, leaf)| leaf).collect();
+        merkle::root(&leaves)
+    }
+
+    /// Hash of a market's pool, outcomes and resolution together with the
+    /// Merkle root of its positions. Chains holding the same market state get
+    /// the same hash; any bet changes it. All zeros for an unknown market.
+    pub fn compute_state_hash(&self, market_id: &MarketId) -> [u8; 32] {
+        let Some(m) = self.markets.get(market_id) else {
+            return [0; 32];
+        };
+        let fields = (
+            (m.id, m.creator, &m.title, &m.description, m.end_time, &m.outcomes),
+            (m.total_liquidity, &m.outcome_pools, &m.outcome_shares, m.paid_out, m.subsidy),
+            (m.is_resolved, &m.winning_outcomes, &m.voided_outcomes, m.resolved_value),
+            (m.is_cancelled, m.status, &m.suspended_outcomes),
+        );
+        let bytes = bcs::to_bytes(&fields).expect("market fields serialize");
+        Sha256::new()
+            .chain_update(b"conwaybets/market")
+            .chain_update(bytes)
+            .chain_update(self.positions_root(market_id))
+            .finalize()
+            .into()
+    }
+
+    /// Recommits the market's `positions_root` and `state_hash` to its
+    /// current state.
+    fn refresh_state_hash(&mut self, market_id: &MarketId) {
+        let positions_root = self.positions_root(market_id);
+        let state_hash = self.compute_state_hash(market_id);
+        if let Some(market) = self.markets.get_mut(market_id) {
+            market.positions_root = positions_root;
+            market.state_hash = state_hash;
+        }
+    }
+
+    /// Proof that `user`'s holding on an outcome is committed in the market's
+    /// `positions_root`, for light clients that don't fetch every position.
+    pub fn position_proof(&self, market_id: &MarketId, user: AccountOwner, outcome_index: u32) -> Option<MerkleProof> {
+        let leaves = self.position_leaves(market_id);
+        let index = leaves.iter().position(|(key, _)| *key == (user, outcome_index))?;
+        let hashes: Vec<[u8; 32]> = leaves.into_iter().map(|(_, leaf)| leaf).collect();
+        MerkleProof::new(&hashes, index)
+    }
+
+    /// Applies a state sync from `origin`. Hashes reported for markets hosted
+    /// here are checked against ours: a mismatch flags the market as diverged
+    /// and a later match clears it. Other markets are recorded as remote.
+    /// Syncs no newer than the last one `origin` sent for the market are
+    /// dropped.
+    pub fn on_sync(
+        &mut self,
+        origin: ChainId,
+        market_id: MarketId,
+        remote_hash: [u8; 32],
+        height: u64,
+    ) -> SyncOutcome {
+        if self.last_synced_height.get(&(origin, market_id)).is_some_and(|&last| height <= last) {
+            log::debug!("ignoring stale sync of market {} at height {height} from {origin}", market_id.id);
+            return SyncOutcome::Stale;
+        }
+        self.last_synced_height.insert((origin, market_id), height);
+        let Some(market) = self.markets.get(&market_id) else {
+            self.record_remote_market(market_id, remote_hash, height);
+            return SyncOutcome::Updated;
+        };
+        if market.state_hash == remote_hash {
+            self.divergences.remove(&market_id);
+            return SyncOutcome::InSync;
+        }
+        log::warn!("market {} diverged: {origin} reports a different state hash", market_id.id);
+        self.divergences.insert(
+            market_id,
+            DivergenceReport {
+                market_id,
+                local_hash: market.state_hash,
+                remote_hash,
+                reporting_chain: origin,
+                block_height: height,
+                detected_at: self.block.timestamp,
+            },
+        );
+        SyncOutcome::Diverged
+    }
+
+    /// Where a hosted market stands after its latest sync; `None` if it has
+    /// never been synced.
+    pub fn sync_status(&self, market_id: &MarketId) -> Option<SyncOutcome> {
+        let synced = self.last_synced_height.keys().any(|(_, synced)| synced == market_id);
+        if !self.markets.contains_key(market_id) || !synced {
+            return None;
+        }
+        if self.divergences.contains_key(market_id) {
+            Some(SyncOutcome::Diverged)
+        } else {
+            Some(SyncOutcome::InSync)
+        }
+    }
+
+    /// Markets currently flagged as diverged, in id order.
+    pub fn divergent_markets(&self) -> Vec<DivergenceReport> {
+        self.divergences.values().cloned().collect()
+    }
+
+    pub fn record_remote_market(&mut self, market_id: MarketId, state_hash: [u8; 32], block_height: u64) {
+        self.remote_markets.insert(
+            market_id,
+            RemoteMarketRef { market_id, state_hash, block_height },
+        );
+    }
+}
+
+/// Who a stake is paid out to: a known account, or whoever can open a commitment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Holder {
+    User(AccountOwner),
+    Commitment([u8; 32]),
+}
+
+/// A resolved market's pool, in attos, by destination.
+struct PoolSplit {
+    refunds: BTreeMap<Holder, u128>,
+    winnings: BTreeMap<Holder, u128>,
+    winning_stake: u128,
+    fees: u128,
+    dust: u128,
+}
+
+/// Computes `value * numerator / denominator` rounded down, using a 256-bit
+/// intermediate so stakes and pools in attos can't overflow. Returns zero
+/// for a zero denominator. The result must fit in a `u128`.
+pub(crate) fn mul_div(value: u128, numerator: u128, denominator: u128) -> u128 {
+    if denominator == 0 {
+        return 0;
+    }
+    const MASK: u128 = u64::MAX as u128;
+    let (a_hi, a_lo) = (value >> 64, value & MASK);
+    let (b_hi, b_lo) = (numerator >> 64, numerator & MASK);
+    let lo_lo = a_lo * b_lo;
+    let hi_lo = a_hi * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let cross = (lo_lo >> 64) + (hi_lo & MASK) + (lo_hi & MASK);
+    let low = (cross << 64) | (lo_lo & MASK);
+    let high = a_hi * b_hi + (hi_lo >> 64) + (lo_hi >> 64) + (cross >> 64);
+
+    let mut quotient = 0u128;
+    let mut remainder = 0u128;
+    for i in (0..256).rev() {
+        let bit = if i >= 128 { (high >> (i - 128)) & 1 } else { (low >> i) & 1 };
+        let carry = remainder >> 127;
+        remainder = (remainder << 1) | bit;
+        quotient <<= 1;
+        if carry == 1 || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            quotient |= 1;
+        }
+    }
+    quotient
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    fn chain(n: u64) -> ChainId {
+        ChainId([n; 4].into())
+    }
+
+    fn owner(n: u8) -> AccountOwner {
+        AccountOwner::Address20([n; 20])
+    }
+
+    fn market_data() -> MarketCreationData {
+        MarketCreationData {
+            title: "Test Market".to_string(),
+            description: "Description".to_string(),
+            end_time: 1_000_000_000,
+            outcomes: vec!["Yes".to_string(), "No".to_string()],
+            ..MarketCreationData::default()
+        }
+    }
+
+    fn resolution(market_id: MarketId, winning_outcome: u32) -> ResolutionData {
+        ResolutionData {
+            market_id,
+            winning_outcome,
+            resolution_proof: vec![],
+            voided_outcomes: BTreeSet::new(),
+        }
+    }
+
+    /// Fresh state where owners 1 through 9 each have plenty to bet with.
+    fn funded() -> ConwayBets {
+        let mut conwaybets = ConwayBets::default();
+        for n in 1..=9 {
+            conwaybets.credit(owner(n), Amount::from_tokens(1_000_000));
+        }
+        conwaybets
+    }
+
+    fn create_test_market(conwaybets: &mut ConwayBets, data: MarketCreationData) -> MarketId {
+        conwaybets.create_market(owner(1), data).now_or_never().unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_remote_refs_with_same_id_on_two_chains_coexist() {
+        let mut conwaybets = ConwayBets::default();
+        let on_a = MarketId { chain_id: chain(1), id: 7 };
+        let on_b = MarketId { chain_id: chain(2), id: 7 };
+
+        conwaybets.record_remote_market(on_a, [1; 32], 10);
+        conwaybets.record_remote_market(on_b, [2; 32], 20);
+
+        assert_eq!(conwaybets.remote_markets.len(), 2);
+        assert_eq!(conwaybets.remote_markets[&on_a].state_hash, [1; 32]);
+        assert_eq!(conwaybets.remote_markets[&on_b].state_hash, [2; 32]);
+        assert_eq!(conwaybets.remote_markets[&on_b].block_height, 20);
+    }
+
+    #[test]
+    fn test_cross_chain_bet_stays_pending_until_confirmed() {
+        let mut conwaybets = ConwayBets::default();
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData { min_confirmations: 3, ..market_data() });
+        let bet = BetMessage {
+            correlation_id: [7; 16],
+            market_id,
+            user: owner(2),
+            outcome_index: 0,
+            amount: Amount::from_tokens(5),
+            nonce: 1,
+            block_height: 10,
+        };
+
+        let status = conwaybets.receive_bet(chain(9), bet).unwrap();
+        assert!(matches!(status, Status::Pending));
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::ZERO);
+
+        let status = conwaybets.confirm_bet(chain(9), 1, 12);
+        assert!(matches!(status, Some(Status::Pending)));
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::ZERO);
+
+        let status = conwaybets.confirm_bet(chain(9), 1, 13);
+        assert!(matches!(status, Some(Status::Finalized)));
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::from_tokens(5));
+        assert!(conwaybets.pending_bets.is_empty());
+        assert!(conwaybets.confirm_bet(chain(9), 1, 20).is_none());
+    }
+
+    #[test]
+    fn test_resolver_change_after_bet_is_rejected() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+
+        conwaybets.set_resolver(owner(1), market_id, Some(owner(3))).unwrap();
+        assert_eq!(
+            conwaybets.set_resolver(owner(2), market_id, None),
+            Err(ConwayBetsError::NotAuthorized)
+        );
+
+        conwaybets
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(1))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            conwaybets.set_resolver(owner(1), market_id, Some(owner(4))),
+            Err(ConwayBetsError::ResolverLocked)
+        );
+        assert_eq!(conwaybets.markets[&market_id].resolver, Some(owner(3)));
+    }
+
+    #[test]
+    fn test_outcome_edit_logged_before_bets_and_frozen_after() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+
+        conwaybets.edit_outcome(owner(1), market_id, 1, "Nope".to_string()).unwrap();
+        let market = &conwaybets.markets[&market_id];
+        assert_eq!(market.outcomes[1], "Nope");
+        assert_eq!(market.outcome_edit_log.len(), 1);
+        assert_eq!(market.outcome_edit_log[0].old_label, "No");
+        assert_eq!(market.outcome_edit_log[0].new_label, "Nope");
+
+        conwaybets
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(1))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            conwaybets.edit_outcome(owner(1), market_id, 0, "Maybe".to_string()),
+            Err(ConwayBetsError::OutcomesFrozen)
+        );
+        assert_eq!(conwaybets.markets[&market_id].outcomes[0], "Yes");
+        assert_eq!(conwaybets.markets[&market_id].outcome_edit_log.len(), 1);
+    }
+
+    #[test]
+    fn test_mul_div_handles_large_products() {
+        assert_eq!(mul_div(10, 7, 3), 23);
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX), u128::MAX);
+        let tokens = u128::from(Amount::from_tokens(1_000_000));
+        assert_eq!(mul_div(tokens, tokens, tokens * 2), tokens / 2);
+        assert_eq!(mul_div(5, 5, 0), 0);
+    }
+
+    #[test]
+    fn test_settlement_summary_after_resolution() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        for (user, outcome, attos) in [(2, 0, 1), (3, 0, 2), (4, 1, 7)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_attos(attos))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        assert!(conwaybets.settlement_summary(&market_id).is_none());
+
+        let resolution = resolution(market_id, 0);
+        conwaybets.resolve_market(owner(1), resolution).unwrap();
+
+        let settlement = conwaybets.settlement_summary(&market_id).unwrap();
+        assert_eq!(settlement.winning_outcomes, vec![0]);
+        assert_eq!(settlement.total_pool, Amount::from_attos(10));
+        assert_eq!(settlement.total_paid, Amount::from_attos(9));
+        assert_eq!(settlement.fees, Amount::ZERO);
+        assert_eq!(settlement.dust, Amount::from_attos(1));
+        assert_eq!(settlement.winners, 2);
+        assert_eq!(
+            settlement.total_paid.saturating_add(settlement.fees).saturating_add(settlement.dust),
+            settlement.total_pool
+        );
+    }
+
+    #[test]
+    fn test_reindex_over_oversized_dataset_trips_work_limit() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        for user in 2..6 {
+            conwaybets
+                .place_bet(market_id, owner(user), 0, Amount::from_tokens(1))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        conwaybets.market_bettors.clear();
+
+        conwaybets.config.max_work_per_operation = 3;
+        assert_eq!(
+            conwaybets.reindex(),
+            Err(ConwayBetsError::WorkLimitExceeded { limit: 3 })
+        );
+        assert!(conwaybets.market_bettors.is_empty());
+
+        conwaybets.config.max_work_per_operation = 4;
+        conwaybets.reindex().unwrap();
+        assert_eq!(conwaybets.market_bettors[&market_id].len(), 4);
+    }
+
+    #[test]
+    fn test_locked_position_rejects_withdrawal_until_unlocked() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        conwaybets
+            .place_bet(market_id, owner(2), 1, Amount::from_tokens(4))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        conwaybets.set_position_lock(owner(2), market_id, 1, true).unwrap();
+        assert_eq!(
+            conwaybets.set_position_lock(owner(3), market_id, 1, true),
+            Err(ConwayBetsError::PositionNotFound)
+        );
+        assert_eq!(
+            conwaybets.withdraw_bet(owner(2), market_id, 0).now_or_never().unwrap(),
+            Err(ConwayBetsError::PositionLocked)
+        );
+
+        conwaybets.set_position_lock(owner(2), market_id, 1, false).unwrap();
+        assert_eq!(
+            conwaybets.withdraw_bet(owner(2), market_id, 0).now_or_never().unwrap(),
+            Ok(Amount::from_tokens(4))
+        );
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::ZERO);
+        assert!(conwaybets.user_positions[&owner(2)].is_empty());
+    }
+
+    #[test]
+    fn test_break_even_is_average_entry_price() {
+        let mut conwaybets = ConwayBets::default();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        assert_eq!(conwaybets.break_even(owner(2), market_id, 0), None);
+
+        // 30 staked for 100 shares, then 20 staked for 25 shares: 50 / 125.
+        for (amount, shares) in [(30, 100), (20, 25)] {
+            conwaybets.user_positions.entry(owner(2)).or_default().push(UserPosition {
+                market_id,
+                outcome_index: 0,
+                amount: Amount::from_tokens(amount),
+                shares: Amount::from_tokens(shares),
+                state_hash: [0; 32],
+                locked: false,
+                claimed: false,
+                early_stake: Amount::ZERO,
+                placed_at: 0,
+            });
+        }
+
+        let break_even = conwaybets.break_even(owner(2), market_id, 0).unwrap();
+        assert!((break_even - 0.4).abs() < 1e-12);
+        assert_eq!(conwaybets.break_even(owner(2), market_id, 1), None);
+    }
+
+    #[test]
+    fn test_fallback_oracle_resolves_only_after_primary_defaults() {
+        let mut conwaybets = ConwayBets::default();
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            fallback_oracle: Some(owner(9)),
+            fallback_after_secs: 3_600,
+            ..market_data()
+        });
+        let resolution = resolution(market_id, 1);
+
+        conwaybets.block.timestamp = 1_000_000_000 + 3_599;
+        assert_eq!(
+            conwaybets.resolve_market(owner(9), resolution.clone()),
+            Err(ConwayBetsError::NotAuthorized)
+        );
+        assert!(!conwaybets.markets[&market_id].is_resolved);
+
+        conwaybets.block.timestamp = 1_000_000_000 + 3_600;
+        assert_eq!(conwaybets.resolve_market(owner(9), resolution.clone()), Ok(true));
+        assert_eq!(conwaybets.markets[&market_id].winning_outcome, Some(1));
+        assert_eq!(conwaybets.resolve_market(owner(1), resolution), Ok(false));
+    }
+
+    #[test]
+    fn test_streaks_follow_resolution_order() {
+        let mut conwaybets = funded();
+        // (resolved_at, won) per market, created in a different order than resolved.
+        let outcomes = [(50, true), (10, true), (30, true), (20, false), (40, true), (60, false)];
+        for (resolved_at, won) in outcomes {
+            let market_id = create_test_market(&mut conwaybets, market_data());
+            conwaybets
+                .place_bet(market_id, owner(2), 0, Amount::from_tokens(1))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+            conwaybets.block.timestamp = resolved_at;
+            let winning_outcome = if won { 0 } else { 1 };
+            let resolution = resolution(market_id, winning_outcome);
+            conwaybets.resolve_market(owner(1), resolution).unwrap();
+        }
+
+        // Ordered by time: W L W W W L
+        assert_eq!(
+            conwaybets.streaks(owner(2)),
+            StreakStats { current_wins: 0, current_losses: 1, longest_wins: 3, longest_losses: 1 }
+        );
+        assert_eq!(conwaybets.streaks(owner(3)), StreakStats::default());
+    }
+
+    #[test]
+    fn test_any_of_resolution_splits_pool_across_winning_outcomes() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            outcomes: vec!["Rain".to_string(), "Snow".to_string(), "Dry".to_string()],
+            ..market_data()
+        });
+        for (user, outcome, tokens) in [(2, 0, 10), (3, 1, 30), (4, 2, 60)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        assert_eq!(
+            conwaybets.resolve_market_any_of(owner(1), market_id, BTreeSet::new()),
+            Err(ConwayBetsError::EmptyResolution)
+        );
+        assert_eq!(
+            conwaybets.resolve_market_any_of(owner(1), market_id, BTreeSet::from([0, 3])),
+            Err(ConwayBetsError::OutcomeOutOfRange)
+        );
+        assert_eq!(
+            conwaybets.resolve_market_any_of(owner(1), market_id, BTreeSet::from([0, 1])),
+            Ok(true)
+        );
+
+        let market = &conwaybets.markets[&market_id];
+        assert_eq!(market.winning_outcome, None);
+        assert!(market.is_winning(0) && market.is_winning(1) && !market.is_winning(2));
+        assert_eq!(conwaybets.payout(owner(2), &market_id), Amount::from_tokens(25));
+        assert_eq!(conwaybets.payout(owner(3), &market_id), Amount::from_tokens(75));
+        assert_eq!(conwaybets.payout(owner(4), &market_id), Amount::ZERO);
+    }
+
+    // Bets need funds, and only the faucet puts them in through the log.
+    #[cfg(all(feature = "debug-log", feature = "faucet"))]
+    #[test]
+    fn test_replay_reproduces_live_state() {
+        let mut live = ConwayBets::default();
+        live.config.owner = Some(owner(9));
+        for user in [2, 3] {
+            let credit = Operation::Credit { user: owner(user), amount: Amount::from_tokens(3) };
+            live.execute(Some(owner(9)), credit).now_or_never().unwrap().unwrap();
+        }
+        live.execute(Some(owner(1)), Operation::CreateMarket { creator: owner(1), data: market_data() })
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let market_id = MarketId { chain_id: live.block.chain_id, id: live.next_market_id };
+        for (user, outcome_index) in [(2, 0), (3, 1)] {
+            live.execute(Some(owner(user)), Operation::PlaceBet {
+                market_id,
+                user: owner(user),
+                outcome_index,
+                amount: Amount::from_tokens(3),
+            })
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        }
+        live.block.timestamp = 42;
+        live.execute(Some(owner(1)), Operation::ResolveMarket {
+            resolver: owner(1),
+            resolution: resolution(market_id, 1),
+        })
+        .now_or_never()
+        .unwrap()
+        .unwrap();
+        assert_eq!(live.operation_log.len(), 6);
+
+        let replayed = ConwayBets::replay(&live.config, &live.operation_log).now_or_never().unwrap();
+        assert_eq!(
+            replayed.markets[&market_id].state_hash,
+            live.markets[&market_id].state_hash
+        );
+        assert_eq!(replayed.markets[&market_id].resolved_at, Some(42));
+        assert_eq!(bcs::to_bytes(&replayed).unwrap(), bcs::to_bytes(&live).unwrap());
+    }
+
+    #[test]
+    fn test_liquidity_for_price_move_reaches_target_on_lmsr_market() {
+        let mut conwaybets = ConwayBets::default();
+        let plain = create_test_market(&mut conwaybets, market_data());
+        assert_eq!(
+            conwaybets.liquidity_for_price_move(plain, 0, 0.75),
+            Err(ConwayBetsError::NotAmmMarket)
+        );
+
+        let b = Amount::from_tokens(100);
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            lmsr_b: Some(b),
+            ..market_data()
+        });
+        assert_eq!(
+            conwaybets.liquidity_for_price_move(market_id, 0, 0.4),
+            Err(ConwayBetsError::InvalidTargetProbability)
+        );
+
+        // From even odds, reaching 0.75 costs b * ln(2).
+        let amount = conwaybets.liquidity_for_price_move(market_id, 0, 0.75).unwrap();
+        let expected = 100.0 * 2f64.ln();
+        let tokens = u128::from(amount) as f64 / 1e18;
+        assert!((tokens - expected).abs() < 1e-6);
+
+        let b = u128::from(b) as f64;
+        let market = &conwaybets.markets[&market_id];
+        let mut pools = market.outcome_shares.clone();
+        pools[0] = pools[0].saturating_add(amm::shares_for_cost(&pools, 0, amount, b));
+        assert!((amm::price(&pools, b)[0] - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_failed_message_dead_letter_carries_correlation_id() {
+        let mut conwaybets = ConwayBets::default();
+        let unknown = MarketId { chain_id: chain(3), id: 99 };
+        let bet = BetMessage {
+            correlation_id: [5; 16],
+            market_id: unknown,
+            user: owner(2),
+            outcome_index: 0,
+            amount: Amount::from_tokens(1),
+            nonce: 1,
+            block_height: 1,
+        };
+        conwaybets.block.timestamp = 77;
+
+        conwaybets.execute_message(chain(9), ConwayBetsMessage::Bet(bet));
+
+        assert_eq!(conwaybets.dead_letters.len(), 1);
+        let dead_letter = &conwaybets.dead_letters[0];
+        assert_eq!(dead_letter.correlation_id, [5; 16]);
+        assert_eq!(dead_letter.origin, chain(9));
+        assert_eq!(dead_letter.received_at, 77);
+    }
+
+    #[test]
+    fn test_estimated_resolution_delay_is_historical_mean() {
+        let mut conwaybets = ConwayBets::default();
+        let sports = || MarketCreationData { category: Some("sports".to_string()), ..market_data() };
+
+        for (i, delay) in [100, 200].into_iter().enumerate() {
+            conwaybets.block.timestamp = 0;
+            let market_id = create_test_market(&mut conwaybets, sports());
+            conwaybets.block.timestamp = 1_000_000_000 + delay;
+            let resolution = resolution(market_id, i as u32 % 2);
+            conwaybets.resolve_market(owner(1), resolution).unwrap();
+        }
+        conwaybets.block.timestamp = 0;
+        let market_id = create_test_market(&mut conwaybets, sports());
+        assert_eq!(conwaybets.estimated_resolution_delay(&market_id), None);
+
+        let resolved = create_test_market(&mut conwaybets, sports());
+        conwaybets.block.timestamp = 1_000_000_000 + 600;
+        let resolution = resolution(resolved, 0);
+        conwaybets.resolve_market(owner(1), resolution).unwrap();
+
+        assert_eq!(conwaybets.estimated_resolution_delay(&market_id), Some(300));
+        let uncategorized = create_test_market(&mut conwaybets, market_data());
+        assert_eq!(conwaybets.estimated_resolution_delay(&uncategorized), Some(300));
+    }
+
+    #[test]
+    fn test_voided_outcome_stakes_are_refunded_at_par() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            outcomes: vec!["Home".to_string(), "Away".to_string(), "Draw".to_string()],
+            ..market_data()
+        });
+        for (user, outcome, tokens) in [(2, 0, 20), (3, 0, 20), (4, 1, 40), (5, 2, 20)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        let mut voided = resolution(market_id, 0);
+        voided.voided_outcomes = BTreeSet::from([0]);
+        assert_eq!(conwaybets.resolve_market(owner(1), voided), Err(ConwayBetsError::VoidedWinner));
+
+        let mut voided = resolution(market_id, 0);
+        voided.voided_outcomes = BTreeSet::from([2]);
+        assert_eq!(conwaybets.resolve_market(owner(1), voided), Ok(true));
+
+        // Pool of 100 less the 20 voided: winners split 80 evenly.
+        assert_eq!(conwaybets.payout(owner(2), &market_id), Amount::from_tokens(40));
+        assert_eq!(conwaybets.payout(owner(3), &market_id), Amount::from_tokens(40));
+        assert_eq!(conwaybets.payout(owner(4), &market_id), Amount::ZERO);
+        assert_eq!(conwaybets.payout(owner(5), &market_id), Amount::from_tokens(20));
+
+        let settlement = conwaybets.settlement_summary(&market_id).unwrap();
+        assert_eq!(settlement.total_paid, Amount::from_tokens(100));
+        assert_eq!(settlement.winners, 2);
+    }
+
+    #[test]
+    fn test_market_creation_quota_resets_each_block() {
+        let mut conwaybets = ConwayBets::default();
+        conwaybets.config.max_markets_per_block = 2;
+        conwaybets.block.block_height = 5;
+
+        for _ in 0..2 {
+            conwaybets.create_market(owner(1), market_data()).now_or_never().unwrap().unwrap();
+        }
+        assert_eq!(
+            conwaybets.create_market(owner(1), market_data()).now_or_never().unwrap(),
+            Err(ConwayBetsError::BlockQuotaExceeded)
+        );
+        assert_eq!(conwaybets.markets.len(), 2);
+
+        conwaybets.block.block_height = 6;
+        conwaybets.create_market(owner(1), market_data()).now_or_never().unwrap().unwrap();
+        assert_eq!(conwaybets.markets.len(), 3);
+    }
+
+    #[test]
+    fn test_claimable_positions_exclude_markets_still_in_dispute_window() {
+        let mut conwaybets = funded();
+        let unlocked = create_test_market(&mut conwaybets, market_data());
+        let vesting = create_test_market(&mut conwaybets, MarketCreationData {
+            dispute_window_secs: 1_000,
+            ..market_data()
+        });
+        for market_id in [unlocked, vesting] {
+            for (user, outcome) in [(2, 0), (3, 1)] {
+                conwaybets
+                    .place_bet(market_id, owner(user), outcome, Amount::from_tokens(5))
+                    .now_or_never()
+                    .unwrap()
+                    .unwrap();
+            }
+            conwaybets.block.timestamp = 500;
+            conwaybets.resolve_market(owner(1), resolution(market_id, 0)).unwrap();
+        }
+
+        conwaybets.block.timestamp = 600;
+        let claimable = conwaybets.claimable_positions(10, None);
+        assert_eq!(claimable.len(), 1);
+        assert_eq!(claimable[0].market_id, unlocked);
+        assert_eq!(claimable[0].user, owner(2));
+        assert_eq!(claimable[0].amount, Amount::from_tokens(10));
+
+        conwaybets.block.timestamp = 1_500;
+        assert_eq!(conwaybets.claimable_positions(10, None).len(), 2);
+        let next_page = conwaybets.claimable_positions(1, Some(unlocked));
+        assert_eq!(next_page.len(), 1);
+        assert_eq!(next_page[0].market_id, vesting);
+    }
+
+    #[test]
+    fn test_delegated_resolver_gains_exclusive_authority() {
+        let mut conwaybets = ConwayBets::default();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+
+        assert_eq!(
+            conwaybets.delegate_resolver(owner(2), market_id, owner(2)),
+            Err(ConwayBetsError::NotAuthorized)
+        );
+
+        conwaybets.delegate_resolver(owner(1), market_id, owner(3)).unwrap();
+        let market = &conwaybets.markets[&market_id];
+        assert_eq!(market.resolver, Some(owner(3)));
+        assert_eq!(market.resolver_delegations.len(), 1);
+        assert_eq!(market.resolver_delegations[0].from, owner(1));
+
+        assert_eq!(
+            conwaybets.resolve_market(owner(1), resolution(market_id, 0)),
+            Err(ConwayBetsError::NotAuthorized)
+        );
+        assert_eq!(conwaybets.resolve_market(owner(3), resolution(market_id, 0)), Ok(true));
+    }
+
+    #[test]
+    fn test_min_bettors_gates_resolution_but_not_cancellation() {
+        let mut conwaybets = funded();
+        let data = || MarketCreationData { min_bettors_to_resolve: Some(2), ..market_data() };
+        let thin = create_test_market(&mut conwaybets, data());
+        let busy = create_test_market(&mut conwaybets, data());
+        for (market_id, users) in [(thin, vec![2, 2]), (busy, vec![2, 3])] {
+            for user in users {
+                conwaybets
+                    .place_bet(market_id, owner(user), 0, Amount::from_tokens(5))
+                    .now_or_never()
+                    .unwrap()
+                    .unwrap();
+            }
+        }
+
+        assert_eq!(
+            conwaybets.resolve_market(owner(1), resolution(thin, 0)),
+            Err(ConwayBetsError::NotEnoughBettors { required: 2, actual: 1 })
+        );
+        conwaybets.cancel_market(owner(1), thin).unwrap();
+        assert_eq!(conwaybets.payout(owner(2), &thin), Amount::from_tokens(10));
+        assert_eq!(
+            conwaybets.resolve_market(owner(1), resolution(thin, 0)),
+            Err(ConwayBetsError::MarketCancelled)
+        );
+
+        assert_eq!(conwaybets.resolve_market(owner(1), resolution(busy, 0)), Ok(true));
+    }
+
+    #[test]
+    fn test_market_state_size_grows_linearly_with_positions() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        let mut sizes = vec![conwaybets.market_state_size(&market_id).unwrap()];
+        for user in 2..12 {
+            conwaybets
+                .place_bet(market_id, owner(user), 0, Amount::from_tokens(1))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+            sizes.push(conwaybets.market_state_size(&market_id).unwrap());
+        }
+
+        let steps: Vec<usize> = sizes.windows(2).map(|w| w[1] - w[0]).collect();
+        assert!(steps.iter().all(|step| *step > 0));
+        let (min, max) = (steps.iter().min().unwrap(), steps.iter().max().unwrap());
+        // Only the length prefixes and liquidity encoding may vary a few bytes.
+        assert!(max - min <= 4);
+        assert!(conwaybets.market_state_size(&MarketId { chain_id: chain(5), id: 1 }).is_none());
+    }
+
+    #[test]
+    fn test_conditional_bet_only_executes_when_odds_allow() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        for (user, outcome, tokens) in [(2, 0, 30), (3, 1, 10)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        // "Yes" is priced at 0.75.
+        let error = conwaybets
+            .conditional_bet(owner(4), market_id, 0, Amount::from_tokens(5), 0.5)
+            .now_or_never()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.downcast_ref::<ConwayBetsError>(), Some(&ConwayBetsError::ConditionNotMet));
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::from_tokens(40));
+        assert!(!conwaybets.user_positions.contains_key(&owner(4)));
+
+        // "No" is priced at 0.25.
+        conwaybets
+            .conditional_bet(owner(4), market_id, 1, Amount::from_tokens(5), 0.5)
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::from_tokens(45));
+        assert_eq!(conwaybets.user_positions[&owner(4)].len(), 1);
+    }
+
+    #[test]
+    fn test_solvency_report_flags_corrupted_escrow() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        let staged = create_test_market(&mut conwaybets, MarketCreationData { min_confirmations: 2, ..market_data() });
+        conwaybets
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(10))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let bet = BetMessage {
+            correlation_id: [1; 16],
+            market_id: staged,
+            user: owner(3),
+            outcome_index: 1,
+            amount: Amount::from_tokens(4),
+            nonce: 1,
+            block_height: 1,
+        };
+        conwaybets.receive_bet(chain(9), bet).unwrap();
+        conwaybets.withdraw_bet(owner(2), market_id, 0).now_or_never().unwrap().unwrap();
+        conwaybets
+            .place_bet(market_id, owner(2), 1, Amount::from_tokens(6))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        let report = conwaybets.solvency_report();
+        assert_eq!(report.market_liquidity, Amount::from_tokens(6));
+        assert_eq!(report.reserved, Amount::from_tokens(4));
+        assert!(report.is_balanced());
+
+        conwaybets.escrow_balance = Amount::from_tokens(7);
+        let report = conwaybets.solvency_report();
+        assert!(!report.is_balanced());
+        assert_eq!(report.deficit, Amount::from_tokens(3));
+    }
+
+    #[test]
+    fn test_suspending_an_outcome_keeps_other_indices_stable() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            outcomes: vec!["A".to_string(), "B".to_string(), "C".to_string()],
+            ..market_data()
+        });
+        for (user, outcome) in [(2, 0), (3, 2)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(5))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        conwaybets.suspend_outcome(owner(1), market_id, 1).unwrap();
+        let error = conwaybets
+            .place_bet(market_id, owner(4), 1, Amount::from_tokens(5))
+            .now_or_never()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.downcast_ref::<ConwayBetsError>(), Some(&ConwayBetsError::OutcomeSuspended));
+
+        let market = &conwaybets.markets[&market_id];
+        assert_eq!(market.outcomes, vec!["A", "B", "C"]);
+        let label = |user: u8| {
+            let position = &conwaybets.user_positions[&owner(user)][0];
+            market.outcomes[position.outcome_index as usize].clone()
+        };
+        assert_eq!(label(2), "A");
+        assert_eq!(label(3), "C");
+
+        conwaybets.resolve_market(owner(1), resolution(market_id, 2)).unwrap();
+        assert_eq!(conwaybets.payout(owner(3), &market_id), Amount::from_tokens(10));
+        assert_eq!(conwaybets.payout(owner(2), &market_id), Amount::ZERO);
+    }
+
+    #[test]
+    fn test_position_proof_verifies_against_positions_root() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        for (user, outcome) in [(2, 0), (3, 1), (4, 0), (2, 1), (2, 0)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(3))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        let root = conwaybets.markets[&market_id].positions_root;
+
+        let proof = conwaybets.position_proof(&market_id, owner(4), 0).unwrap();
+        assert!(proof.verify(&root));
+        let proof = conwaybets.position_proof(&market_id, owner(2), 0).unwrap();
+        assert!(proof.verify(&root));
+
+        let mut tampered = proof.clone();
+        tampered.leaf[0] ^= 1;
+        assert!(!tampered.verify(&root));
+        assert!(conwaybets.position_proof(&market_id, owner(3), 0).is_none());
+    }
+
+    #[test]
+    fn test_claim_all_collects_only_claimable_markets() {
+        let mut conwaybets = funded();
+        let first = create_test_market(&mut conwaybets, market_data());
+        let second = create_test_market(&mut conwaybets, market_data());
+        let disputed = create_test_market(&mut conwaybets, MarketCreationData {
+            dispute_window_secs: 1_000,
+            ..market_data()
+        });
+        for market_id in [first, second, disputed] {
+            for (user, outcome) in [(2, 0), (3, 1)] {
+                conwaybets
+                    .place_bet(market_id, owner(user), outcome, Amount::from_tokens(5))
+                    .now_or_never()
+                    .unwrap()
+                    .unwrap();
+            }
+            conwaybets.block.timestamp = 500;
+            conwaybets.resolve_market(owner(1), resolution(market_id, 0)).unwrap();
+        }
+
+        conwaybets.block.timestamp = 600;
+        let batch = conwaybets.claim_all(owner(2), vec![first, disputed, second]).now_or_never().unwrap();
+        assert_eq!(batch.total, Amount::from_tokens(20));
+        assert_eq!(
+            batch.results,
+            vec![
+                (first, Ok(Amount::from_tokens(10))),
+                (disputed, Err(ConwayBetsError::ClaimsNotOpen)),
+                (second, Ok(Amount::from_tokens(10))),
+            ]
+        );
+        assert_eq!(conwaybets.claim_winnings(owner(2), first).now_or_never().unwrap(), Ok(Amount::ZERO));
+        assert_eq!(conwaybets.claimable_positions(10, None).len(), 0);
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_built_place_bet_operation_round_trips_and_executes() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        let operation = build_place_bet_operation(market_id, owner(2), 1, Amount::from_tokens(3));
+
+        let bytes = bcs::to_bytes(&operation).unwrap();
+        let decoded: Operation = bcs::from_bytes(&bytes).unwrap();
+        conwaybets.execute(Some(owner(2)), decoded).now_or_never().unwrap().unwrap();
+
+        let positions = &conwaybets.user_positions[&owner(2)];
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].market_id, market_id);
+        assert_eq!(positions[0].outcome_index, 1);
+        assert_eq!(positions[0].amount, Amount::from_tokens(3));
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::from_tokens(3));
+    }
+
+    #[test]
+    fn test_auto_b_adjustment_keeps_prices_continuous() {
+        let mut conwaybets = funded();
+        let b0 = Amount::from_tokens(50);
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            lmsr_b: Some(b0),
+            lmsr_b_growth_bps: 5_000,
+            ..market_data()
+        });
+        conwaybets
+            .place_bet(market_id, owner(2), 1, Amount::from_tokens(10))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        let market = &conwaybets.markets[&market_id];
+        let b_before = market.lmsr_b.unwrap();
+        assert_eq!(b_before, Amount::from_tokens(55));
+        let mut pools = market.outcome_shares.clone();
+        pools[0] = pools[0].saturating_add(market.fill(0, Amount::from_tokens(200)));
+        let unadjusted = amm::price(&pools, u128::from(b_before) as f64);
+
+        conwaybets
+            .place_bet(market_id, owner(3), 0, Amount::from_tokens(200))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let market = &conwaybets.markets[&market_id];
+        assert_eq!(market.lmsr_b, Some(Amount::from_tokens(155)));
+        for (adjusted, unadjusted) in market.implied_probabilities().iter().zip(&unadjusted) {
+            assert!((adjusted - unadjusted).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_resolution_distribution_tallies_winning_labels() {
+        let mut conwaybets = ConwayBets::default();
+        for winner in [0, 0, 1, 0] {
+            let market_id = create_test_market(&mut conwaybets, market_data());
+            conwaybets.resolve_market(owner(1), resolution(market_id, winner)).unwrap();
+        }
+        create_test_market(&mut conwaybets, market_data());
+
+        assert_eq!(
+            conwaybets.resolution_distribution(),
+            vec![("No".to_string(), 1), ("Yes".to_string(), 3)]
+        );
+    }
+
+    #[test]
+    fn test_fixed_odds_market_takes_bets_only_once_funded() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            min_pool_to_open: Amount::from_tokens(100),
+            ..market_data()
+        });
+        assert_eq!(conwaybets.markets[&market_id].status, MarketStatus::Funding);
+
+        let status = conwaybets
+            .fund_market(owner(1), market_id, Amount::from_tokens(60))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(status, MarketStatus::Funding);
+        let rejected = conwaybets
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(5))
+            .now_or_never()
+            .unwrap();
+        assert!(rejected.unwrap_err().to_string().contains("funded"));
+        assert!(conwaybets
+            .fund_market(owner(2), market_id, Amount::from_tokens(40))
+            .now_or_never()
+            .unwrap()
+            .is_err());
+
+        let status = conwaybets
+            .fund_market(owner(1), market_id, Amount::from_tokens(40))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(status, MarketStatus::Open);
+        conwaybets
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(5))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_exposure_by_event_aggregates_across_markets() {
+        let mut conwaybets = funded();
+        let event = MarketCreationData {
+            external_event_id: Some("final-2026".to_string()),
+            ..market_data()
+        };
+        let winner = create_test_market(&mut conwaybets, event.clone());
+        let margin = create_test_market(&mut conwaybets, event);
+        let unrelated = create_test_market(&mut conwaybets, market_data());
+        for (market_id, outcome, tokens) in [(winner, 0, 4), (margin, 0, 6), (margin, 1, 2), (unrelated, 0, 9)] {
+            conwaybets
+                .place_bet(market_id, owner(2), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        let exposure = conwaybets.exposure_by_event(owner(2), "final-2026");
+        assert_eq!(exposure.len(), 2);
+        assert_eq!(exposure[0].label, "No");
+        assert_eq!(exposure[0].staked, Amount::from_tokens(2));
+        assert_eq!(exposure[1].label, "Yes");
+        assert_eq!(exposure[1].staked, Amount::from_tokens(10));
+        assert_eq!(exposure[1].markets, 2);
+        assert!(conwaybets.exposure_by_event(owner(3), "final-2026").is_empty());
+    }
+
+    #[test]
+    fn test_bets_wait_for_first_bet_delay() {
+        let mut conwaybets = funded();
+        conwaybets.block.timestamp = 1_000;
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            first_bet_delay_secs: 300,
+            ..market_data()
+        });
+        assert_eq!(conwaybets.markets[&market_id].created_at, 1_000);
+
+        let rejected = conwaybets
+            .place_bet(market_id, owner(1), 0, Amount::from_tokens(5))
+            .now_or_never()
+            .unwrap();
+        assert!(rejected.is_err());
+
+        conwaybets.block.timestamp = 1_300;
+        conwaybets
+            .place_bet(market_id, owner(1), 0, Amount::from_tokens(5))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_bet_draws_on_credited_balance() {
+        let mut conwaybets = ConwayBets::default();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        assert_eq!(conwaybets.credit(owner(2), Amount::from_tokens(8)), Amount::from_tokens(8));
+
+        conwaybets
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(5))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(conwaybets.balances[&owner(2)], Amount::from_tokens(3));
+        assert_eq!(conwaybets.escrow_balance, Amount::from_tokens(5));
+        assert_eq!(conwaybets.user_positions[&owner(2)][0].amount, Amount::from_tokens(5));
+
+        for (user, tokens) in [(2, 4), (3, 1)] {
+            let bet = conwaybets.place_bet(market_id, owner(user), 0, Amount::from_tokens(tokens));
+            assert_eq!(bet.now_or_never().unwrap().unwrap_err(), ConwayBetsError::InsufficientFunds);
+        }
+        assert_eq!(conwaybets.balances[&owner(2)], Amount::from_tokens(3));
+        assert_eq!(conwaybets.escrow_balance, Amount::from_tokens(5));
+        assert_eq!(conwaybets.user_positions[&owner(2)].len(), 1);
+    }
+
+    #[test]
+    fn test_price_history_is_windowed_and_downsampled() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        for step in 1..=10 {
+            conwaybets.block.timestamp = step * 100;
+            conwaybets
+                .place_bet(market_id, owner(2), 0, Amount::from_tokens(1))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        conwaybets
+            .place_bet(market_id, owner(3), 1, Amount::from_tokens(10))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        let window = conwaybets.price_history(&market_id, 0, 200, 900, 100);
+        assert_eq!(window.len(), 8);
+        assert!(window.iter().all(|(t, p)| (200..=900).contains(t) && *p == 1.0));
+
+        let thinned = conwaybets.price_history(&market_id, 1, 0, 1_000, 4);
+        let times: Vec<u64> = thinned.iter().map(|(t, _)| *t).collect();
+        assert_eq!(times, vec![100, 400, 700, 1_000]);
+        assert_eq!(thinned[3].1, 0.5);
+    }
+
+    #[test]
+    fn test_fee_discount_applies_once_volume_tier_is_crossed() {
+        let mut conwaybets = funded();
+        conwaybets.config.fee_basis_points = 200;
+        conwaybets.config.volume_fee_tiers = vec![FeeTier {
+            min_volume: Amount::from_tokens(100),
+            fee_basis_points: 50,
+        }];
+        let market_id = create_test_market(&mut conwaybets, market_data());
+
+        for _ in 0..2 {
+            conwaybets
+                .place_bet(market_id, owner(2), 0, Amount::from_tokens(60))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        // The second bet still pays full fee: volume before it was only 60.
+        assert_eq!(conwaybets.treasury, Amount::from_millis(2_400));
+        assert_eq!(conwaybets.fee_basis_points_for(owner(2)), 50);
+
+        conwaybets
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(100))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert_eq!(conwaybets.treasury, Amount::from_millis(2_900));
+        assert_eq!(conwaybets.user_positions[&owner(2)][2].amount, Amount::from_millis(99_500));
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_configure_takes_the_fee_from_the_parameters_and_rejects_conflicts() {
+        let mut conwaybets = ConwayBets::default();
+        let config = |fee_basis_points| Config { max_events: 5, fee_basis_points, ..Config::default() };
+
+        assert_eq!(conwaybets.configure(config(0), 150), Ok(()));
+        assert_eq!((conwaybets.config.fee_basis_points, conwaybets.config.max_events), (150, 5));
+        assert_eq!(conwaybets.configure(config(150), 150), Ok(()));
+
+        assert_eq!(conwaybets.configure(config(300), 150), Err(ConwayBetsError::FeeMismatch));
+        assert_eq!(conwaybets.configure(config(0), 10_000), Err(ConwayBetsError::FeeTooHigh));
+        let tiers = vec![FeeTier { min_volume: Amount::from_tokens(1), fee_basis_points: 10_000 }];
+        let expensive_tier = Config { volume_fee_tiers: tiers, ..config(0) };
+        assert_eq!(conwaybets.configure(expensive_tier, 150), Err(ConwayBetsError::FeeTooHigh));
+        assert_eq!(conwaybets.config.fee_basis_points, 150);
+        assert!(conwaybets.config.volume_fee_tiers.is_empty());
+    }
+
+    #[test]
+    fn test_stale_sync_flags_divergence_until_hashes_match() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        let stale_hash = conwaybets.markets[&market_id].state_hash;
+        conwaybets
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(5))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let current_hash = conwaybets.markets[&market_id].state_hash;
+
+        conwaybets.execute_message(chain(4), ConwayBetsMessage::SyncState {
+            correlation_id: [1; 16],
+            market_id,
+            state_hash: stale_hash,
+            block_height: 7,
+        });
+        let report = conwaybets.divergent_markets();
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].market_id, market_id);
+        assert_eq!(report[0].local_hash, current_hash);
+        assert_eq!(report[0].remote_hash, stale_hash);
+        assert_eq!(report[0].reporting_chain, chain(4));
+
+        conwaybets.execute_message(chain(4), ConwayBetsMessage::SyncState {
+            correlation_id: [2; 16],
+            market_id,
+            state_hash: current_hash,
+            block_height: 8,
+        });
+        assert!(conwaybets.divergent_markets().is_empty());
+    }
+
+    #[test]
+    fn test_scalar_resolution_maps_value_to_bucket() {
+        let mut conwaybets = ConwayBets::default();
+        let scalar = MarketCreationData {
+            outcomes: vec!["<25".to_string(), "25-50".to_string(), "50-75".to_string(), ">=75".to_string()],
+            scalar_range: Some(ScalarRange { min: 0, max: 100 }),
+            ..market_data()
+        };
+        let middle = create_test_market(&mut conwaybets, scalar.clone());
+        let clamped = create_test_market(&mut conwaybets, scalar);
+        let binary = create_test_market(&mut conwaybets, market_data());
+
+        assert_eq!(conwaybets.resolve_scalar(owner(1), middle, 50), Ok(true));
+        assert_eq!(conwaybets.markets[&middle].winning_outcome, Some(2));
+        assert_eq!(conwaybets.markets[&middle].resolved_value, Some(50));
+
+        assert_eq!(conwaybets.resolve_scalar(owner(1), clamped, -40), Ok(true));
+        assert_eq!(conwaybets.markets[&clamped].winning_outcome, Some(0));
+        assert_eq!(ScalarRange { min: 0, max: 100 }.bucket(i64::MAX, 4), 3);
+
+        assert_eq!(
+            conwaybets.resolve_scalar(owner(1), binary, 1),
+            Err(ConwayBetsError::NotScalarMarket)
+        );
+    }
+
+    #[test]
+    fn test_merge_markets_moves_positions_and_liquidity() {
+        let mut conwaybets = funded();
+        conwaybets.config.owner = Some(owner(9));
+        let source = create_test_market(&mut conwaybets, market_data());
+        let target = create_test_market(&mut conwaybets, market_data());
+        let mismatched = create_test_market(&mut conwaybets, MarketCreationData {
+            outcomes: vec!["Home".to_string(), "Away".to_string()],
+            ..market_data()
+        });
+        for (market_id, user, outcome, tokens) in [(source, 2, 0, 4), (source, 3, 1, 6), (target, 2, 1, 5)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        assert_eq!(
+            conwaybets.merge_markets(owner(1), source, target),
+            Err(ConwayBetsError::NotAuthorized)
+        );
+        assert_eq!(
+            conwaybets.merge_markets(owner(9), source, mismatched),
+            Err(ConwayBetsError::OutcomesMismatch)
+        );
+        conwaybets.merge_markets(owner(9), source, target).unwrap();
+
+        let merged = &conwaybets.markets[&target];
+        assert_eq!(merged.total_liquidity, Amount::from_tokens(15));
+        assert_eq!(merged.outcome_shares, vec![Amount::from_tokens(4), Amount::from_tokens(11)]);
+        assert_eq!(merged.merged_from, vec![source]);
+        assert_eq!(conwaybets.market_bettors[&target].len(), 2);
+        assert!(conwaybets.user_positions.values().flatten().all(|p| p.market_id == target));
+
+        let cancelled = &conwaybets.markets[&source];
+        assert!(cancelled.is_cancelled);
+        assert_eq!(cancelled.merged_into, Some(target));
+        assert_eq!(cancelled.total_liquidity, Amount::ZERO);
+        assert_eq!(conwaybets.creator_markets[&owner(1)], vec![target, mismatched]);
+        let last = conwaybets.events_since(0).last().map(|e| e.event.clone());
+        assert_eq!(last, Some(ConwayBetsEvent::MarketCancelled { market_id: source }));
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_estimate_cost_grows_with_claim_all_batch() {
+        let mut conwaybets = funded();
+        let market_ids: Vec<MarketId> = (0..3).map(|_| create_test_market(&mut conwaybets, market_data())).collect();
+        for market_id in &market_ids {
+            conwaybets
+                .place_bet(*market_id, owner(2), 0, Amount::from_tokens(1))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        let single = conwaybets.estimate_cost(&build_claim_winnings_operation(owner(2), market_ids[0]));
+        let batch = conwaybets.estimate_cost(&build_claim_all_operation(owner(2), market_ids));
+        assert_eq!(single, CostEstimate { markets_touched: 1, positions_scanned: 3, messages_emitted: 0 });
+        assert_eq!(batch.markets_touched, 3);
+        assert!(batch.total() > single.total());
+    }
+
+    fn overturned_market(post_dispute: PostDisputeAction) -> (ConwayBets, MarketId) {
+        let mut conwaybets = funded();
+        conwaybets.config.owner = Some(owner(9));
+        conwaybets.config.post_dispute = post_dispute;
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            dispute_window_secs: 1_000,
+            ..market_data()
+        });
+        for (user, outcome) in [(2, 0), (3, 1)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(5))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        conwaybets.block.timestamp = 500;
+        conwaybets.resolve_market(owner(1), resolution(market_id, 0)).unwrap();
+        conwaybets.challenge_resolution(owner(3), market_id).unwrap();
+        conwaybets.block.timestamp = 2_000;
+        assert!(!conwaybets.markets[&market_id].claims_open(2_000));
+        conwaybets.rule_on_dispute(owner(9), market_id, true).unwrap();
+        (conwaybets, market_id)
+    }
+
+    #[test]
+    fn test_overturned_resolution_reopens_for_resolver() {
+        let (mut conwaybets, market_id) = overturned_market(PostDisputeAction::ReopenForResolution);
+        let market = &conwaybets.markets[&market_id];
+        assert!(!market.is_resolved && !market.is_cancelled);
+        assert_eq!(
+            conwaybets.claim_winnings(owner(2), market_id).now_or_never().unwrap(),
+            Err(ConwayBetsError::ClaimsNotOpen)
+        );
+
+        conwaybets.resolve_market(owner(1), resolution(market_id, 1)).unwrap();
+        conwaybets.block.timestamp = 3_000;
+        assert_eq!(
+            conwaybets.claim_winnings(owner(3), market_id).now_or_never().unwrap(),
+            Ok(Amount::from_tokens(10))
+        );
+    }
+
+    #[test]
+    fn test_overturned_resolution_cancels_market() {
+        let (mut conwaybets, market_id) = overturned_market(PostDisputeAction::Cancel);
+        let market = &conwaybets.markets[&market_id];
+        assert!(!market.is_resolved && market.is_cancelled);
+        assert!(!conwaybets.creator_markets.contains_key(&owner(1)));
+        assert_eq!(
+            conwaybets.claim_winnings(owner(2), market_id).now_or_never().unwrap(),
+            Ok(Amount::from_tokens(5))
+        );
+        assert_eq!(
+            conwaybets.claim_winnings(owner(3), market_id).now_or_never().unwrap(),
+            Ok(Amount::from_tokens(5))
+        );
+    }
+
+    #[test]
+    fn test_open_positions_skip_resolved_markets() {
+        let mut conwaybets = funded();
+        let open = create_test_market(&mut conwaybets, market_data());
+        let resolved = create_test_market(&mut conwaybets, market_data());
+        let bets = [(open, 2, 0, 3), (open, 3, 1, 1), (open, 2, 0, 1), (resolved, 2, 0, 5)];
+        for (market_id, user, outcome, tokens) in bets {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        conwaybets.resolve_market(owner(1), resolution(resolved, 0)).unwrap();
+
+        let positions = conwaybets.open_positions(owner(2));
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].market_id, open);
+        assert_eq!(positions[0].label, "Yes");
+        assert_eq!(positions[0].staked, Amount::from_tokens(4));
+        assert_eq!(positions[0].implied_probability, 0.8);
+        assert_eq!(positions[0].value, Amount::from_millis(3_200));
+    }
+
+    #[test]
+    fn test_bet_after_same_block_resolution_is_rejected() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        conwaybets.block = BlockContext { timestamp: 500, block_height: 12, ..BlockContext::default() };
+        let resolve = Operation::ResolveMarket { resolver: owner(1), resolution: resolution(market_id, 0) };
+        conwaybets.execute(Some(owner(1)), resolve).now_or_never().unwrap().unwrap();
+
+        let late = conwaybets
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(5))
+            .now_or_never()
+            .unwrap();
+        assert_eq!(late.unwrap_err(), ConwayBetsError::AlreadyResolved);
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::ZERO);
+        assert_eq!(conwaybets.escrow_balance, Amount::ZERO);
+    }
+
+    #[test]
+    fn test_fee_report_buckets_fees_by_time() {
+        let mut conwaybets = funded();
+        conwaybets.config.fee_basis_points = 100;
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        for (timestamp, tokens) in [(100, 10), (150, 20), (250, 40), (420, 50)] {
+            conwaybets.block.timestamp = timestamp;
+            conwaybets
+                .place_bet(market_id, owner(2), 0, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        assert_eq!(
+            conwaybets.fee_report(100, 400, 100),
+            vec![
+                (100, Amount::from_millis(300)),
+                (200, Amount::from_millis(400)),
+                (300, Amount::ZERO),
+            ]
+        );
+        assert_eq!(conwaybets.fee_report(150, 300, 200), vec![(150, Amount::from_millis(600))]);
+        assert!(conwaybets.fee_report(400, 400, 100).is_empty());
+    }
+
+    #[test]
+    fn test_committed_bet_claim_rejects_reused_nullifier() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        let secret = [7; 32];
+        conwaybets
+            .place_committed_bet(owner(2), market_id, 0, Amount::from_tokens(5), privacy::commitment(&secret))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        conwaybets
+            .place_bet(market_id, owner(3), 1, Amount::from_tokens(5))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert!(conwaybets.user_positions.get(&owner(2)).is_none());
+
+        conwaybets.resolve_market(owner(1), resolution(market_id, 0)).unwrap();
+        assert_eq!(
+            conwaybets.claim_committed(owner(4), market_id, [8; 32]).now_or_never().unwrap(),
+            Err(ConwayBetsError::UnknownCommitment)
+        );
+        assert_eq!(
+            conwaybets.claim_committed(owner(4), market_id, secret).now_or_never().unwrap(),
+            Ok(Amount::from_tokens(10))
+        );
+        assert_eq!(
+            conwaybets.claim_committed(owner(4), market_id, secret).now_or_never().unwrap(),
+            Err(ConwayBetsError::NullifierSpent)
+        );
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_suspensions_leave_at_least_two_live_outcomes() {
+        let mut conwaybets = ConwayBets::default();
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            outcomes: ["A", "B", "C", "D"].map(String::from).to_vec(),
+            ..market_data()
+        });
+        conwaybets.suspend_outcome(owner(1), market_id, 0).unwrap();
+        conwaybets.suspend_outcome(owner(1), market_id, 2).unwrap();
+        conwaybets.suspend_outcome(owner(1), market_id, 2).unwrap();
+
+        assert_eq!(
+            conwaybets.suspend_outcome(owner(1), market_id, 3),
+            Err(ConwayBetsError::TooManySuspensions { min_live: 2 })
+        );
+        assert_eq!(conwaybets.markets[&market_id].suspended_outcomes, BTreeSet::from([0, 2]));
+    }
+
+    #[test]
+    fn test_time_to_close_counts_down_past_zero() {
+        let mut conwaybets = ConwayBets::default();
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData { end_time: 1_000, ..market_data() });
+        assert_eq!(conwaybets.time_to_close(&market_id, 400), Some(600));
+        assert_eq!(conwaybets.time_to_close(&market_id, 1_250), Some(-250));
+
+        conwaybets.cancel_market(owner(1), market_id).unwrap();
+        assert_eq!(conwaybets.time_to_close(&market_id, 400), None);
+    }
+
+    #[test]
+    fn test_pending_bet_past_ttl_expires_and_releases_reserve() {
+        let mut conwaybets = ConwayBets::default();
+        conwaybets.config.pending_bet_ttl_secs = Some(600);
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData { min_confirmations: 3, ..market_data() });
+        for (nonce, received_at) in [(1, 100), (2, 500)] {
+            conwaybets.block.timestamp = received_at;
+            let bet = BetMessage {
+                correlation_id: [nonce as u8; 16],
+                market_id,
+                user: owner(2),
+                outcome_index: 0,
+                amount: Amount::from_tokens(4),
+                nonce,
+                block_height: 1,
+            };
+            conwaybets.receive_bet(chain(5), bet).unwrap();
+        }
+
+        conwaybets.block.timestamp = 800;
+        assert_eq!(conwaybets.expire_pending_bets(), 1);
+        assert!(conwaybets.pending_bets.contains_key(&(chain(5), 2)));
+        assert_eq!(conwaybets.solvency_report().reserved, Amount::from_tokens(4));
+        assert_eq!(conwaybets.escrow_balance, Amount::from_tokens(4));
+        assert!(conwaybets.solvency_report().is_balanced());
+        assert_eq!(conwaybets.dead_letters.len(), 1);
+        assert_eq!(conwaybets.dead_letters[0].correlation_id, [1; 16]);
+        assert!(conwaybets.dead_letters[0].reason.contains("timed out"));
+        assert_eq!(conwaybets.confirm_bet(chain(5), 1, 10), None);
+    }
+
+    #[test]
+    fn test_recent_activity_outranks_old_activity() {
+        let mut conwaybets = funded();
+        let old = create_test_market(&mut conwaybets, market_data());
+        let recent = create_test_market(&mut conwaybets, market_data());
+        let idle = create_test_market(&mut conwaybets, market_data());
+        for _ in 0..4 {
+            conwaybets
+                .place_bet(old, owner(2), 0, Amount::from_tokens(1))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        conwaybets.block.timestamp = 4 * 3_600;
+        conwaybets
+            .place_bet(recent, owner(2), 0, Amount::from_tokens(1))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        let trending: Vec<MarketId> = conwaybets.trending_markets(10, 4 * 3_600).iter().map(|m| m.id).collect();
+        assert_eq!(trending, vec![recent, old, idle]);
+        assert!((conwaybets.markets[&old].trending_score(4 * 3_600, 3_600) - 0.25).abs() < 1e-9);
+        assert_eq!(conwaybets.trending_markets(1, 4 * 3_600).len(), 1);
+    }
+
+    #[test]
+    fn test_thin_outcomes_report_no_odds() {
+        let mut conwaybets = funded();
+        conwaybets.config.min_liquidity_for_odds = Amount::from_tokens(1);
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            outcomes: ["A", "B", "C"].map(String::from).to_vec(),
+            ..market_data()
+        });
+        for (outcome, attos) in [(0, 3_000_000_000_000_000_000), (1, 1_000_000_000_000_000_000), (2, 5)] {
+            conwaybets
+                .place_bet(market_id, owner(2), outcome, Amount::from_attos(attos))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        let odds = conwaybets.odds(&market_id).unwrap();
+        assert!((odds[0].unwrap() - 0.75).abs() < 1e-9);
+        assert!((odds[1].unwrap() - 0.25).abs() < 1e-9);
+        assert_eq!(odds[2], None);
+    }
+
+    #[test]
+    fn test_split_market_migrates_positions_into_binaries() {
+        let mut conwaybets = funded();
+        let parent = create_test_market(&mut conwaybets, MarketCreationData {
+            outcomes: ["Red", "Green", "Blue"].map(String::from).to_vec(),
+            ..market_data()
+        });
+        for (user, outcome, tokens) in [(2, 0, 4), (3, 2, 6), (2, 2, 1)] {
+            conwaybets
+                .place_bet(parent, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(
+            conwaybets.split_market(owner(2), parent).now_or_never().unwrap(),
+            Err(ConwayBetsError::NotAuthorized)
+        );
+
+        let binaries = conwaybets.split_market(owner(1), parent).now_or_never().unwrap().unwrap();
+        assert_eq!(binaries.len(), 3);
+        let liquidity: Vec<Amount> = binaries.iter().map(|id| conwaybets.markets[id].total_liquidity).collect();
+        assert_eq!(liquidity, vec![Amount::from_tokens(4), Amount::ZERO, Amount::from_tokens(7)]);
+        let blue = &conwaybets.markets[&binaries[2]];
+        assert_eq!(blue.outcomes, vec!["Blue".to_string(), "Not Blue".to_string()]);
+        assert_eq!(blue.depends_on, Some(parent));
+        assert_eq!(conwaybets.market_bettors[&binaries[2]].len(), 2);
+        assert_eq!(conwaybets.market_bettors[&binaries[1]].len(), 2);
+        assert!(conwaybets.user_positions.values().flatten().all(|p| binaries.contains(&p.market_id)));
+        // owner(3) backs Blue, Not Red and Not Green; only Blue holds the stake.
+        let sides: Vec<(MarketId, u32, Amount)> = conwaybets.user_positions[&owner(3)]
+            .iter()
+            .map(|p| (p.market_id, p.outcome_index, p.amount))
+            .collect();
+        assert_eq!(
+            sides,
+            vec![
+                (binaries[2], 0, Amount::from_tokens(6)),
+                (binaries[0], 1, Amount::ZERO),
+                (binaries[1], 1, Amount::ZERO),
+            ]
+        );
+
+        let frozen = &conwaybets.markets[&parent];
+        assert_eq!(frozen.status, MarketStatus::Frozen);
+        assert_eq!(frozen.total_liquidity, Amount::ZERO);
+        assert!(conwaybets
+            .place_bet(parent, owner(2), 0, Amount::from_tokens(1))
+            .now_or_never()
+            .unwrap()
+            .is_err());
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_resolve_market_reports_whether_it_settled() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        conwaybets
+            .place_bet(market_id, owner(2), 1, Amount::from_tokens(5))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let unknown = MarketId { chain_id: chain(3), id: 42 };
+
+        assert_eq!(
+            conwaybets.resolve_market(owner(1), resolution(unknown, 0)),
+            Err(ConwayBetsError::MarketNotFound)
+        );
+        assert_eq!(
+            conwaybets.resolve_market(owner(1), resolution(market_id, 2)),
+            Err(ConwayBetsError::OutcomeOutOfRange)
+        );
+        assert_eq!(conwaybets.resolve_market(owner(1), resolution(market_id, 1)), Ok(true));
+        let market = &conwaybets.markets[&market_id];
+        assert!(market.is_resolved);
+        assert_eq!(market.winning_outcome, Some(1));
+        assert_eq!(conwaybets.resolve_market(owner(1), resolution(market_id, 0)), Ok(false));
+        assert_eq!(conwaybets.markets[&market_id].winning_outcome, Some(1));
+    }
+
+    #[test]
+    fn test_net_odds_discount_gross_odds_by_fee() {
+        let mut conwaybets = funded();
+        conwaybets.config.fee_basis_points = 500;
+        conwaybets.config.volume_fee_tiers = vec![FeeTier { min_volume: Amount::from_tokens(10), fee_basis_points: 100 }];
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        for (user, outcome, tokens) in [(2, 0, 20), (3, 1, 20)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        let gross = 1.0 / conwaybets.markets[&market_id].implied_probabilities()[0];
+        assert!((gross - 2.0).abs() < 1e-9);
+        let net = conwaybets.net_odds(&market_id, 0, None).unwrap();
+        assert!((net - 1.9).abs() < 1e-9);
+        let discounted = conwaybets.net_odds(&market_id, 0, Some(owner(2))).unwrap();
+        assert!((discounted - 1.98).abs() < 1e-9);
+        assert_eq!(conwaybets.net_odds(&market_id, 5, None), None);
+    }
+
+    #[test]
+    fn test_claim_winnings_pays_pro_rata_share_once() {
+        let mut conwaybets = ConwayBets::default();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        for (user, outcome, attos) in [(2, 0, 1), (3, 0, 2), (4, 1, 8)] {
+            conwaybets.credit(owner(user), Amount::from_attos(attos));
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_attos(attos))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(
+            conwaybets.claim_winnings(owner(2), market_id).now_or_never().unwrap(),
+            Err(ConwayBetsError::ClaimsNotOpen)
+        );
+
+        conwaybets.resolve_market(owner(1), resolution(market_id, 0)).unwrap();
+        let first = conwaybets.claim_winnings(owner(2), market_id).now_or_never().unwrap();
+        let second = conwaybets.claim_winnings(owner(3), market_id).now_or_never().unwrap();
+        // 11 attos split 1:2 floors to 3 and 7, leaving 1 atto of dust.
+        assert_eq!(first, Ok(Amount::from_attos(3)));
+        assert_eq!(second, Ok(Amount::from_attos(7)));
+        assert_eq!(conwaybets.balances[&owner(2)], Amount::from_attos(3));
+        assert_eq!(conwaybets.balances[&owner(3)], Amount::from_attos(7));
+        assert_eq!(conwaybets.claim_winnings(owner(2), market_id).now_or_never().unwrap(), Ok(Amount::ZERO));
+        assert_eq!(conwaybets.claim_winnings(owner(4), market_id).now_or_never().unwrap(), Ok(Amount::ZERO));
+        assert!(conwaybets.user_positions[&owner(2)][0].claimed);
+        assert_eq!(conwaybets.escrow_balance, Amount::from_attos(1));
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_markets_awaiting_approval_take_no_bets_until_approved() {
+        let mut conwaybets = funded();
+        conwaybets.config.require_market_approval = true;
+        conwaybets.config.moderators.insert(owner(9));
+        conwaybets.config.creation_deposit = Amount::from_tokens(10);
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        assert_eq!(conwaybets.markets[&market_id].status, MarketStatus::PendingApproval);
+
+        let rejected = conwaybets
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(5))
+            .now_or_never()
+            .unwrap();
+        assert!(rejected.unwrap_err().to_string().contains("approval"));
+        assert_eq!(conwaybets.approve_market(owner(2), market_id), Err(ConwayBetsError::NotAuthorized));
+
+        assert_eq!(conwaybets.approve_market(owner(9), market_id), Ok(MarketStatus::Open));
+        assert_eq!(conwaybets.markets[&market_id].subsidy, Amount::from_tokens(10));
+        conwaybets
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(5))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_rejected_market_refunds_creation_deposit() {
+        let mut conwaybets = ConwayBets::default();
+        conwaybets.config.require_market_approval = true;
+        conwaybets.config.owner = Some(owner(9));
+        conwaybets.config.creation_deposit = Amount::from_tokens(10);
+        conwaybets.credit(owner(1), Amount::from_tokens(25));
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        assert_eq!(conwaybets.balances[&owner(1)], Amount::from_tokens(15));
+
+        assert_eq!(conwaybets.reject_market(owner(9), market_id), Ok(Amount::from_tokens(10)));
+        assert!(conwaybets.markets[&market_id].is_cancelled);
+        assert!(!conwaybets.creator_markets.contains_key(&owner(1)));
+        assert_eq!(conwaybets.balances[&owner(1)], Amount::from_tokens(25));
+        assert_eq!(
+            conwaybets.approve_market(owner(9), market_id),
+            Err(ConwayBetsError::NotPendingApproval)
+        );
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_stored_state_keeps_markets_positions_and_id_counters() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        conwaybets
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(5))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+
+        let bytes = bcs::to_bytes(&conwaybets).unwrap();
+        let mut reloaded: ConwayBets = bcs::from_bytes(&bytes).unwrap();
+        assert_eq!(reloaded.next_market_id, conwaybets.next_market_id);
+        assert_eq!(reloaded.next_bet_id, conwaybets.next_bet_id);
+        assert_eq!(reloaded.markets[&market_id].total_liquidity, Amount::from_tokens(5));
+        assert_eq!(reloaded.user_positions[&owner(2)].len(), 1);
+
+        let next = create_test_market(&mut reloaded, market_data());
+        assert_eq!(next.id, market_id.id + 1);
+    }
+
+    #[test]
+    fn test_break_even_outcomes_flags_profitable_hedge_legs() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        for (user, outcome, tokens) in [(2, 0, 10), (2, 1, 5), (3, 1, 30)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        // Yes pays 45 on 15 staked; No pays 5/35 of 45, under 7.
+        assert_eq!(conwaybets.break_even_outcomes(owner(2), market_id), vec![(0, true), (1, false)]);
+        assert_eq!(conwaybets.break_even_outcomes(owner(3), market_id), vec![(0, false), (1, true)]);
+        assert_eq!(conwaybets.break_even_outcomes(owner(4), market_id), vec![(0, false), (1, false)]);
+    }
+
+    #[test]
+    fn test_markets_are_created_on_the_executing_chain() {
+        let mut on_a = ConwayBets::default();
+        let mut on_b = ConwayBets::default();
+        on_a.block.chain_id = chain(1);
+        on_b.block.chain_id = chain(2);
+
+        let market_a = create_test_market(&mut on_a, market_data());
+        let market_b = create_test_market(&mut on_b, market_data());
+        assert_eq!(market_a.chain_id, chain(1));
+        assert_eq!(market_b.chain_id, chain(2));
+        assert_eq!(market_a.id, market_b.id);
+        assert_ne!(market_a, market_b);
+    }
+
+    #[test]
+    fn test_sweep_dust_moves_rounding_dust_and_small_balances_to_destination() {
+        let mut conwaybets = ConwayBets::default();
+        conwaybets.config.owner = Some(owner(9));
+        conwaybets.config.dust_threshold = Amount::from_attos(10);
+        conwaybets.config.dust_idle_secs = 1_000;
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        let unbacked = create_test_market(&mut conwaybets, market_data());
+        let bets = [(market_id, 2, 0), (market_id, 3, 0), (market_id, 4, 0), (market_id, 5, 1), (unbacked, 5, 1)];
+        for (market, user, outcome_index) in bets {
+            conwaybets.credit(owner(user), Amount::from_attos(1));
+            conwaybets
+                .place_bet(market, owner(user), outcome_index, Amount::from_attos(1))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        conwaybets.resolve_market(owner(1), resolution(market_id, 0)).unwrap();
+        conwaybets.resolve_market(owner(1), resolution(unbacked, 0)).unwrap();
+        conwaybets.credit(owner(6), Amount::from_attos(3));
+        conwaybets.credit(owner(7), Amount::from_tokens(1));
+
+        let sweep = |conwaybets: &mut ConwayBets, caller| {
+            conwaybets.sweep_dust(caller, owner(8)).now_or_never().unwrap()
+        };
+        assert_eq!(sweep(&mut conwaybets, owner(1)), Err(ConwayBetsError::NotAuthorized));
+        // Three winners splitting 4 attos get 1 each, leaving 1 of dust. Nobody
+        // backed the other market's winner, so its pot is refunded rather than
+        // swept, and the small balance was touched too recently to count as
+        // abandoned.
+        assert_eq!(sweep(&mut conwaybets, owner(9)), Ok(Amount::from_attos(1)));
+        assert_eq!(conwaybets.balances[&owner(6)], Amount::from_attos(3));
+        conwaybets.block.timestamp = 1_000;
+        assert_eq!(sweep(&mut conwaybets, owner(9)), Ok(Amount::from_attos(3)));
+        assert_eq!(conwaybets.balances[&owner(8)], Amount::from_attos(4));
+        assert!(!conwaybets.balances.contains_key(&owner(6)));
+        assert_eq!(conwaybets.balances[&owner(7)], Amount::from_tokens(1));
+        assert!(conwaybets.solvency_report().is_balanced());
+
+        assert_eq!(sweep(&mut conwaybets, owner(9)), Ok(Amount::ZERO));
+        conwaybets.claim_winnings(owner(2), market_id).now_or_never().unwrap().unwrap();
+        assert_eq!(
+            conwaybets.claim_winnings(owner(5), unbacked).now_or_never().unwrap(),
+            Ok(Amount::from_attos(1))
+        );
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_state_hash_tracks_market_state() {
+        let mut on_a = funded();
+        let mut on_b = funded();
+        let market_id = create_test_market(&mut on_a, market_data());
+        create_test_market(&mut on_b, market_data());
+        let created = on_a.markets[&market_id].state_hash;
+        assert_ne!(created, [0; 32]);
+        assert_eq!(on_b.markets[&market_id].state_hash, created);
+
+        for conwaybets in [&mut on_a, &mut on_b] {
+            conwaybets
+                .place_bet(market_id, owner(2), 0, Amount::from_tokens(5))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        let after_bet = on_a.markets[&market_id].state_hash;
+        assert_ne!(after_bet, created);
+        assert_eq!(on_b.markets[&market_id].state_hash, after_bet);
+        assert_eq!(on_a.compute_state_hash(&market_id), after_bet);
+
+        on_a.resolve_market(owner(1), resolution(market_id, 0)).unwrap();
+        assert_ne!(on_a.markets[&market_id].state_hash, after_bet);
+        assert_ne!(on_a.markets[&market_id].state_hash, on_b.markets[&market_id].state_hash);
+    }
+
+    #[test]
+    fn test_watchlist_keeps_markets_until_unwatched() {
+        let mut conwaybets = ConwayBets::default();
+        let first = create_test_market(&mut conwaybets, market_data());
+        let second = create_test_market(&mut conwaybets, market_data());
+        let unknown = MarketId { chain_id: chain(3), id: 99 };
+
+        assert_eq!(conwaybets.watch(owner(2), first), Ok(true));
+        assert_eq!(conwaybets.watch(owner(2), second), Ok(true));
+        assert_eq!(conwaybets.watch(owner(2), first), Ok(false));
+        assert_eq!(conwaybets.watch(owner(2), unknown), Err(ConwayBetsError::MarketNotFound));
+        let watched = |conwaybets: &ConwayBets| -> Vec<MarketId> {
+            conwaybets.watched_markets(owner(2)).iter().map(|m| m.id).collect()
+        };
+        assert_eq!(watched(&conwaybets), vec![first, second]);
+
+        conwaybets.resolve_market(owner(1), resolution(first, 0)).unwrap();
+        assert_eq!(watched(&conwaybets), vec![first, second]);
+
+        assert!(conwaybets.unwatch(owner(2), first));
+        assert!(!conwaybets.unwatch(owner(2), first));
+        assert_eq!(watched(&conwaybets), vec![second]);
+        assert!(conwaybets.watched_markets(owner(3)).is_empty());
+    }
+
+    #[test]
+    fn test_claim_during_dispute_window_releases_interim_share_first() {
+        let mut conwaybets = funded();
+        conwaybets.config.interim_payout_bps = 5_000;
+        conwaybets.config.post_dispute = PostDisputeAction::Cancel;
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            dispute_window_secs: 100,
+            ..market_data()
+        });
+        for (user, outcome) in [(2, 0), (3, 1)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(10))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        conwaybets.resolve_market(owner(1), resolution(market_id, 0)).unwrap();
+
+        let claim = |conwaybets: &mut ConwayBets| {
+            conwaybets.claim_winnings(owner(2), market_id).now_or_never().unwrap()
+        };
+        assert_eq!(claim(&mut conwaybets), Ok(Amount::from_tokens(10)));
+        assert_eq!(claim(&mut conwaybets), Ok(Amount::ZERO));
+        assert!(!conwaybets.user_positions[&owner(2)][0].claimed);
+        assert!(conwaybets.solvency_report().is_balanced());
+
+        conwaybets.block.timestamp = 100;
+        assert_eq!(claim(&mut conwaybets), Ok(Amount::from_tokens(10)));
+        assert_eq!(claim(&mut conwaybets), Ok(Amount::ZERO));
+        assert!(conwaybets.interim_payouts.is_empty());
+        assert_eq!(conwaybets.escrow_balance, Amount::ZERO);
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_odds_correlation_over_overlapping_observations() {
+        let mut conwaybets = ConwayBets::default();
+        let market_a = create_test_market(&mut conwaybets, market_data());
+        let market_b = create_test_market(&mut conwaybets, market_data());
+        let market_c = create_test_market(&mut conwaybets, market_data());
+        let observe = |conwaybets: &mut ConwayBets, market_id: MarketId, start: u64, series: &[u32]| {
+            let observations = &mut conwaybets.markets.get_mut(&market_id).unwrap().price_observations;
+            for (i, ppm) in series.iter().enumerate() {
+                observations.push_back(PriceObservation {
+                    timestamp: start + 10 * i as u64,
+                    probabilities_ppm: vec![*ppm, 1_000_000 - ppm],
+                });
+            }
+        };
+        observe(&mut conwaybets, market_a, 10, &[100_000, 200_000, 300_000, 400_000, 500_000]);
+        observe(&mut conwaybets, market_b, 10, &[200_000, 100_000, 400_000, 300_000, 500_000]);
+        observe(&mut conwaybets, market_c, 60, &[300_000, 600_000]);
+
+        let correlation = conwaybets.odds_correlation(&market_a, 0, &market_b, 0).unwrap();
+        assert!((correlation - 0.8).abs() < 1e-9, "{correlation}");
+        let inverse = conwaybets.odds_correlation(&market_a, 0, &market_a, 1).unwrap();
+        assert!((inverse + 1.0).abs() < 1e-9, "{inverse}");
+        // C starts after A's last observation, so they never overlap.
+        assert_eq!(conwaybets.odds_correlation(&market_a, 0, &market_c, 0), None);
+        assert_eq!(conwaybets.odds_correlation(&market_a, 0, &market_a, 5), None);
+    }
+
+    #[test]
+    fn test_place_bet_tracks_stake_per_outcome() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        assert_eq!(conwaybets.markets[&market_id].outcome_pools, vec![Amount::ZERO; 2]);
+        for (user, outcome, tokens) in [(2, 0, 10), (3, 1, 4), (4, 0, 6)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        let market = &conwaybets.markets[&market_id];
+        assert_eq!(market.outcome_pools, vec![Amount::from_tokens(16), Amount::from_tokens(4)]);
+        assert_eq!(market.total_liquidity, Amount::from_tokens(20));
+
+        let out_of_range = conwaybets
+            .place_bet(market_id, owner(2), 2, Amount::from_tokens(1))
+            .now_or_never()
+            .unwrap();
+        assert!(out_of_range.unwrap_err().to_string().contains("range"));
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::from_tokens(20));
+    }
+
+    #[test]
+    fn test_cancelling_pending_cross_chain_bet_releases_reservation() {
+        let mut betting = ConwayBets::default();
+        let mut hosting = ConwayBets::default();
+        betting.block.chain_id = chain(1);
+        hosting.block.chain_id = chain(2);
+        let market_id = create_test_market(&mut hosting, MarketCreationData { min_confirmations: 3, ..market_data() });
+        betting.record_remote_market(market_id, hosting.markets[&market_id].state_hash, 0);
+        betting.credit(owner(2), Amount::from_tokens(10));
+
+        let receipt = betting
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(4))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        assert!(matches!(receipt.status, Status::Pending));
+        assert_eq!(betting.balances[&owner(2)], Amount::from_tokens(6));
+        let bet = betting.unacked_bets[&receipt.id].clone();
+        hosting.execute_message(chain(1), ConwayBetsMessage::Bet(bet));
+        assert_eq!(hosting.pending_bets.len(), 1);
+
+        assert_eq!(betting.cancel_pending_bet(owner(3), receipt.id), Err(ConwayBetsError::NotAuthorized));
+        assert_eq!(betting.cancel_pending_bet(owner(2), receipt.id), Ok(()));
+        let nonce = receipt.id;
+        hosting.execute_message(chain(1), ConwayBetsMessage::CancelBet { correlation_id: [1; 16], nonce });
+        assert!(hosting.pending_bets.is_empty());
+        assert_eq!(hosting.escrow_balance, Amount::ZERO);
+        betting.execute_message(chain(2), ConwayBetsMessage::BetCancelled { correlation_id: [2; 16], nonce });
+        assert_eq!(betting.balances[&owner(2)], Amount::from_tokens(10));
+        assert!(betting.unacked_bets.is_empty());
+        assert!(betting.solvency_report().is_balanced());
+
+        // Once the market chain confirms a bet it can no longer be cancelled.
+        let receipt = betting
+            .place_bet(market_id, owner(2), 1, Amount::from_tokens(4))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let nonce = receipt.id;
+        hosting.execute_message(chain(1), ConwayBetsMessage::Bet(betting.unacked_bets[&nonce].clone()));
+        assert!(matches!(hosting.confirm_bet(chain(1), nonce, 5), Some(Status::Finalized)));
+        assert_eq!(hosting.cancel_received_bet(chain(1), nonce), Err(ConwayBetsError::BetNotPending));
+        betting.execute_message(chain(2), ConwayBetsMessage::BetFinalized { correlation_id: [3; 16], nonce });
+        assert_eq!(betting.cancel_pending_bet(owner(2), nonce), Err(ConwayBetsError::BetNotPending));
+        assert_eq!(betting.balances[&owner(2)], Amount::from_tokens(6));
+        assert!(betting.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_create_market_rejects_invalid_definitions() {
+        let mut conwaybets = ConwayBets::default();
+        conwaybets.block.timestamp = 500;
+        let create = |conwaybets: &mut ConwayBets, data| {
+            conwaybets.create_market(owner(1), data).now_or_never().unwrap()
+        };
+        let outcomes = |labels: &[&str]| labels.iter().map(|l| l.to_string()).collect();
+        let cases = [
+            (MarketCreationData { title: " ".to_string(), ..market_data() }, MarketError::EmptyTitle),
+            (MarketCreationData { outcomes: outcomes(&["Yes"]), ..market_data() }, MarketError::TooFewOutcomes),
+            (
+                MarketCreationData { outcomes: outcomes(&["Yes", "No", "Yes"]), ..market_data() },
+                MarketError::DuplicateOutcome("Yes".to_string()),
+            ),
+            (MarketCreationData { end_time: 500, ..market_data() }, MarketError::EndTimeInPast),
+        ];
+        for (data, error) in cases {
+            assert_eq!(create(&mut conwaybets, data), Err(ConwayBetsError::Market(error)));
+        }
+        assert!(conwaybets.markets.is_empty());
+        assert!(create(&mut conwaybets, MarketCreationData { end_time: 501, ..market_data() }).is_ok());
+    }
+
+    #[test]
+    fn test_early_bettors_earn_bonus_from_reward_pool() {
+        let mut conwaybets = funded();
+        conwaybets.config.early_liquidity_cap = Amount::from_tokens(10);
+        conwaybets.config.early_bonus_bps = 5_000;
+        conwaybets.fund_rewards(owner(9), Amount::from_millis(4_500)).now_or_never().unwrap().unwrap();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        for (user, tokens) in [(2, 8), (3, 6), (4, 5)] {
+            conwaybets
+                .place_bet(market_id, owner(user), 0, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        conwaybets.resolve_market(owner(1), resolution(market_id, 0)).unwrap();
+
+        let claim = |conwaybets: &mut ConwayBets, user| {
+            conwaybets.claim_winnings(owner(user), market_id).now_or_never().unwrap().unwrap()
+        };
+        // 8 early tokens earn 4; the 2 of owner(3)'s stake under the cap would
+        // earn 1 but only 0.5 is left in the pool.
+        assert_eq!(claim(&mut conwaybets, 2), Amount::from_tokens(12));
+        assert_eq!(claim(&mut conwaybets, 3), Amount::from_millis(6_500));
+        assert_eq!(claim(&mut conwaybets, 4), Amount::from_tokens(5));
+        let starting = Amount::from_tokens(1_000_000);
+        assert_eq!(conwaybets.balances[&owner(2)], starting.saturating_add(Amount::from_tokens(4)));
+        assert_eq!(conwaybets.balances[&owner(3)], starting.saturating_add(Amount::from_millis(500)));
+        assert_eq!(conwaybets.balances[&owner(4)], starting);
+        assert_eq!(conwaybets.reward_pool, Amount::ZERO);
+        assert_eq!(conwaybets.escrow_balance, Amount::ZERO);
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_bets_rejected_once_market_ends_or_is_unknown() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData { end_time: 1_000, ..market_data() });
+        let bet = |conwaybets: &mut ConwayBets, market_id| {
+            conwaybets.place_bet(market_id, owner(2), 0, Amount::from_tokens(1)).now_or_never().unwrap()
+        };
+
+        conwaybets.block.timestamp = 999;
+        assert!(bet(&mut conwaybets, market_id).is_ok());
+        conwaybets.block.timestamp = 1_000;
+        let closed = bet(&mut conwaybets, market_id).unwrap_err();
+        assert_eq!(closed, ConwayBetsError::MarketClosed);
+        let unknown = bet(&mut conwaybets, MarketId { chain_id: chain(3), id: 42 }).unwrap_err();
+        assert_eq!(unknown, ConwayBetsError::MarketNotFound);
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::from_tokens(1));
+    }
+
+    #[test]
+    fn test_market_config_reflects_creation_settings() {
+        let mut conwaybets = ConwayBets::default();
+        conwaybets.config.fee_basis_points = 150;
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            lmsr_b: Some(Amount::from_tokens(100)),
+            min_confirmations: 2,
+            fallback_oracle: Some(owner(9)),
+            fallback_after_secs: 3_600,
+            dispute_window_secs: 600,
+            first_bet_delay_secs: 30,
+            min_bettors_to_resolve: Some(3),
+            ..market_data()
+        });
+
+        let config = conwaybets.market_config(&market_id).unwrap();
+        assert_eq!(config.kind, MarketKind::Amm);
+        assert_eq!(config.fee_basis_points, 150);
+        assert_eq!(config.lmsr_b, Some(Amount::from_tokens(100)));
+        assert_eq!(config.min_pool_to_open, Amount::ZERO);
+        assert_eq!(config.min_bet, Amount::ZERO);
+        assert_eq!(config.min_confirmations, 2);
+        assert_eq!(config.min_bettors_to_resolve, Some(3));
+        assert_eq!(config.resolver, owner(1));
+        assert_eq!(config.fallback_oracle, Some(owner(9)));
+        assert_eq!(config.fallback_after_secs, 3_600);
+        assert_eq!(config.first_bet_delay_secs, 30);
+        assert_eq!(config.dispute_window_secs, 600);
+
+        let plain = create_test_market(&mut conwaybets, market_data());
+        assert_eq!(conwaybets.market_config(&plain).unwrap().kind, MarketKind::Parimutuel);
+        assert_eq!(conwaybets.market_config(&MarketId { chain_id: chain(3), id: 42 }), None);
+    }
+
+    #[test]
+    fn test_bet_placement_errors_are_matchable() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        let unknown = MarketId { chain_id: chain(3), id: 42 };
+        let bet = |conwaybets: &mut ConwayBets, market_id, outcome_index| {
+            conwaybets
+                .place_bet(market_id, owner(2), outcome_index, Amount::from_tokens(1))
+                .now_or_never()
+                .unwrap()
+                .map(|receipt| receipt.id)
+        };
+
+        assert_eq!(bet(&mut conwaybets, unknown, 0), Err(ConwayBetsError::MarketNotFound));
+        assert_eq!(bet(&mut conwaybets, market_id, 2), Err(ConwayBetsError::OutcomeOutOfRange));
+        assert_eq!(
+            conwaybets.fund_market(owner(2), market_id, Amount::from_tokens(1)).now_or_never().unwrap(),
+            Err(ConwayBetsError::NotAuthorized)
+        );
+        let commit = |conwaybets: &mut ConwayBets| {
+            conwaybets
+                .place_committed_bet(owner(2), market_id, 0, Amount::from_tokens(1), [5; 32])
+                .now_or_never()
+                .unwrap()
+        };
+        assert_eq!(commit(&mut conwaybets), Ok(()));
+        assert_eq!(commit(&mut conwaybets), Err(ConwayBetsError::DuplicateCommitment));
+
+        conwaybets.cancel_market(owner(1), market_id).unwrap();
+        assert_eq!(bet(&mut conwaybets, market_id, 0), Err(ConwayBetsError::MarketCancelled));
+        let received = conwaybets.receive_bet(chain(9), BetMessage {
+            correlation_id: [0; 16],
+            market_id: unknown,
+            user: owner(2),
+            outcome_index: 0,
+            amount: Amount::from_tokens(1),
+            nonce: 1,
+            block_height: 0,
+        });
+        assert!(matches!(received, Err(ConwayBetsError::MarketNotFound)));
+    }
+
+    #[test]
+    fn test_quoted_shares_match_placed_shares() {
+        // A small LCG keeps the "random" markets and bets reproducible.
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let mut next = |bound: u64| {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            (seed >> 33) % bound
+        };
+        for _ in 0..50 {
+            let mut conwaybets = funded();
+            conwaybets.config.fee_basis_points = next(500) as u16;
+            let outcomes = (0..2 + next(3)).map(|i| format!("Outcome {i}")).collect();
+            let lmsr_b = (next(2) == 0).then(|| Amount::from_attos(1 + u128::from(next(1_000)) * 10u128.pow(17)));
+            let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+                outcomes,
+                lmsr_b,
+                lmsr_b_growth_bps: next(3) as u32 * 2_500,
+                ..market_data()
+            });
+            let outcome_count = conwaybets.markets[&market_id].outcomes.len() as u64;
+            for bettor in 0..8u8 {
+                let user = owner(bettor);
+                let outcome_index = next(outcome_count) as u32;
+                let amount = u128::from(next(1_000_000)) * 10u128.pow(15) + u128::from(next(1_000)) + 1;
+                let amount = Amount::from_attos(amount);
+
+                let quote = conwaybets.quote_bet(market_id, user, outcome_index, amount).unwrap();
+                conwaybets.place_bet(market_id, user, outcome_index, amount).now_or_never().unwrap().unwrap();
+
+                let position = conwaybets.user_positions[&user].last().unwrap();
+                assert_eq!(position.shares, quote.shares);
+                assert_eq!(position.amount, quote.stake);
+            }
+        }
+    }
+
+    #[test]
+    fn test_only_creator_or_designated_resolver_resolves() {
+        let mut conwaybets = ConwayBets::default();
+        let delegated = create_test_market(&mut conwaybets, MarketCreationData {
+            resolver: Some(owner(5)),
+            ..market_data()
+        });
+        let by_creator = create_test_market(&mut conwaybets, MarketCreationData {
+            resolver: Some(owner(5)),
+            ..market_data()
+        });
+        let plain = create_test_market(&mut conwaybets, market_data());
+        assert_eq!(conwaybets.markets[&delegated].resolver, Some(owner(5)));
+
+        for market_id in [delegated, by_creator, plain] {
+            assert_eq!(
+                conwaybets.resolve_market(owner(2), resolution(market_id, 0)),
+                Err(ConwayBetsError::NotAuthorized)
+            );
+        }
+        assert_eq!(conwaybets.resolve_market(owner(5), resolution(plain, 0)), Err(ConwayBetsError::NotAuthorized));
+
+        assert_eq!(conwaybets.resolve_market(owner(5), resolution(delegated, 1)), Ok(true));
+        assert_eq!(conwaybets.resolve_market(owner(1), resolution(by_creator, 0)), Ok(true));
+        assert_eq!(conwaybets.resolve_market(owner(1), resolution(plain, 0)), Ok(true));
+    }
+
+    #[test]
+    fn test_feeder_bulk_creates_markets() {
+        let mut conwaybets = funded();
+        conwaybets.config.feeders.insert(owner(7));
+        conwaybets.config.require_market_approval = true;
+        let feed = ["Match 1", "Match 2", "Match 3"]
+            .map(|title| MarketCreationData { title: title.to_string(), ..market_data() })
+            .to_vec();
+        let bulk_create = |conwaybets: &mut ConwayBets, feeder, markets| {
+            conwaybets.bulk_create_markets(feeder, markets).now_or_never().unwrap()
+        };
+
+        assert_eq!(bulk_create(&mut conwaybets, owner(2), feed.clone()), Err(ConwayBetsError::NotAuthorized));
+
+        let market_ids = bulk_create(&mut conwaybets, owner(7), feed).unwrap();
+        assert_eq!(market_ids.len(), 3);
+        for (market_id, title) in market_ids.iter().zip(["Match 1", "Match 2", "Match 3"]) {
+            let market = &conwaybets.markets[market_id];
+            assert_eq!(market.title, title);
+            assert_eq!(market.creator, owner(7));
+            assert_eq!(market.status, MarketStatus::Open);
+        }
+    }
+
+    #[test]
+    fn test_invalid_entry_rolls_back_bulk_creation() {
+        let mut conwaybets = funded();
+        conwaybets.config.feeders.insert(owner(7));
+        let feed = vec![
+            market_data(),
+            MarketCreationData { outcomes: vec!["Yes".to_string()], ..market_data() },
+            market_data(),
+        ];
+
+        assert_eq!(
+            conwaybets.bulk_create_markets(owner(7), feed).now_or_never().unwrap(),
+            Err(MarketError::TooFewOutcomes.into())
+        );
+        assert!(conwaybets.markets.is_empty());
+        assert_eq!(conwaybets.next_market_id, 0);
+
+        conwaybets.config.max_markets_per_block = 2;
+        assert_eq!(
+            conwaybets.bulk_create_markets(owner(7), vec![market_data(); 3]).now_or_never().unwrap(),
+            Err(ConwayBetsError::BlockQuotaExceeded)
+        );
+        assert!(conwaybets.markets.is_empty());
+    }
+
+    #[test]
+    fn test_probability_drift_since_open() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        assert_eq!(conwaybets.probability_drift(&market_id, 1), None);
+
+        // Outcome 1 goes from 0 to 5 / 15 to 15 / 25 of the pool.
+        for (outcome, tokens) in [(0, 10), (1, 5), (1, 10)] {
+            conwaybets
+                .place_bet(market_id, owner(2), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        let rising = conwaybets.probability_drift(&market_id, 1).unwrap();
+        assert!((rising - 0.6).abs() < 1e-9, "{rising}");
+        let falling = conwaybets.probability_drift(&market_id, 0).unwrap();
+        assert!((falling + 0.6).abs() < 1e-9, "{falling}");
+        assert_eq!(conwaybets.probability_drift(&market_id, 2), None);
+    }
+
+    #[test]
+    fn test_withdraw_bet_refunds_stake_before_close() {
+        let mut conwaybets = ConwayBets::default();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        for (user, outcome, tokens) in [(2, 0, 4), (3, 1, 6), (2, 1, 3)] {
+            conwaybets.credit(owner(user), Amount::from_tokens(tokens));
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        let withdraw = |conwaybets: &mut ConwayBets, user, position_index| {
+            conwaybets.withdraw_bet(owner(user), market_id, position_index).now_or_never().unwrap()
+        };
+
+        assert_eq!(withdraw(&mut conwaybets, 2, 1), Ok(Amount::from_tokens(3)));
+        let market = &conwaybets.markets[&market_id];
+        assert_eq!(market.total_liquidity, Amount::from_tokens(10));
+        assert_eq!(market.outcome_pools, vec![Amount::from_tokens(4), Amount::from_tokens(6)]);
+        assert_eq!(market.outcome_shares, vec![Amount::from_tokens(4), Amount::from_tokens(6)]);
+        assert_eq!(conwaybets.balances[&owner(2)], Amount::from_tokens(3));
+        assert_eq!(conwaybets.user_positions[&owner(2)].len(), 1);
+        assert!(conwaybets.solvency_report().is_balanced());
+
+        conwaybets.user_positions.get_mut(&owner(2)).unwrap()[0].claimed = true;
+        assert_eq!(withdraw(&mut conwaybets, 2, 0), Err(ConwayBetsError::AlreadyClaimed));
+        assert_eq!(withdraw(&mut conwaybets, 3, 1), Err(ConwayBetsError::PositionNotFound));
+
+        conwaybets.block.timestamp = 1_000_000_000;
+        assert_eq!(withdraw(&mut conwaybets, 3, 0), Err(ConwayBetsError::MarketClosed));
+        conwaybets.resolve_market(owner(1), resolution(market_id, 1)).unwrap();
+        assert_eq!(withdraw(&mut conwaybets, 3, 0), Err(ConwayBetsError::AlreadyResolved));
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::from_tokens(10));
+    }
+
+    #[test]
+    fn test_withdrawal_cannot_underflow_pool() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        for (user, outcome, tokens) in [(2, 0, 4), (3, 1, 6)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        // Three tokens already went out, e.g. as an interim payout.
+        conwaybets.markets.get_mut(&market_id).unwrap().paid_out = Amount::from_tokens(3);
+        let withdraw = |conwaybets: &mut ConwayBets, user| {
+            conwaybets.withdraw_bet(owner(user), market_id, 0).now_or_never().unwrap()
+        };
+
+        assert_eq!(withdraw(&mut conwaybets, 2), Err(ConwayBetsError::WouldUnderflowPool));
+        assert_eq!(withdraw(&mut conwaybets, 3), Err(ConwayBetsError::WouldUnderflowPool));
+        let market = &conwaybets.markets[&market_id];
+        assert_eq!(market.total_liquidity, Amount::from_tokens(10));
+        assert_eq!(market.outcome_pools, vec![Amount::from_tokens(4), Amount::from_tokens(6)]);
+        assert_eq!(conwaybets.user_positions[&owner(2)].len(), 1);
+
+        conwaybets.markets.get_mut(&market_id).unwrap().paid_out = Amount::ZERO;
+        assert_eq!(withdraw(&mut conwaybets, 2), Ok(Amount::from_tokens(4)));
+        assert_eq!(withdraw(&mut conwaybets, 3), Ok(Amount::from_tokens(6)));
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::ZERO);
+    }
+
+    #[test]
+    fn test_lmsr_bets_buy_shares_at_market_price() {
+        let mut conwaybets = funded();
+        let b = Amount::from_tokens(100);
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            outcomes: ["Red", "Green", "Blue"].map(String::from).to_vec(),
+            lmsr_b: Some(b),
+            ..market_data()
+        });
+        let b = u128::from(b) as f64;
+        let amount = Amount::from_tokens(20);
+
+        let mut before = conwaybets.markets[&market_id].implied_probabilities();
+        for probability in &before {
+            assert!((probability - 1.0 / 3.0).abs() < 1e-9);
+        }
+        for user in 2..5 {
+            let pools = conwaybets.markets[&market_id].outcome_shares.clone();
+            conwaybets.place_bet(market_id, owner(user), 0, amount).now_or_never().unwrap().unwrap();
+
+            // Each share costs less than a token while the price is below 1,
+            // and the shares credited cost what was staked.
+            let shares = conwaybets.user_positions[&owner(user)][0].shares;
+            assert!(shares > amount);
+            let cost = amm::cost(&pools, 0, shares, b);
+            assert!(u128::from(amount).abs_diff(u128::from(cost)) < 1_000_000_000);
+
+            let after = conwaybets.markets[&market_id].implied_probabilities();
+            assert!((after.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+            assert!(after[0] > before[0]);
+            assert!(after[1] < before[1] && after[2] < before[2]);
+            before = after;
+        }
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::from_tokens(60));
+    }
+
+    #[test]
+    fn test_implied_probabilities_are_pool_shares_or_lmsr_prices() {
+        let mut conwaybets = funded();
+        let outcomes = ["Red", "Green", "Blue", "Gold"].map(String::from).to_vec();
+        let pooled = create_test_market(&mut conwaybets, MarketCreationData {
+            outcomes: outcomes.clone(),
+            ..market_data()
+        });
+        let amm = create_test_market(&mut conwaybets, MarketCreationData {
+            outcomes,
+            lmsr_b: Some(Amount::from_tokens(50)),
+            ..market_data()
+        });
+        for market_id in [pooled, amm] {
+            for odds in conwaybets.markets[&market_id].implied_probabilities() {
+                assert!((odds - 0.25).abs() < 1e-9);
+            }
+        }
+
+        for market_id in [pooled, amm] {
+            for (outcome, tokens) in [(0, 6), (2, 3), (0, 1)] {
+                conwaybets
+                    .place_bet(market_id, owner(2), outcome, Amount::from_tokens(tokens))
+                    .now_or_never()
+                    .unwrap()
+                    .unwrap();
+            }
+            let odds = conwaybets.markets[&market_id].implied_probabilities();
+            assert!((odds.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        }
+
+        let odds = conwaybets.markets[&pooled].implied_probabilities();
+        for (odds, expected) in odds.iter().zip([0.7, 0.0, 0.3, 0.0]) {
+            assert!((odds - expected).abs() < 1e-9);
+        }
+        let market = &conwaybets.markets[&amm];
+        let prices = amm::price(&market.outcome_shares, u128::from(market.lmsr_b.unwrap()) as f64);
+        assert_eq!(market.implied_probabilities(), prices);
+    }
+
+    #[test]
+    fn test_global_bet_feed_interleaves_markets_by_time() {
+        let mut conwaybets = funded();
+        conwaybets.config.max_bet_feed_entries = 4;
+        let market_a = create_test_market(&mut conwaybets, market_data());
+        let market_b = create_test_market(&mut conwaybets, market_data());
+        let bets = [(10, market_a, 2), (20, market_b, 3), (30, market_a, 4), (30, market_b, 2), (50, market_a, 3)];
+        for (timestamp, market_id, user) in bets {
+            conwaybets.block.timestamp = timestamp;
+            conwaybets
+                .place_bet(market_id, owner(user), 0, Amount::from_tokens(1))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        // The oldest bet fell out of the capped log.
+        let feed = conwaybets.global_bet_feed(10, None);
+        let seen: Vec<(u64, u64, MarketId)> = feed.iter().map(|b| (b.seq, b.placed_at, b.market_id)).collect();
+        assert_eq!(seen, vec![(2, 20, market_b), (3, 30, market_a), (4, 30, market_b), (5, 50, market_a)]);
+        assert_eq!(feed[1].user, owner(4));
+
+        let seqs = |page: Vec<&FeedBet>| -> Vec<u64> { page.iter().map(|b| b.seq).collect() };
+        assert_eq!(seqs(conwaybets.global_bet_feed(2, None)), vec![4, 5]);
+        // Paging past the first bet at 30 still reaches the second one.
+        assert_eq!(seqs(conwaybets.global_bet_feed(1, Some(2))), vec![3]);
+        assert_eq!(seqs(conwaybets.global_bet_feed(2, Some(3))), vec![4, 5]);
+        assert!(conwaybets.global_bet_feed(2, Some(5)).is_empty());
+    }
+
+    #[test]
+    fn test_overturned_resolution_counts_against_resolver() {
+        let (mut conwaybets, overturned) = overturned_market(PostDisputeAction::ReopenForResolution);
+        let upheld = create_test_market(&mut conwaybets, MarketCreationData {
+            resolver: Some(owner(5)),
+            dispute_window_secs: 1_000,
+            ..market_data()
+        });
+        conwaybets
+            .place_bet(upheld, owner(2), 0, Amount::from_tokens(5))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        conwaybets.resolve_market(owner(5), resolution(upheld, 0)).unwrap();
+        conwaybets.challenge_resolution(owner(2), upheld).unwrap();
+        conwaybets.rule_on_dispute(owner(9), upheld, false).unwrap();
+
+        let expected = ReputationStats { markets_resolved: 1, disputes_raised: 1, disputes_lost: 1 };
+        assert_eq!(conwaybets.resolver_reputation(owner(1)), expected);
+        let expected = ReputationStats { markets_resolved: 1, disputes_raised: 1, disputes_lost: 0 };
+        assert_eq!(conwaybets.resolver_reputation(owner(5)), expected);
+        assert_eq!(conwaybets.resolver_reputation(owner(6)), ReputationStats::default());
+
+        // Resolving the reopened market again counts as another ruling.
+        conwaybets.resolve_market(owner(1), resolution(overturned, 1)).unwrap();
+        assert_eq!(conwaybets.markets[&overturned].resolved_by, Some(owner(1)));
+        assert_eq!(conwaybets.resolver_reputation(owner(1)).markets_resolved, 2);
+        assert_eq!(conwaybets.resolver_reputation(owner(1)).disputes_lost, 1);
+    }
+
+    #[test]
+    fn test_fee_never_exceeds_the_bet() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        let bet = |conwaybets: &mut ConwayBets, fee_basis_points, attos| {
+            conwaybets.config.fee_basis_points = fee_basis_points;
+            let treasury = conwaybets.treasury;
+            conwaybets
+                .place_bet(market_id, owner(2), 0, Amount::from_attos(attos))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+            let staked = conwaybets.user_positions[&owner(2)].last().unwrap().amount;
+            (u128::from(conwaybets.treasury.saturating_sub(treasury)), u128::from(staked))
+        };
+
+        assert_eq!(bet(&mut conwaybets, 0, 1_000), (0, 1_000));
+        assert_eq!(bet(&mut conwaybets, 250, 1_000), (25, 975));
+        // Fees round down, so dust-sized bets pay none.
+        assert_eq!(bet(&mut conwaybets, 9_999, 1), (0, 1));
+        assert_eq!(bet(&mut conwaybets, 10_000, 7), (7, 0));
+        assert_eq!(bet(&mut conwaybets, u16::MAX, 7), (7, 0));
+        assert_eq!(conwaybets.treasury, Amount::from_attos(39));
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_creator_fee_is_skimmed_from_pot_once() {
+        let mut conwaybets = ConwayBets::default();
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            creator_fee_bps: 500,
+            ..market_data()
+        });
+        for (user, outcome, tokens) in [(2, 0, 6), (3, 1, 4)] {
+            conwaybets.credit(owner(user), Amount::from_tokens(tokens));
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        conwaybets.resolve_market(owner(1), resolution(market_id, 0)).unwrap();
+        let claim = |conwaybets: &mut ConwayBets, user| {
+            conwaybets.claim_winnings(owner(user), market_id).now_or_never().unwrap().unwrap()
+        };
+
+        // 5% of the 10 token pot goes to the creator; the winner gets the rest.
+        assert_eq!(claim(&mut conwaybets, 2), Amount::from_millis(9_500));
+        for user in [2, 3, 2] {
+            assert_eq!(claim(&mut conwaybets, user), Amount::ZERO);
+        }
+        assert_eq!(conwaybets.markets[&market_id].creator_fees, Amount::from_millis(500));
+        assert_eq!(conwaybets.balances[&owner(1)], Amount::from_millis(500));
+        assert_eq!(conwaybets.settlement_summary(&market_id).unwrap().fees, Amount::from_millis(500));
+        assert!(conwaybets.solvency_report().is_balanced());
+
+        let too_high = MarketCreationData { creator_fee_bps: 10_001, ..market_data() };
+        assert_eq!(
+            conwaybets.create_market(owner(1), too_high).now_or_never().unwrap(),
+            Err(MarketError::CreatorFeeTooHigh.into())
+        );
+    }
+
+    #[test]
+    fn test_top_up_retries_underfunded_cross_chain_bet_but_not_one_below_the_minimum() {
+        let mut betting = ConwayBets::default();
+        let mut hosting = ConwayBets::default();
+        betting.block.chain_id = chain(1);
+        hosting.block.chain_id = chain(2);
+        let market_id = create_test_market(&mut hosting, MarketCreationData {
+            min_bet: Amount::from_tokens(3),
+            ..market_data()
+        });
+        betting.record_remote_market(market_id, hosting.markets[&market_id].state_hash, 0);
+        betting.credit(owner(2), Amount::from_tokens(10));
+        let top_up = |betting: &mut ConwayBets, user, nonce| {
+            betting.top_up_and_retry(owner(user), nonce, Amount::from_tokens(4)).now_or_never().unwrap()
+        };
+        let rejected = |nonce, reason: &str, retriable| ConwayBetsMessage::BetRejected {
+            correlation_id: [nonce as u8; 16],
+            nonce,
+            reason: reason.to_string(),
+            retriable,
+        };
+
+        let zero = betting.place_bet(market_id, owner(2), 0, Amount::ZERO).now_or_never().unwrap();
+        assert_eq!(zero.unwrap_err(), ConwayBetsError::BetTooSmall);
+
+        // Only the market chain knows its minimum. Topping up can't fix a
+        // failed validation, so the bet is refunded rather than held for retry.
+        let receipt = betting
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(1))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        hosting.outbox.clear();
+        hosting.execute_message(chain(1), ConwayBetsMessage::Bet(betting.unacked_bets[&receipt.id].clone()));
+        assert_eq!(hosting.dead_letters[0].reason, ConwayBetsError::BetTooSmall.to_string());
+        assert!(matches!(hosting.outbox[..], [(_, ConwayBetsMessage::BetRejected { retriable: false, .. })]));
+        betting.execute_message(chain(2), rejected(receipt.id, &hosting.dead_letters[0].reason, false));
+        assert_eq!(betting.balances[&owner(2)], Amount::from_tokens(10));
+        assert_eq!(top_up(&mut betting, 2, receipt.id), Err(ConwayBetsError::BetNotRetriable));
+
+        // The reservation raced to nothing, so the market chain turns it away
+        // as underfunded and the bettor may top it up.
+        let receipt = betting
+            .place_bet(market_id, owner(2), 0, Amount::from_tokens(3))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let nonce = receipt.id;
+        assert_eq!(top_up(&mut betting, 2, nonce), Err(ConwayBetsError::BetNotRetriable));
+        hosting.outbox.clear();
+        let drained = BetMessage { amount: Amount::ZERO, ..betting.unacked_bets[&nonce].clone() };
+        hosting.execute_message(chain(1), ConwayBetsMessage::Bet(drained));
+        assert_eq!(hosting.dead_letters[1].reason, ConwayBetsError::InsufficientFunds.to_string());
+        assert!(matches!(hosting.outbox[..], [(_, ConwayBetsMessage::BetRejected { retriable: true, .. })]));
+        betting.execute_message(chain(2), rejected(nonce, &hosting.dead_letters[1].reason, true));
+        assert!(betting.unacked_bets.is_empty());
+        assert!(betting.failed_bets.contains_key(&nonce));
+
+        assert_eq!(top_up(&mut betting, 3, nonce), Err(ConwayBetsError::NotAuthorized));
+        assert!(matches!(top_up(&mut betting, 2, nonce).unwrap().status, Status::Pending));
+        assert_eq!(betting.balances[&owner(2)], Amount::from_tokens(3));
+        assert!(betting.failed_bets.is_empty());
+        let retried = betting.unacked_bets[&nonce].clone();
+        assert_eq!(retried.amount, Amount::from_tokens(7));
+
+        hosting.execute_message(chain(1), ConwayBetsMessage::Bet(retried));
+        assert_eq!(hosting.dead_letters.len(), 2);
+        assert_eq!(hosting.markets[&market_id].total_liquidity, Amount::from_tokens(7));
+        betting.execute_message(chain(2), ConwayBetsMessage::BetFinalized { correlation_id: [9; 16], nonce });
+        assert!(betting.unacked_bets.is_empty());
+        assert!(betting.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_payout_table_shows_each_outcome_winning() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            outcomes: ["Red", "Green", "Blue"].map(String::from).to_vec(),
+            creator_fee_bps: 1_000,
+            ..market_data()
+        });
+        for (user, outcome, tokens) in [(2, 0, 6), (3, 1, 4), (4, 0, 2)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+
+        // A 12 token pot less the 10% creator fee leaves 10.8 for the winners.
+        let table = conwaybets.payout_table(&market_id).unwrap();
+        let labels: Vec<&str> = table.iter().map(|row| row.label.as_str()).collect();
+        assert_eq!(labels, ["Red", "Green", "Blue"]);
+        assert_eq!(table[0].total_payout, Amount::from_millis(10_800));
+        assert_eq!(table[1].total_payout, Amount::from_millis(10_800));
+        assert!((table[0].multiplier.unwrap() - 1.35).abs() < 1e-9);
+        assert!((table[1].multiplier.unwrap() - 2.7).abs() < 1e-9);
+        assert_eq!(table[2].total_payout, Amount::ZERO);
+        assert_eq!(table[2].multiplier, None);
+
+        // The table matches what a real resolution pays out.
+        conwaybets.resolve_market(owner(1), resolution(market_id, 1)).unwrap();
+        assert_eq!(conwaybets.payout(owner(3), &market_id), table[1].total_payout);
+        assert_eq!(conwaybets.payout_table(&MarketId { chain_id: chain(3), id: 42 }), None);
+    }
+
+    #[test]
+    fn test_bets_below_market_minimum_are_rejected() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            min_bet: Amount::from_tokens(1),
+            ..market_data()
+        });
+        let bet = |conwaybets: &mut ConwayBets, amount| {
+            conwaybets.place_bet(market_id, owner(2), 0, amount).now_or_never().unwrap().map(|receipt| receipt.id)
+        };
+
+        let dust = Amount::from_millis(999);
+        assert_eq!(bet(&mut conwaybets, dust), Err(ConwayBetsError::BetTooSmall));
+        assert_eq!(conwaybets.quote_bet(market_id, owner(2), 0, dust), Err(ConwayBetsError::BetTooSmall));
+        assert!(conwaybets.user_positions.is_empty());
+        assert_eq!(conwaybets.escrow_balance, Amount::ZERO);
+
+        assert!(bet(&mut conwaybets, Amount::from_tokens(1)).is_ok());
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::from_tokens(1));
+        assert_eq!(conwaybets.market_config(&market_id).unwrap().min_bet, Amount::from_tokens(1));
+    }
+
+    #[test]
+    fn test_each_message_kind_is_handled_without_panicking() {
+        let mut conwaybets = ConwayBets::default();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        let remote = MarketId { chain_id: chain(3), id: 5 };
+        let bet = BetMessage {
+            correlation_id: [1; 16],
+            market_id,
+            user: owner(2),
+            outcome_index: 1,
+            amount: Amount::from_tokens(2),
+            nonce: 1,
+            block_height: 1,
+        };
+
+        conwaybets.execute_message(chain(9), ConwayBetsMessage::Initialize { correlation_id: [2; 16] });
+        conwaybets.execute_message(chain(9), ConwayBetsMessage::Bet(bet));
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::from_tokens(2));
+        conwaybets.execute_message(chain(3), ConwayBetsMessage::SyncState {
+            correlation_id: [3; 16],
+            market_id: remote,
+            state_hash: [7; 32],
+            block_height: 4,
+        });
+        assert_eq!(conwaybets.remote_markets[&remote].state_hash, [7; 32]);
+        assert!(conwaybets.dead_letters.is_empty());
+
+        // Confirming or cancelling a bet that isn't pending is recorded, not fatal.
+        let confirm = ConwayBetsMessage::Confirm { correlation_id: [4; 16], nonce: 8, block_height: 10 };
+        conwaybets.execute_message(chain(9), confirm);
+        conwaybets.execute_message(chain(9), ConwayBetsMessage::CancelBet { correlation_id: [5; 16], nonce: 8 });
+        let failed: Vec<[u8; 16]> = conwaybets.dead_letters.iter().map(|d| d.correlation_id).collect();
+        assert_eq!(failed, vec![[4; 16], [5; 16]]);
+        assert_eq!(conwaybets.dead_letters[0].reason, ConwayBetsError::BetNotPending.to_string());
+    }
+
+    #[test]
+    fn test_cross_chain_bets_get_clock_skew_grace_past_end_time() {
+        let mut conwaybets = funded();
+        conwaybets.config.clock_skew_tolerance_secs = 30;
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData { end_time: 1_000, ..market_data() });
+        let bet = |nonce| BetMessage {
+            correlation_id: [nonce as u8; 16],
+            market_id,
+            user: owner(2),
+            outcome_index: 0,
+            amount: Amount::from_tokens(1),
+            nonce,
+            block_height: 1,
+        };
+
+        conwaybets.block.timestamp = 1_029;
+        assert!(matches!(conwaybets.receive_bet(chain(9), bet(1)), Ok(Status::Finalized)));
+        // Local bets get no such grace.
+        let local = conwaybets.place_bet(market_id, owner(3), 0, Amount::from_tokens(1)).now_or_never().unwrap();
+        assert_eq!(local.unwrap_err(), ConwayBetsError::MarketClosed);
+
+        conwaybets.block.timestamp = 1_030;
+        let late = conwaybets.receive_bet(chain(9), bet(2)).unwrap_err();
+        assert_eq!(late, ConwayBetsError::MarketClosed);
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::from_tokens(1));
+    }
+
+    #[test]
+    fn test_open_interest_sums_stakes_by_label_across_open_markets() {
+        let mut conwaybets = funded();
+        let three_way = MarketCreationData {
+            outcomes: vec!["Yes".to_string(), "No".to_string(), "Draw".to_string()],
+            ..market_data()
+        };
+        let first = create_test_market(&mut conwaybets, market_data());
+        let second = create_test_market(&mut conwaybets, three_way);
+        let settled = create_test_market(&mut conwaybets, market_data());
+        let bets = [(first, 0, 5), (first, 1, 2), (second, 0, 3), (second, 2, 4), (settled, 0, 9)];
+        for (market_id, outcome, tokens) in bets {
+            let bet = conwaybets.place_bet(market_id, owner(2), outcome, Amount::from_tokens(tokens));
+            bet.now_or_never().unwrap().unwrap();
+        }
+        conwaybets.resolve_market(owner(1), resolution(settled, 0)).unwrap();
+
+        assert_eq!(
+            conwaybets.open_interest_by_label(),
+            vec![
+                ("Draw".to_string(), Amount::from_tokens(4)),
+                ("No".to_string(), Amount::from_tokens(2)),
+                ("Yes".to_string(), Amount::from_tokens(8)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_out_of_order_syncs_keep_the_newest_hash() {
+        let mut conwaybets = ConwayBets::default();
+        let remote = MarketId { chain_id: chain(3), id: 2 };
+        let sync = |conwaybets: &mut ConwayBets, state_hash, block_height| {
+            conwaybets.execute_message(chain(3), ConwayBetsMessage::SyncState {
+                correlation_id: [block_height as u8; 16],
+                market_id: remote,
+                state_hash,
+                block_height,
+            });
+        };
+
+        sync(&mut conwaybets, [5; 32], 5);
+        sync(&mut conwaybets, [9; 32], 9);
+        sync(&mut conwaybets, [7; 32], 7);
+        sync(&mut conwaybets, [1; 32], 9);
+
+        assert_eq!(conwaybets.remote_markets[&remote].state_hash, [9; 32]);
+        assert_eq!(conwaybets.remote_markets[&remote].block_height, 9);
+        assert_eq!(conwaybets.last_synced_height[&(chain(3), remote)], 9);
+    }
+
+    #[test]
+    fn test_sync_heights_are_tracked_per_reporting_chain() {
+        let mut conwaybets = ConwayBets::default();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        let local_hash = conwaybets.markets[&market_id].state_hash;
+
+        assert_eq!(conwaybets.on_sync(chain(3), market_id, local_hash, 9), SyncOutcome::InSync);
+        // A lower height from another chain isn't stale: it's that chain's own count.
+        assert_eq!(conwaybets.on_sync(chain(4), market_id, [0xAB; 32], 2), SyncOutcome::Diverged);
+        assert_eq!(conwaybets.on_sync(chain(4), market_id, local_hash, 2), SyncOutcome::Stale);
+        assert_eq!(conwaybets.on_sync(chain(3), market_id, local_hash, 8), SyncOutcome::Stale);
+        assert_eq!(conwaybets.last_synced_height[&(chain(3), market_id)], 9);
+        assert_eq!(conwaybets.last_synced_height[&(chain(4), market_id)], 2);
+    }
+
+    #[test]
+    fn test_on_sync_reports_each_outcome() {
+        let mut conwaybets = ConwayBets::default();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        let local_hash = conwaybets.markets[&market_id].state_hash;
+        let remote = MarketId { chain_id: chain(3), id: 1 };
+        assert_eq!(conwaybets.sync_status(&market_id), None);
+
+        assert_eq!(conwaybets.on_sync(chain(3), market_id, local_hash, 1), SyncOutcome::InSync);
+        assert_eq!(conwaybets.sync_status(&market_id), Some(SyncOutcome::InSync));
+        assert_eq!(conwaybets.on_sync(chain(3), market_id, [0xAB; 32], 2), SyncOutcome::Diverged);
+        assert_eq!(conwaybets.sync_status(&market_id), Some(SyncOutcome::Diverged));
+        assert_eq!(conwaybets.on_sync(chain(3), market_id, local_hash, 2), SyncOutcome::Stale);
+        assert_eq!(conwaybets.sync_status(&market_id), Some(SyncOutcome::Diverged));
+
+        assert_eq!(conwaybets.on_sync(chain(3), remote, [0xCD; 32], 1), SyncOutcome::Updated);
+        assert_eq!(conwaybets.remote_markets[&remote].state_hash, [0xCD; 32]);
+        assert_eq!(conwaybets.sync_status(&remote), None);
+    }
+
+    #[test]
+    fn test_market_state_hash_tracks_bets_and_rejects_unknown_markets() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        let before = conwaybets.market_state_hash(&market_id).unwrap();
+        conwaybets.place_bet(market_id, owner(2), 0, Amount::from_tokens(1)).now_or_never().unwrap().unwrap();
+
+        let after = conwaybets.market_state_hash(&market_id).unwrap();
+        assert_ne!(before, after);
+        assert_eq!(after, conwaybets.markets[&market_id].state_hash);
+        let unknown = MarketId { chain_id: chain(3), id: 42 };
+        assert_eq!(conwaybets.market_state_hash(&unknown), Err(ConwayBetsError::MarketNotFound));
+    }
+
+    #[test]
+    fn test_bets_for_one_chain_travel_as_a_batch_that_stops_at_the_first_failure() {
+        let mut betting = ConwayBets::default();
+        let mut hosting = ConwayBets::default();
+        betting.block.chain_id = chain(1);
+        hosting.block.chain_id = chain(2);
+        let market_id = create_test_market(&mut hosting, market_data());
+        betting.record_remote_market(market_id, hosting.markets[&market_id].state_hash, 0);
+        betting.credit(owner(2), Amount::from_tokens(10));
+        for outcome in [0, 1, 7, 0] {
+            let bet = betting.place_bet(market_id, owner(2), outcome, Amount::from_tokens(1));
+            bet.now_or_never().unwrap().unwrap();
+        }
+
+        assert_eq!(betting.outgoing_bets[&chain(2)].len(), 4);
+        assert!(betting.outbox.is_empty());
+        betting.flush_bet_batches();
+        assert!(betting.outgoing_bets.is_empty());
+        assert_eq!(betting.outbox.len(), 1);
+        let (destination, batch) = betting.outbox.pop().unwrap();
+        assert_eq!(destination, chain(2));
+        let ConwayBetsMessage::BetBatch(bets) = batch.clone() else {
+            panic!("expected a batch, got {batch:?}");
+        };
+
+        // The out-of-range third bet stops the batch; the fourth is never applied.
+        hosting.outbox.clear();
+        hosting.execute_message(chain(1), batch);
+        assert_eq!(hosting.markets[&market_id].total_liquidity, Amount::from_tokens(2));
+        assert_eq!(hosting.dead_letters.len(), 1);
+        assert_eq!(hosting.dead_letters[0].correlation_id, bets[2].correlation_id);
+
+        // Each bet gets a reply: two finalized, two rejected and refunded.
+        assert!(hosting.outbox.iter().all(|(destination, _)| *destination == chain(1)));
+        assert_eq!(hosting.outbox.len(), 4);
+        for (_, reply) in hosting.outbox.drain(..) {
+            betting.execute_message(chain(2), reply);
+        }
+        assert_eq!(betting.balances[&owner(2)], Amount::from_tokens(8));
+        assert!(betting.unacked_bets.is_empty());
+        assert!(betting.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_markets_filtered_by_creator_resolution_and_liquidity() {
+        let mut conwaybets = funded();
+        let first = create_test_market(&mut conwaybets, market_data());
+        let second = create_test_market(&mut conwaybets, market_data());
+        let other = conwaybets.create_market(owner(3), market_data()).now_or_never().unwrap().unwrap();
+        conwaybets.place_bet(second, owner(2), 0, Amount::from_tokens(5)).now_or_never().unwrap().unwrap();
+        conwaybets.place_bet(other, owner(2), 0, Amount::from_tokens(5)).now_or_never().unwrap().unwrap();
+        conwaybets.resolve_market(owner(1), resolution(first, 0)).unwrap();
+        let ids = |filter: MarketFilter| -> Vec<MarketId> {
+            conwaybets.markets_filtered(&filter).into_iter().map(|m| m.id).collect()
+        };
+
+        assert_eq!(ids(MarketFilter::default()), vec![first, second, other]);
+        assert_eq!(ids(MarketFilter { creator: Some(owner(1)), ..MarketFilter::default() }), vec![first, second]);
+        assert_eq!(ids(MarketFilter { resolved: Some(false), ..MarketFilter::default() }), vec![second, other]);
+        let liquid = MarketFilter { min_liquidity: Some(Amount::from_tokens(5)), ..MarketFilter::default() };
+        assert_eq!(ids(liquid), vec![second, other]);
+        let mine_open = MarketFilter { creator: Some(owner(1)), resolved: Some(false), ..MarketFilter::default() };
+        assert_eq!(ids(mine_open), vec![second]);
+        assert!(ids(MarketFilter { creator: Some(owner(9)), ..MarketFilter::default() }).is_empty());
+    }
+
+    #[test]
+    fn test_creator_index_drops_cancelled_markets_and_rebuilds_when_missing() {
+        let mut conwaybets = ConwayBets::default();
+        let first = create_test_market(&mut conwaybets, market_data());
+        let second = create_test_market(&mut conwaybets, market_data());
+        let other = conwaybets.create_market(owner(3), market_data()).now_or_never().unwrap().unwrap();
+        assert_eq!(conwaybets.creator_markets[&owner(1)], vec![first, second]);
+
+        conwaybets.cancel_market(owner(1), first).unwrap();
+        conwaybets.cancel_market(owner(3), other).unwrap();
+        assert_eq!(conwaybets.creator_markets[&owner(1)], vec![second]);
+        assert!(!conwaybets.creator_markets.contains_key(&owner(3)));
+
+        let indexed = conwaybets.creator_markets.clone();
+        let mut loaded: ConwayBets = bcs::from_bytes(&bcs::to_bytes(&conwaybets).unwrap()).unwrap();
+        assert!(loaded.creator_markets.is_empty());
+        loaded.rebuild_creator_index();
+        assert_eq!(loaded.creator_markets, indexed);
+        let ids = |filter: MarketFilter| -> Vec<MarketId> {
+            loaded.markets_filtered(&filter).into_iter().map(|m| m.id).collect()
+        };
+        assert_eq!(ids(MarketFilter { creator: Some(owner(1)), ..MarketFilter::default() }), vec![second]);
+        // The index and the full scan agree on what a creator's markets are.
+        let scanned: Vec<MarketId> = ids(MarketFilter::default());
+        assert_eq!(scanned, vec![second]);
+    }
+
+    #[test]
+    fn test_portfolio_sums_open_stakes_wins_and_unclaimed_payouts() {
+        let mut conwaybets = funded();
+        let open = create_test_market(&mut conwaybets, market_data());
+        let won = create_test_market(&mut conwaybets, market_data());
+        let lost = create_test_market(&mut conwaybets, market_data());
+        for (market_id, user, outcome, tokens) in [
+            (open, 2, 0, 3),
+            (open, 2, 1, 1),
+            (won, 2, 0, 4),
+            (won, 3, 1, 6),
+            (lost, 2, 1, 5),
+            (lost, 3, 0, 5),
+        ] {
+            let bet = conwaybets.place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens));
+            bet.now_or_never().unwrap().unwrap();
+        }
+        conwaybets.resolve_market(owner(1), resolution(won, 0)).unwrap();
+        conwaybets.resolve_market(owner(1), resolution(lost, 0)).unwrap();
+
+        let portfolio = conwaybets.portfolio(owner(2));
+        assert_eq!(portfolio, Portfolio {
+            total_staked: Amount::from_tokens(4),
+            open_positions: 2,
+            resolved_wins: 1,
+            claimable: Amount::from_tokens(10),
+        });
+
+        conwaybets.claim_winnings(owner(2), won).now_or_never().unwrap().unwrap();
+        assert_eq!(conwaybets.portfolio(owner(2)).claimable, Amount::ZERO);
+        assert_eq!(conwaybets.portfolio(owner(9)), Portfolio::default());
+    }
+
+    #[test]
+    fn test_user_bets_are_listed_newest_first_and_filtered_by_time() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        for (timestamp, tokens) in [(100, 1), (300, 2), (300, 3), (200, 4)] {
+            conwaybets.block.timestamp = timestamp;
+            let bet = conwaybets.place_bet(market_id, owner(2), 0, Amount::from_tokens(tokens));
+            bet.now_or_never().unwrap().unwrap();
+        }
+        let listed = |since| -> Vec<(u64, Amount)> {
+            conwaybets.user_bets(owner(2), since).into_iter().map(|p| (p.placed_at, p.amount)).collect()
+        };
+
+        assert_eq!(listed(None), vec![
+            (300, Amount::from_tokens(3)),
+            (300, Amount::from_tokens(2)),
+            (200, Amount::from_tokens(4)),
+            (100, Amount::from_tokens(1)),
+        ]);
+        assert_eq!(listed(Some(250)), vec![(300, Amount::from_tokens(3)), (300, Amount::from_tokens(2))]);
+        assert!(listed(Some(301)).is_empty());
+        assert!(conwaybets.user_bets(owner(3), None).is_empty());
+    }
+
+    #[test]
+    fn test_lifecycle_events_are_logged_in_order_and_capped() {
+        let mut conwaybets = funded();
+        let won = create_test_market(&mut conwaybets, market_data());
+        let dropped = create_test_market(&mut conwaybets, market_data());
+        conwaybets.place_bet(won, owner(2), 0, Amount::from_tokens(3)).now_or_never().unwrap().unwrap();
+        conwaybets.resolve_market(owner(1), resolution(won, 0)).unwrap();
+        conwaybets.claim_winnings(owner(2), won).now_or_never().unwrap().unwrap();
+        conwaybets.cancel_market(owner(1), dropped).unwrap();
+
+        let events: Vec<ConwayBetsEvent> = conwaybets.events_since(0).into_iter().map(|e| e.event.clone()).collect();
+        assert_eq!(events, vec![
+            ConwayBetsEvent::MarketCreated { market_id: won, creator: owner(1) },
+            ConwayBetsEvent::MarketCreated { market_id: dropped, creator: owner(1) },
+            ConwayBetsEvent::BetPlaced {
+                market_id: won,
+                user: owner(2),
+                outcome_index: 0,
+                amount: Amount::from_tokens(3),
+            },
+            ConwayBetsEvent::MarketResolved { market_id: won, winning_outcomes: BTreeSet::from([0]) },
+            ConwayBetsEvent::WinningsClaimed { market_id: won, user: owner(2), amount: Amount::from_tokens(3) },
+            ConwayBetsEvent::MarketCancelled { market_id: dropped },
+        ]);
+        let tail: Vec<u64> = conwaybets.events_since(4).iter().map(|e| e.seq).collect();
+        assert_eq!(tail, vec![5, 6]);
+
+        let kinds: Vec<EventKind> = conwaybets.events_since(4).iter().map(|e| e.event.kind()).collect();
+        assert_eq!(kinds, vec![EventKind::WinningsClaimed, EventKind::MarketCancelled]);
+        assert_eq!(conwaybets.events[5].event.market_id(), dropped);
+
+        conwaybets.config.max_events = 2;
+        create_test_market(&mut conwaybets, market_data());
+        let kept: Vec<u64> = conwaybets.events_since(0).iter().map(|e| e.seq).collect();
+        assert_eq!(kept, vec![6, 7]);
+        assert_eq!(conwaybets.retained_event_seqs(), 6..=7);
+    }
+
+    #[test]
+    fn test_market_id_round_trips_through_its_string_form() {
+        let market_id = MarketId { chain_id: chain(7), id: 42 };
+        let rendered = market_id.to_string();
+        assert_eq!(rendered, format!("{}:42", chain(7)));
+        assert_eq!(rendered.parse::<MarketId>(), Ok(market_id));
+
+        let chain_id = chain(7).to_string();
+        let malformed = ["", "42", ":42", "nothex:42"]
+            .map(String::from)
+            .into_iter()
+            .chain(["", "-1", "4:2"].map(|id| format!("{chain_id}:{id}")));
+        for input in malformed {
+            assert_eq!(
+                input.parse::<MarketId>(),
+                Err(ConwayBetsError::InvalidMarketId(input.to_string())),
+                "{input:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_local_bet_sends_no_message() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        conwaybets.outbox.clear();
+        conwaybets.place_bet(market_id, owner(2), 0, Amount::from_tokens(1)).now_or_never().unwrap().unwrap();
+
+        assert_eq!(market_id.chain_id, conwaybets.block.chain_id);
+        assert!(conwaybets.outbox.is_empty());
+        assert_eq!(conwaybets.markets[&market_id].total_liquidity, Amount::from_tokens(1));
+    }
+
+    #[test]
+    fn test_received_bet_checks_min_bet_and_records_the_bettors_position() {
+        let mut conwaybets = funded();
+        let data = MarketCreationData { min_bet: Amount::from_tokens(2), ..market_data() };
+        let market_id = create_test_market(&mut conwaybets, data);
+        let bet = |nonce, amount| BetMessage {
+            correlation_id: [nonce as u8; 16],
+            market_id,
+            user: owner(7),
+            outcome_index: 1,
+            amount: Amount::from_tokens(amount),
+            nonce,
+            block_height: 1,
+        };
+
+        assert_eq!(conwaybets.receive_bet(chain(9), bet(1, 1)).unwrap_err(), ConwayBetsError::BetTooSmall);
+        assert!(!conwaybets.user_positions.contains_key(&owner(7)));
+
+        assert!(matches!(conwaybets.receive_bet(chain(9), bet(2, 3)), Ok(Status::Finalized)));
+        let positions = &conwaybets.user_positions[&owner(7)];
+        assert_eq!(positions.len(), 1);
+        assert_eq!((positions[0].market_id, positions[0].outcome_index), (market_id, 1));
+        assert_eq!(positions[0].amount, Amount::from_tokens(3));
+        assert_eq!(conwaybets.markets[&market_id].state_hash, conwaybets.market_state_hash(&market_id).unwrap());
+    }
+
+    #[test]
+    fn test_amm_winners_are_paid_by_shares_so_early_buyers_get_more_per_token() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            lmsr_b: Some(Amount::from_tokens(100)),
+            ..market_data()
+        });
+        for (user, outcome) in [(2, 0), (4, 0), (3, 1)] {
+            let bet = conwaybets.place_bet(market_id, owner(user), outcome, Amount::from_tokens(50));
+            bet.now_or_never().unwrap().unwrap();
+        }
+        let early = conwaybets.user_positions[&owner(2)][0].shares;
+        let late = conwaybets.user_positions[&owner(4)][0].shares;
+        assert!(early > late);
+
+        conwaybets.resolve_market(owner(1), resolution(market_id, 0)).unwrap();
+        let early_payout = conwaybets.claim_winnings(owner(2), market_id).now_or_never().unwrap().unwrap();
+        let late_payout = conwaybets.claim_winnings(owner(4), market_id).now_or_never().unwrap().unwrap();
+        // Same stake, so the cheaper shares pay more per token staked.
+        assert!(early_payout > late_payout);
+        let expected = mul_div(u128::from(early), 150 * 10u128.pow(18), u128::from(early) + u128::from(late));
+        assert_eq!(u128::from(early_payout), expected);
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_expired_pending_bet_is_refunded_on_its_origin_chain() {
+        let mut betting = ConwayBets::default();
+        let mut hosting = ConwayBets::default();
+        betting.block.chain_id = chain(1);
+        hosting.block.chain_id = chain(2);
+        hosting.config.pending_bet_ttl_secs = Some(600);
+        let data = MarketCreationData { min_confirmations: 3, ..market_data() };
+        let market_id = create_test_market(&mut hosting, data);
+        betting.record_remote_market(market_id, hosting.markets[&market_id].state_hash, 0);
+        betting.credit(owner(2), Amount::from_tokens(5));
+        betting.place_bet(market_id, owner(2), 0, Amount::from_tokens(4)).now_or_never().unwrap().unwrap();
+        betting.flush_bet_batches();
+        let (_, bet) = betting.outbox.pop().unwrap();
+        hosting.execute_message(chain(1), bet);
+        assert_eq!(hosting.pending_bets.len(), 1);
+
+        hosting.outbox.clear();
+        hosting.block.timestamp = 601;
+        assert_eq!(hosting.expire_pending_bets(), 1);
+        assert_eq!(hosting.outbox.len(), 1);
+        let (destination, reply) = hosting.outbox.pop().unwrap();
+        assert_eq!(destination, chain(1));
+        assert!(matches!(reply, ConwayBetsMessage::BetRejected { retriable: false, .. }));
+
+        betting.execute_message(chain(2), reply);
+        assert_eq!(betting.balances[&owner(2)], Amount::from_tokens(5));
+        assert!(betting.unacked_bets.is_empty());
+        assert!(betting.failed_bets.is_empty());
+        assert!(betting.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_state_hash_follows_funding_approval_suspension_and_claims() {
+        let mut conwaybets = funded();
+        conwaybets.config.moderators.insert(owner(9));
+        let changed = |conwaybets: &ConwayBets, market_id: MarketId, before: [u8; 32]| {
+            let hash = conwaybets.markets[&market_id].state_hash;
+            hash != before && hash == conwaybets.compute_state_hash(&market_id)
+        };
+
+        let funding = create_test_market(&mut conwaybets, MarketCreationData {
+            min_pool_to_open: Amount::from_tokens(10),
+            ..market_data()
+        });
+        let before = conwaybets.markets[&funding].state_hash;
+        conwaybets.fund_market(owner(1), funding, Amount::from_tokens(10)).now_or_never().unwrap().unwrap();
+        assert!(changed(&conwaybets, funding, before));
+
+        conwaybets.config.require_market_approval = true;
+        let approved = create_test_market(&mut conwaybets, market_data());
+        let rejected = create_test_market(&mut conwaybets, market_data());
+        conwaybets.config.require_market_approval = false;
+        let before = conwaybets.markets[&approved].state_hash;
+        conwaybets.approve_market(owner(9), approved).unwrap();
+        assert!(changed(&conwaybets, approved, before));
+        let before = conwaybets.markets[&rejected].state_hash;
+        conwaybets.reject_market(owner(9), rejected).unwrap();
+        assert!(changed(&conwaybets, rejected, before));
+
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            outcomes: vec!["A".to_string(), "B".to_string(), "C".to_string()],
+            creator_fee_bps: 100,
+            ..market_data()
+        });
+        let before = conwaybets.markets[&market_id].state_hash;
+        conwaybets.suspend_outcome(owner(1), market_id, 2).unwrap();
+        assert!(changed(&conwaybets, market_id, before));
+
+        for (user, outcome) in [(2, 0), (3, 1)] {
+            let bet = conwaybets.place_bet(market_id, owner(user), outcome, Amount::from_tokens(5));
+            bet.now_or_never().unwrap().unwrap();
+        }
+        conwaybets.resolve_market(owner(1), resolution(market_id, 0)).unwrap();
+        let before = conwaybets.markets[&market_id].state_hash;
+        conwaybets.claim_winnings(owner(2), market_id).now_or_never().unwrap().unwrap();
+        assert!(conwaybets.markets[&market_id].creator_fees > Amount::ZERO);
+        assert!(changed(&conwaybets, market_id, before));
+    }
+
+    #[test]
+    fn test_interim_payout_is_covered_when_an_overturn_cancels_the_market() {
+        let mut conwaybets = funded();
+        conwaybets.config.owner = Some(owner(9));
+        conwaybets.config.interim_payout_bps = 8_000;
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            dispute_window_secs: 100,
+            ..market_data()
+        });
+        for (user, outcome, tokens) in [(2, 0, 5), (3, 1, 15)] {
+            let bet = conwaybets.place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens));
+            bet.now_or_never().unwrap().unwrap();
+        }
+        conwaybets.resolve_market(owner(1), resolution(market_id, 0)).unwrap();
+        let claim = |conwaybets: &mut ConwayBets, user| {
+            conwaybets.claim_winnings(owner(user), market_id).now_or_never().unwrap()
+        };
+
+        // Reopening could hand the win to the other side, so under the default
+        // policy nothing is released and none is advertised.
+        assert_eq!(claim(&mut conwaybets, 2), Err(ConwayBetsError::ClaimsNotOpen));
+        assert_eq!(conwaybets.market_config(&market_id).unwrap().interim_payout_bps, 0);
+
+        // 80% of a 20-token payout is 16, capped at the 5 staked.
+        conwaybets.config.post_dispute = PostDisputeAction::Cancel;
+        assert_eq!(conwaybets.market_config(&market_id).unwrap().interim_payout_bps, 8_000);
+        assert_eq!(claim(&mut conwaybets, 2), Ok(Amount::from_tokens(5)));
+        conwaybets.challenge_resolution(owner(3), market_id).unwrap();
+        conwaybets.rule_on_dispute(owner(9), market_id, true).unwrap();
+        assert!(conwaybets.markets[&market_id].is_cancelled);
+
+        assert_eq!(claim(&mut conwaybets, 2), Ok(Amount::ZERO));
+        assert_eq!(claim(&mut conwaybets, 3), Ok(Amount::from_tokens(15)));
+        assert_eq!(conwaybets.escrow_balance, Amount::ZERO);
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_operations_only_act_for_their_signer() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        let resolve = Operation::ResolveMarket { resolver: owner(1), resolution: resolution(market_id, 0) };
+        assert_eq!(resolve.acting_owner(), Some(owner(1)));
+        assert_eq!(resolve.check_signer(None), Err(ConwayBetsError::NotAuthorized));
+        assert_eq!(resolve.check_signer(Some(owner(2))), Err(ConwayBetsError::NotAuthorized));
+        assert_eq!(resolve.check_signer(Some(owner(1))), Ok(()));
+        assert_eq!(Operation::TickPending.check_signer(None), Ok(()));
+
+        // Naming the creator doesn't let someone else resolve their market.
+        assert_eq!(
+            conwaybets.execute(Some(owner(2)), resolve.clone()).now_or_never().unwrap(),
+            Err(ConwayBetsError::NotAuthorized)
+        );
+        assert!(!conwaybets.markets[&market_id].is_resolved);
+        conwaybets.execute(Some(owner(1)), resolve).now_or_never().unwrap().unwrap();
+        assert!(conwaybets.markets[&market_id].is_resolved);
+    }
+
+    #[test]
+    fn test_credit_is_refused_unless_signed_by_the_owner_in_a_faucet_build() {
+        let mut conwaybets = ConwayBets::default();
+        conwaybets.config.owner = Some(owner(9));
+        let credit = Operation::Credit { user: owner(2), amount: Amount::from_tokens(3) };
+        for signer in [Some(owner(2)), None] {
+            assert_eq!(
+                conwaybets.execute(signer, credit.clone()).now_or_never().unwrap(),
+                Err(ConwayBetsError::NotAuthorized)
+            );
+        }
+        assert_eq!(conwaybets.balances.get(&owner(2)).copied().unwrap_or_default(), Amount::ZERO);
+
+        let _ = conwaybets.execute(Some(owner(9)), credit).now_or_never().unwrap();
+        let expected = if cfg!(feature = "faucet") { Amount::from_tokens(3) } else { Amount::ZERO };
+        assert_eq!(conwaybets.balances.get(&owner(2)).copied().unwrap_or_default(), expected);
+    }
+
+    #[test]
+    fn test_failed_operation_is_reported_to_the_caller() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        let amount = Amount::from_tokens(5);
+        let broke = Operation::PlaceBet { market_id, user: owner(10), outcome_index: 0, amount };
+        assert_eq!(
+            conwaybets.execute(Some(owner(10)), broke).now_or_never().unwrap(),
+            Err(ConwayBetsError::InsufficientFunds)
+        );
+    }
+
+    #[test]
+    fn test_losing_early_stake_earns_no_bonus() {
+        let mut conwaybets = funded();
+        conwaybets.config.early_liquidity_cap = Amount::from_tokens(10);
+        conwaybets.config.early_bonus_bps = 5_000;
+        conwaybets.fund_rewards(owner(9), Amount::from_tokens(10)).now_or_never().unwrap().unwrap();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        for (user, outcome_index) in [(2, 1), (3, 0)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome_index, Amount::from_tokens(4))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        conwaybets.resolve_market(owner(1), resolution(market_id, 0)).unwrap();
+
+        assert_eq!(conwaybets.claim_winnings(owner(2), market_id).now_or_never().unwrap(), Ok(Amount::ZERO));
+        assert_eq!(conwaybets.balances[&owner(2)], Amount::from_tokens(999_996));
+        assert_eq!(conwaybets.reward_pool, Amount::from_tokens(10));
+        // The winner's 4 early tokens earn 2 on top of the 8-token pot.
+        assert_eq!(
+            conwaybets.claim_winnings(owner(3), market_id).now_or_never().unwrap(),
+            Ok(Amount::from_tokens(10))
+        );
+        assert_eq!(conwaybets.balances[&owner(3)], Amount::from_tokens(1_000_006));
+        assert_eq!(conwaybets.reward_pool, Amount::from_tokens(8));
+    }
+
+    #[cfg(feature = "debug-log")]
+    #[test]
+    fn test_operation_log_keeps_only_the_latest_operations() {
+        let mut conwaybets = ConwayBets::default();
+        for _ in 0..OPERATION_LOG_LIMIT + 5 {
+            conwaybets.execute(None, Operation::TickPending).now_or_never().unwrap().unwrap();
+        }
+        assert_eq!(conwaybets.operation_log.len(), OPERATION_LOG_LIMIT);
+        assert_eq!(conwaybets.operation_log[0].0, 6);
+        // The log isn't part of the stored state.
+        let decoded: ConwayBets = bcs::from_bytes(&bcs::to_bytes(&conwaybets).unwrap()).unwrap();
+        assert!(decoded.operation_log.is_empty());
+    }
+
+    #[test]
+    fn test_every_escrowed_token_is_claimable_after_a_split() {
+        let mut conwaybets = funded();
+        let parent = create_test_market(&mut conwaybets, MarketCreationData {
+            outcomes: ["Red", "Green", "Blue"].map(String::from).to_vec(),
+            ..market_data()
+        });
+        for (user, outcome, tokens) in [(2, 0, 10), (3, 1, 20), (4, 2, 30)] {
+            conwaybets
+                .place_bet(parent, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        let secret = [7; 32];
+        conwaybets
+            .place_committed_bet(owner(5), parent, 1, Amount::from_tokens(10), privacy::commitment(&secret))
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        let binaries = conwaybets.split_market(owner(1), parent).now_or_never().unwrap().unwrap();
+        assert_eq!(conwaybets.markets[&binaries[1]].total_liquidity, Amount::from_tokens(30));
+        assert!(conwaybets.solvency_report().is_balanced());
+
+        // Green wins: Red and Blue resolve to "Not X".
+        for (binary, outcome) in binaries.iter().zip([1, 0, 1]) {
+            conwaybets.resolve_market(owner(1), resolution(*binary, outcome)).unwrap();
+        }
+        let mut claimed = BTreeMap::new();
+        for user in [2, 3, 4] {
+            let batch = conwaybets.claim_all(owner(user), binaries.clone()).now_or_never().unwrap();
+            claimed.insert(user, batch.total);
+        }
+        let committed = conwaybets.claim_committed(owner(6), binaries[1], secret).now_or_never().unwrap();
+
+        // Red's 10 goes 4/6 to Green and Blue backers, Blue's 30 goes 10/20 to
+        // Red and Green backers, and Green's 30 goes back to its backers.
+        assert_eq!(claimed[&2], Amount::from_tokens(10));
+        assert_eq!(claimed[&3], Amount::from_tokens(44));
+        assert_eq!(claimed[&4], Amount::from_tokens(6));
+        assert_eq!(committed, Ok(Amount::from_tokens(10)));
+        assert_eq!(conwaybets.escrow_balance, Amount::ZERO);
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_unbacked_winner_refunds_every_stake_at_par() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, MarketCreationData {
+            outcomes: ["Red", "Green", "Blue"].map(String::from).to_vec(),
+            creator_fee_bps: 500,
+            ..market_data()
+        });
+        for (user, outcome, tokens) in [(2, 0, 4), (3, 1, 6)] {
+            conwaybets
+                .place_bet(market_id, owner(user), outcome, Amount::from_tokens(tokens))
+                .now_or_never()
+                .unwrap()
+                .unwrap();
+        }
+        conwaybets.resolve_market(owner(1), resolution(market_id, 2)).unwrap();
+
+        assert_eq!(conwaybets.payout(owner(2), &market_id), Amount::from_tokens(4));
+        for (user, tokens) in [(2, 4), (3, 6)] {
+            assert_eq!(
+                conwaybets.claim_winnings(owner(user), market_id).now_or_never().unwrap(),
+                Ok(Amount::from_tokens(tokens))
+            );
+        }
+        assert_eq!(conwaybets.balances.get(&owner(1)), Some(&Amount::from_tokens(1_000_000)));
+        assert_eq!(conwaybets.escrow_balance, Amount::ZERO);
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_creator_gets_deposit_and_subsidy_back_on_resolution_or_cancellation() {
+        let mut conwaybets = funded();
+        conwaybets.config.require_market_approval = true;
+        conwaybets.config.moderators.insert(owner(9));
+        conwaybets.config.creation_deposit = Amount::from_tokens(10);
+        let data = MarketCreationData { min_pool_to_open: Amount::from_tokens(15), ..market_data() };
+        let resolved = create_test_market(&mut conwaybets, data.clone());
+        let cancelled = create_test_market(&mut conwaybets, data);
+        for market_id in [resolved, cancelled] {
+            assert_eq!(conwaybets.approve_market(owner(9), market_id), Ok(MarketStatus::Funding));
+            conwaybets.fund_market(owner(1), market_id, Amount::from_tokens(5)).now_or_never().unwrap().unwrap();
+        }
+        assert_eq!(conwaybets.balances[&owner(1)], Amount::from_tokens(999_970));
+        for (user, outcome) in [(2, 0), (3, 1)] {
+            let bet = conwaybets.place_bet(resolved, owner(user), outcome, Amount::from_tokens(4));
+            bet.now_or_never().unwrap().unwrap();
+        }
+
+        conwaybets.resolve_market(owner(1), resolution(resolved, 0)).unwrap();
+        assert_eq!(conwaybets.balances[&owner(1)], Amount::from_tokens(999_985));
+        assert_eq!(conwaybets.markets[&resolved].subsidy, Amount::ZERO);
+        conwaybets.cancel_market(owner(1), cancelled).unwrap();
+        assert_eq!(conwaybets.balances[&owner(1)], Amount::from_tokens(1_000_000));
+
+        // The pot is still the bettors' alone.
+        let claim = conwaybets.claim_winnings(owner(2), resolved).now_or_never().unwrap();
+        assert_eq!(claim, Ok(Amount::from_tokens(8)));
+        assert_eq!(conwaybets.escrow_balance, Amount::ZERO);
+        assert!(conwaybets.solvency_report().is_balanced());
+    }
+
+    #[test]
+    fn test_withdrawal_must_leave_committed_stakes_covered() {
+        let mut conwaybets = funded();
+        let market_id = create_test_market(&mut conwaybets, market_data());
+        conwaybets.place_bet(market_id, owner(2), 0, Amount::from_tokens(4)).now_or_never().unwrap().unwrap();
+        conwaybets
+            .place_committed_bet(owner(3), market_id, 1, Amount::from_tokens(6), [5; 32])
+            .now_or_never()
+            .unwrap()
+            .unwrap();
+        conwaybets.markets.get_mut(&market_id).unwrap().paid_out = Amount::from_tokens(3);
+        let withdraw = |conwaybets: &mut ConwayBets| {
+            conwaybets.withdraw_bet(owner(2), market_id, 0).now_or_never().unwrap()
+        };
+
+        // Only the committed stake is left behind, and 3 of its 6 tokens are gone.
+        assert_eq!(withdraw(&mut conwaybets), Err(ConwayBetsError::WouldUnderflowPool));
+        conwaybets.markets.get_mut(&market_id).unwrap().paid_out = Amount::ZERO;
+        assert_eq!(withdraw(&mut conwaybets), Ok(Amount::from_tokens(4)));
+    }
+}